@@ -0,0 +1,517 @@
+use disasm::Mode::*;
+use disasm::{Mode, OPCODES};
+use mapper::Mapper;
+use std::collections::HashMap;
+
+const BRANCHES: &[&str] = &["bpl", "bmi", "bvc", "bvs", "bcc", "bcs", "bne", "beq", "bra"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Number(u32, usize), // value and the width it was written with
+    Label(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Expr {
+    terms: Vec<(bool, Term)>, // (negated, term)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Syntax {
+    None,
+    Accumulator,
+    Immediate,
+    Plain,
+    PlainX,
+    PlainY,
+    PlainS,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    StackIndirectY,
+    LongIndirect,
+    LongIndirectY,
+    Move,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+    Org(Expr),
+    Label(String),
+    Data(usize, Vec<Expr>),
+    Instruction {
+        mnemonic: String,
+        hint: Option<usize>,
+        syntax: Syntax,
+        operands: Vec<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub address: u32,
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembly {
+    pub chunks: Vec<Chunk>,
+    pub labels: HashMap<String, u32>,
+}
+
+impl Assembly {
+    pub fn write(&self, data: &mut [u8]) -> Result<(), String> {
+        for chunk in &self.chunks {
+            let end = chunk.offset + chunk.bytes.len();
+            if end > data.len() {
+                return Err(format!("code at ${:06x} runs past the end of the rom", chunk.address));
+            }
+            data[chunk.offset..end].copy_from_slice(&chunk.bytes);
+        }
+        Ok(())
+    }
+}
+
+// statements are separated by newlines or ':', `name:` defines a label, `;` starts a comment
+pub fn assemble(source: &str, mapper: &Mapper) -> Result<Assembly, String> {
+    let mut statements = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        for part in split_statements(strip_comment(line)) {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            let statement = parse_statement(part).map_err(|e| format!("line {}: {}: {}", number + 1, part, e))?;
+            statements.push((number + 1, statement));
+        }
+    }
+
+    // first pass fixes every instruction's encoding, so label values can't change sizes afterwards
+    let mut labels = HashMap::new();
+    let mut encodings = Vec::new();
+    let mut pc: Option<u32> = None;
+    for &(number, ref statement) in &statements {
+        let error = |e: String| format!("line {}: {}", number, e);
+        match *statement {
+            Statement::Org(ref expr) => pc = Some(evaluate(expr, &labels).map_err(error)?), // labels defined above it
+            Statement::Label(ref name) => {
+                let address = pc.ok_or_else(|| error("label before org".to_string()))?;
+                if labels.insert(name.clone(), address).is_some() {
+                    return Err(error(format!("label {} defined twice", name)));
+                }
+            }
+            _ => {
+                let address = pc.ok_or_else(|| error("code before org".to_string()))?;
+                let size = match *statement {
+                    Statement::Data(width, ref values) => width * values.len(),
+                    Statement::Instruction { ref mnemonic, hint, syntax, ref operands } => {
+                        let (opcode, size) = select_opcode(mnemonic, hint, syntax, operands).map_err(error)?;
+                        encodings.push((opcode, size));
+                        size + 1
+                    }
+                    _ => 0,
+                };
+                pc = Some((address & 0xff0000) | ((address + size as u32) & 0xffff));
+            }
+        }
+    }
+
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut encodings = encodings.into_iter();
+    let mut pc = 0;
+    for &(number, ref statement) in &statements {
+        let error = |e: String| format!("line {}: {}", number, e);
+        let bytes = match *statement {
+            Statement::Org(ref expr) => {
+                pc = evaluate(expr, &labels).map_err(error)?;
+                continue;
+            }
+            Statement::Label(_) => continue,
+            Statement::Data(width, ref values) => {
+                let mut bytes = Vec::new();
+                for value in values {
+                    let value = evaluate(value, &labels).map_err(error)?;
+                    bytes.extend((0..width).map(|byte| (value >> (byte * 8)) as u8));
+                }
+                bytes
+            }
+            Statement::Instruction { hint, ref operands, .. } => {
+                let (opcode, size) = encodings.next().unwrap();
+                encode(opcode, size, hint, operands, pc, &labels).map_err(error)?
+            }
+        };
+        let offset = mapper.to_offset(pc).ok_or_else(|| error(format!("${:06x} is not mapped to rom", pc)))?;
+        match chunks.last_mut() {
+            Some(ref mut last) if last.offset + last.bytes.len() == offset => last.bytes.extend_from_slice(&bytes),
+            _ => chunks.push(Chunk {
+                address: pc,
+                offset,
+                bytes: bytes.clone(),
+            }),
+        }
+        pc = (pc & 0xff0000) | ((pc + bytes.len() as u32) & 0xffff);
+    }
+    Ok(Assembly { chunks, labels })
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn split_statements(line: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (index, character) in line.char_indices() {
+        match character {
+            '"' => quoted = !quoted,
+            ':' if !quoted => {
+                parts.push(&line[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&line[start..]);
+    parts
+}
+
+fn is_identifier(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') &&
+    !text.starts_with(|c: char| c.is_ascii_digit())
+}
+
+// labels are case insensitive like the mnemonics, definitions and uses go through here
+fn label_name(text: &str) -> String {
+    text.to_ascii_lowercase()
+}
+
+fn is_mnemonic(name: &str) -> bool {
+    OPCODES.iter().any(|&(mnemonic, _)| mnemonic == name)
+}
+
+fn parse_statement(text: &str) -> Result<Statement, String> {
+    let (word, rest) = match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, ""),
+    };
+    let word = word.to_ascii_lowercase();
+    let (name, hint) = match word.rfind('.') {
+        Some(index) if index > 0 => {
+            let hint = match &word[index + 1..] {
+                "b" => Some(1),
+                "w" => Some(2),
+                "l" => Some(3),
+                _ => None,
+            };
+            if hint.is_some() { (word[..index].to_string(), hint) } else { (word.clone(), None) }
+        }
+        _ => (word.clone(), None),
+    };
+    match name.trim_start_matches('.') {
+        "org" => return Ok(Statement::Org(parse_expr(rest)?)),
+        "db" | "byte" => return parse_data(1, rest),
+        "dw" | "word" => return parse_data(2, rest),
+        "dl" | "long" => return parse_data(3, rest),
+        _ => {}
+    }
+    if !is_mnemonic(&name) {
+        if rest.is_empty() && is_identifier(text) {
+            return Ok(Statement::Label(label_name(text)));
+        }
+        return Err(format!("unknown instruction {}", name));
+    }
+    let (syntax, operands) = parse_operand(rest)?;
+    Ok(Statement::Instruction {
+        mnemonic: name,
+        hint,
+        syntax,
+        operands,
+    })
+}
+
+// values separated by commas, strings give one value per character
+fn parse_data(width: usize, text: &str) -> Result<Statement, String> {
+    let mut values = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (index, character) in text.char_indices().chain(Some((text.len(), ','))) {
+        match character {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                let value = text[start..index].trim();
+                if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                    values.extend(value[1..value.len() - 1].bytes().map(|byte| Expr {
+                        terms: vec![(false, Term::Number(byte as u32, 1))],
+                    }));
+                } else {
+                    values.push(parse_expr(value)?);
+                }
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    Ok(Statement::Data(width, values))
+}
+
+fn parse_operand(text: &str) -> Result<(Syntax, Vec<Expr>), String> {
+    let lower = text.to_ascii_lowercase().replace(' ', "");
+    let lower = lower.as_str();
+    if lower.is_empty() {
+        return Ok((Syntax::None, vec![]));
+    }
+    if lower == "a" {
+        return Ok((Syntax::Accumulator, vec![]));
+    }
+    if let Some(rest) = lower.strip_prefix('#') {
+        return Ok((Syntax::Immediate, vec![parse_expr(rest)?]));
+    }
+    let patterns: &[(&str, &str, Syntax)] = &[
+        ("(", ",s),y", Syntax::StackIndirectY),
+        ("(", ",x)", Syntax::IndirectX),
+        ("(", "),y", Syntax::IndirectY),
+        ("(", ")", Syntax::Indirect),
+        ("[", "],y", Syntax::LongIndirectY),
+        ("[", "]", Syntax::LongIndirect),
+        ("", ",x", Syntax::PlainX),
+        ("", ",y", Syntax::PlainY),
+        ("", ",s", Syntax::PlainS),
+    ];
+    for &(prefix, suffix, syntax) in patterns {
+        if lower.starts_with(prefix) && lower.ends_with(suffix) && lower.len() > prefix.len() + suffix.len() {
+            return Ok((syntax, vec![parse_expr(&lower[prefix.len()..lower.len() - suffix.len()])?]));
+        }
+    }
+    if let Some(index) = lower.find(',') {
+        return Ok((Syntax::Move, vec![parse_expr(&lower[..index])?, parse_expr(&lower[index + 1..])?]));
+    }
+    Ok((Syntax::Plain, vec![parse_expr(lower)?]))
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let text = text.replace(' ', "");
+    if text.is_empty() {
+        return Err("missing value".to_string());
+    }
+    let mut terms = Vec::new();
+    let mut negated = false;
+    let mut start = 0;
+    let bytes = text.as_bytes();
+    for index in 0..=bytes.len() {
+        if index == bytes.len() || (index > start && (bytes[index] == b'+' || bytes[index] == b'-')) {
+            terms.push((negated, parse_term(&text[start..index])?));
+            if index < bytes.len() {
+                negated = bytes[index] == b'-';
+                start = index + 1;
+            }
+        } else if index == start && bytes[index] == b'-' {
+            negated = !negated;
+            start = index + 1;
+        }
+    }
+    Ok(Expr { terms })
+}
+
+fn parse_term(text: &str) -> Result<Term, String> {
+    // decimal numbers don't say how wide they are meant to be
+    let (digits, radix, digits_per_byte) = if let Some(hex) = text.strip_prefix('$') {
+        (hex, 16, Some(2))
+    } else if let Some(hex) = text.strip_prefix("0x") {
+        (hex, 16, Some(2))
+    } else if let Some(binary) = text.strip_prefix('%') {
+        (binary, 2, Some(8))
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        (text, 10, None)
+    } else if is_identifier(text) {
+        return Ok(Term::Label(label_name(text)));
+    } else {
+        return Err(format!("can't read {}", text));
+    };
+    let value = u32::from_str_radix(digits, radix).map_err(|_| format!("can't read {}", text))?;
+    let width = match digits_per_byte {
+        Some(digits_per_byte) => digits.len().div_ceil(digits_per_byte).clamp(1, 3),
+        None => value_width(value),
+    };
+    Ok(Term::Number(value, width))
+}
+
+fn value_width(value: u32) -> usize {
+    if value > 0xffff {
+        3
+    } else if value > 0xff {
+        2
+    } else {
+        1
+    }
+}
+
+fn evaluate(expr: &Expr, labels: &HashMap<String, u32>) -> Result<u32, String> {
+    let mut value = 0u32;
+    for &(negated, ref term) in &expr.terms {
+        let term = match *term {
+            Term::Number(number, _) => number,
+            Term::Label(ref name) => *labels.get(name).ok_or(format!("unknown label {}", name))?,
+        };
+        value = if negated { value.wrapping_sub(term) } else { value.wrapping_add(term) };
+    }
+    Ok(value & 0xffffff)
+}
+
+// the width an operand asks for: a size hint, the way a number was written, or absolute for labels
+fn operand_width(hint: Option<usize>, expr: &Expr) -> usize {
+    if let Some(hint) = hint {
+        return hint;
+    }
+    let mut width = 0;
+    for (_, term) in &expr.terms {
+        width = width.max(match term {
+            Term::Number(_, width) => *width,
+            Term::Label(_) => 2,
+        });
+    }
+    width
+}
+
+fn has_label(expr: &Expr) -> bool {
+    expr.terms.iter().any(|(_, term)| matches!(term, Term::Label(_)))
+}
+
+fn find_opcode(mnemonic: &str, modes: &[Mode]) -> Option<(u8, Mode)> {
+    for mode in modes {
+        if let Some(opcode) = OPCODES.iter().position(|&(name, candidate)| name == mnemonic && candidate == *mode) {
+            return Some((opcode as u8, *mode));
+        }
+    }
+    None
+}
+
+// picks the opcode and operand size, wider modes are used when the narrow one doesn't exist
+fn select_opcode(mnemonic: &str, hint: Option<usize>, syntax: Syntax, operands: &[Expr]) -> Result<(u8, usize), String> {
+    let width = operands.first().map_or(0, |expr| operand_width(hint, expr));
+    let long_jump = match mnemonic {
+        "jsl" | "jml" => true,
+        "jmp" | "jsr" => width == 3,
+        _ => false,
+    };
+    let mnemonic = match mnemonic {
+        "jmp" if long_jump || syntax == Syntax::LongIndirect => "jml",
+        "jsr" if long_jump => "jsl",
+        other => other,
+    };
+    let modes: Vec<Mode> = match syntax {
+        Syntax::None => vec![Implied, Accumulator],
+        Syntax::Accumulator => vec![Accumulator],
+        Syntax::Immediate => vec![ImmediateM, ImmediateX, Immediate8],
+        Syntax::Plain if BRANCHES.contains(&mnemonic) => vec![Relative],
+        Syntax::Plain if mnemonic == "brl" || mnemonic == "per" => vec![RelativeLong],
+        Syntax::Plain if long_jump => vec![Long],
+        Syntax::Plain if mnemonic == "pea" || mnemonic == "jmp" || mnemonic == "jsr" => vec![Absolute],
+        Syntax::Plain => vec![Direct, Absolute, Long].split_off(width.clamp(1, 3) - 1),
+        Syntax::PlainX => vec![DirectX, AbsoluteX, LongX].split_off(width.clamp(1, 3) - 1),
+        Syntax::PlainY => vec![DirectY, AbsoluteY].split_off(width.clamp(1, 2) - 1),
+        Syntax::PlainS => vec![Stack],
+        Syntax::Indirect if mnemonic == "jmp" => vec![AbsoluteIndirect],
+        Syntax::Indirect => vec![DirectIndirect],
+        Syntax::IndirectX if mnemonic == "jmp" || mnemonic == "jsr" => vec![AbsoluteIndirectX],
+        Syntax::IndirectX => vec![DirectIndirectX],
+        Syntax::IndirectY => vec![DirectIndirectY],
+        Syntax::StackIndirectY => vec![StackIndirectY],
+        Syntax::LongIndirect if mnemonic == "jml" => vec![AbsoluteIndirectLong],
+        Syntax::LongIndirect => vec![DirectIndirectLong],
+        Syntax::LongIndirectY => vec![DirectIndirectLongY],
+        Syntax::Move => vec![BlockMove],
+    };
+    let (opcode, mode) = find_opcode(mnemonic, &modes).ok_or(format!("{} has no such addressing mode", mnemonic))?;
+    let size = match mode {
+        Implied | Accumulator => 0,
+        ImmediateM | ImmediateX => width.clamp(1, 2),
+        Immediate8 | Direct | DirectX | DirectY | DirectIndirect | DirectIndirectX | DirectIndirectY |
+        DirectIndirectLong | DirectIndirectLongY | Stack | StackIndirectY | Relative => 1,
+        Absolute | AbsoluteX | AbsoluteY | AbsoluteIndirect | AbsoluteIndirectX | AbsoluteIndirectLong |
+        RelativeLong | BlockMove => 2,
+        Long | LongX => 3,
+    };
+    Ok((opcode, size))
+}
+
+// labels get 16 bit operands unless hinted, one in another bank would lose its bank there
+fn encode(opcode: u8, size: usize, hint: Option<usize>, operands: &[Expr], pc: u32, labels: &HashMap<String, u32>) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![opcode];
+    let (_, mode) = OPCODES[opcode as usize];
+    let value = match operands.first() {
+        Some(expr) => evaluate(expr, labels)?,
+        None => return Ok(bytes),
+    };
+    let value = match mode {
+        Relative | RelativeLong => {
+            let next = (pc & 0xffff) as i32 + 1 + size as i32;
+            if value & 0xff0000 != pc & 0xff0000 {
+                return Err(format!("${:06x} is in another bank", value));
+            }
+            let distance = (value & 0xffff) as i32 - next;
+            if mode == Relative && !(-128..=127).contains(&distance) {
+                return Err(format!("branch to ${:06x} is out of range", value));
+            }
+            distance as u32
+        }
+        // source bank first in the source, destination bank first in the encoding
+        BlockMove => (evaluate(&operands[1], labels)? & 0xff) | (value & 0xff) << 8,
+        Absolute | AbsoluteX | AbsoluteY | AbsoluteIndirectX if hint.is_none() && has_label(&operands[0]) && value & 0xff0000 != pc & 0xff0000 => {
+            return Err(format!("${:06x} is outside bank ${:02x}, use a .l or .w hint", value, pc >> 16));
+        }
+        _ => value,
+    };
+    bytes.extend((0..size).map(|byte| (value >> (byte * 8)) as u8));
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snesutilities::{RomMakupType, RomType};
+
+    fn lorom() -> Mapper {
+        Mapper { makeup: RomMakupType::LoROM, rom_type: RomType::ROM, size: 0x8000 }
+    }
+
+    #[test]
+    fn mixed_case_labels() {
+        let assembly = assemble("org $008000\nLoop: dex : bne Loop\nJMP LOOP\ndw loop, End\nend:", &lorom()).unwrap();
+        assert_eq!(assembly.labels.get("loop"), Some(&0x8000));
+        assert_eq!(assembly.chunks[0].bytes, vec![0xca, 0xd0, 0xfd, 0x4c, 0x00, 0x80, 0x00, 0x80, 0x0a, 0x80]);
+    }
+
+    #[test]
+    fn org_from_label() {
+        let assembly = assemble("org $008000\nStart: nop\norg START+$10\nlda #$12", &lorom()).unwrap();
+        assert_eq!(assembly.chunks[1].offset, 0x10);
+        assert_eq!(assembly.chunks[1].bytes, vec![0xa9, 0x12]);
+    }
+
+    #[test]
+    fn label_defined_twice() {
+        assert!(assemble("org $008000\nloop: nop\nLOOP: nop", &lorom()).is_err());
+    }
+
+    #[test]
+    fn labels_in_another_bank() {
+        let mapper = Mapper { makeup: RomMakupType::LoROM, rom_type: RomType::ROM, size: 0x20000 };
+        let source = |code: &str| format!("org $008000\n{}\norg $038000\nfar: rts\nfar_data: db 1", code);
+        for code in &["jsr far", "jmp far", "lda far_data", "lda far_data,x"] {
+            assert!(assemble(&source(code), &mapper).is_err(), "{}", code);
+        }
+        let long = assemble(&source("jsr.l far : lda.l far_data : lda.w far_data"), &mapper).unwrap();
+        assert_eq!(long.chunks[0].bytes, vec![0x22, 0x00, 0x80, 0x03, 0xaf, 0x01, 0x80, 0x03, 0xad, 0x01, 0x80]);
+        assert!(assemble("org $038000\nnear: lda near : jsr near", &mapper).is_ok());
+    }
+}
@@ -0,0 +1,233 @@
+// bit rate reduction, the sample format of the s-dsp: 9 byte blocks of a header
+// (range << 4 | filter << 2 | loop << 1 | end) and 16 signed nibbles
+
+pub const BLOCK_SIZE: usize = 9;
+pub const SAMPLES_PER_BLOCK: usize = 16;
+const MIN_SCAN_BLOCKS: usize = 16;
+const MAX_SCAN_BLOCKS: usize = 0x10000 / BLOCK_SIZE;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub offset: usize,
+    pub pcm: Vec<i16>,
+    pub loop_start: Option<usize>, // in samples, only set when the end block has the loop flag
+    pub size: usize,               // brr bytes the sample occupies
+}
+
+// one sample the way the dsp computes it, p1/p2 are the last two outputs
+fn decode_nibble(nibble: i32, range: u8, filter: u8, p1: i32, p2: i32) -> i16 {
+    let mut s = (nibble << range) >> 1;
+    if range >= 13 {
+        s = (s >> 25) << 11; // invalid ranges give 0 or -2048
+    }
+    let p2 = p2 >> 1;
+    match filter {
+        1 => {
+            s += p1 >> 1;
+            s += (-p1) >> 5;
+        }
+        2 => {
+            s += p1 - p2;
+            s += p2 >> 4;
+            s += (p1 * -3) >> 6;
+        }
+        3 => {
+            s += p1 - p2;
+            s += (p1 * -13) >> 7;
+            s += (p2 * 3) >> 4;
+        }
+        _ => {}
+    }
+    (s.clamp(-0x8000, 0x7fff) * 2) as i16
+}
+
+fn block_nibbles(block: &[u8]) -> Vec<i32> {
+    block[1..BLOCK_SIZE]
+        .iter()
+        .flat_map(|byte| vec![(*byte as i8 >> 4) as i32, ((*byte << 4) as i8 >> 4) as i32])
+        .collect()
+}
+
+fn decode_block(block: &[u8], history: &mut (i32, i32), pcm: &mut Vec<i16>) {
+    let range = block[0] >> 4;
+    let filter = (block[0] >> 2) & 3;
+    for nibble in block_nibbles(block) {
+        let sample = decode_nibble(nibble, range, filter, history.0, history.1);
+        *history = (sample as i32, history.0);
+        pcm.push(sample);
+    }
+}
+
+// decodes from offset up to the block with the end flag, loop_offset is relative to offset in bytes
+pub fn decode(data: &[u8], offset: usize, loop_offset: usize) -> Result<Sample, String> {
+    let mut pcm = Vec::new();
+    let mut history = (0, 0);
+    let mut position = offset;
+    loop {
+        let block = data
+            .get(position..position + BLOCK_SIZE)
+            .ok_or(format!("sample at {:#x} runs past the end without an end block", offset))?;
+        decode_block(block, &mut history, &mut pcm);
+        position += BLOCK_SIZE;
+        if block[0] & 1 != 0 {
+            let looped = block[0] & 2 != 0;
+            return Ok(Sample {
+                offset,
+                loop_start: if looped { Some(loop_offset / BLOCK_SIZE * SAMPLES_PER_BLOCK) } else { None },
+                pcm,
+                size: position - offset,
+            });
+        }
+    }
+}
+
+// looks for block runs that end properly, only use valid ranges and actually make noise
+pub fn scan(data: &[u8]) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    let mut offset = 0;
+    while offset + BLOCK_SIZE <= data.len() {
+        match plausible_length(data, offset) {
+            Some(blocks) => {
+                samples.push(decode(data, offset, 0).unwrap());
+                offset += blocks * BLOCK_SIZE;
+            }
+            None => offset += 1,
+        }
+    }
+    samples
+}
+
+fn plausible_length(data: &[u8], offset: usize) -> Option<usize> {
+    let mut blocks = 0;
+    let mut silent = true;
+    let mut position = offset;
+    loop {
+        let block = data.get(position..position + BLOCK_SIZE)?;
+        if block[0] >> 4 > 12 || blocks == MAX_SCAN_BLOCKS {
+            return None;
+        }
+        silent &= block[1..].iter().all(|byte| *byte == 0);
+        blocks += 1;
+        position += BLOCK_SIZE;
+        if block[0] & 1 != 0 {
+            return if blocks >= MIN_SCAN_BLOCKS && !silent { Some(blocks) } else { None };
+        }
+    }
+}
+
+// brute forces range and filter for every block against the exact decoder, the loop block is kept
+// at filter 0 so it doesn't depend on what was played before it
+pub fn encode(pcm: &[i16], loop_start: Option<usize>) -> Vec<u8> {
+    let mut samples = pcm.to_vec();
+    let padded = samples.len().div_ceil(SAMPLES_PER_BLOCK).max(1) * SAMPLES_PER_BLOCK;
+    samples.resize(padded, 0);
+    let loop_block = loop_start.map(|start| start / SAMPLES_PER_BLOCK);
+    let blocks = samples.len() / SAMPLES_PER_BLOCK;
+
+    let mut brr = Vec::with_capacity(blocks * BLOCK_SIZE);
+    let mut history = (0, 0);
+    for (index, chunk) in samples.chunks(SAMPLES_PER_BLOCK).enumerate() {
+        let filters = if index == 0 || Some(index) == loop_block { 0..1 } else { 0..4 };
+        let mut best: Option<(i64, Vec<u8>, (i32, i32))> = None;
+        for filter in filters {
+            for range in 0..13 {
+                let (error, block, state) = encode_block(chunk, range, filter, history);
+                if best.as_ref().is_none_or(|best| error < best.0) {
+                    best = Some((error, block, state));
+                }
+            }
+        }
+        let (_, mut block, state) = best.unwrap();
+        history = state;
+        if index + 1 == blocks {
+            block[0] |= 1;
+            if loop_block.is_some() {
+                block[0] |= 2;
+            }
+        }
+        brr.extend(block);
+    }
+    brr
+}
+
+fn encode_block(chunk: &[i16], range: u8, filter: u8, history: (i32, i32)) -> (i64, Vec<u8>, (i32, i32)) {
+    let mut block = vec![range << 4 | filter << 2];
+    let mut history = history;
+    let mut error = 0i64;
+    let mut nibbles = Vec::with_capacity(SAMPLES_PER_BLOCK);
+    for target in chunk {
+        let mut best = (i64::MAX, 0, 0i16);
+        for nibble in -8..8 {
+            let sample = decode_nibble(nibble, range, filter, history.0, history.1);
+            let difference = (sample as i64 - *target as i64).abs();
+            if difference < best.0 {
+                best = (difference, nibble, sample);
+            }
+        }
+        error += best.0 * best.0;
+        nibbles.push(best.1);
+        history = (best.2 as i32, history.0);
+    }
+    for pair in nibbles.chunks(2) {
+        block.push(((pair[0] as u8 & 0xf) << 4) | (pair[1] as u8 & 0xf));
+    }
+    (error, block, history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(samples: usize) -> Vec<i16> {
+        (0..samples).map(|index| ((index as f64 / 20.0).sin() * 8000.0) as i16).collect()
+    }
+
+    #[test]
+    fn decodes_range_and_sign() {
+        let block = [0xc1, 0x17, 0x80, 0, 0, 0, 0, 0, 0];
+        let sample = decode(&block, 0, 0).unwrap();
+        assert_eq!(&sample.pcm[..4], &[4096, 28672, -32768, 0]);
+        assert_eq!((sample.size, sample.loop_start), (BLOCK_SIZE, None));
+    }
+
+    #[test]
+    fn filters_use_history() {
+        assert_eq!(decode_nibble(0, 0, 1, 1000, 0), 936);
+        assert_eq!(decode_nibble(0, 0, 0, 1000, 0), 0);
+        assert_eq!(decode_nibble(1, 13, 0, 0, 0), 0); // invalid range
+    }
+
+    #[test]
+    fn loop_flag_sets_loop_start() {
+        let mut data = vec![0xc0, 0x11, 0, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&[0xc3, 0x22, 0, 0, 0, 0, 0, 0, 0]);
+        let sample = decode(&data, 0, BLOCK_SIZE).unwrap();
+        assert_eq!(sample.loop_start, Some(SAMPLES_PER_BLOCK));
+        assert_eq!(sample.pcm.len(), 2 * SAMPLES_PER_BLOCK);
+        assert!(decode(&data[..BLOCK_SIZE], 0, 0).is_err());
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let pcm = sine(SAMPLES_PER_BLOCK * 20 - 5);
+        let brr = encode(&pcm, Some(SAMPLES_PER_BLOCK * 4));
+        assert_eq!(brr.len(), 20 * BLOCK_SIZE);
+        let sample = decode(&brr, 0, 4 * BLOCK_SIZE).unwrap();
+        assert_eq!(sample.loop_start, Some(SAMPLES_PER_BLOCK * 4));
+        assert_eq!(brr[4 * BLOCK_SIZE] & 0x0c, 0); // the loop block doesn't filter
+        // the unfiltered first block needs steps of 1024 for the ramp, the filtered ones do much better
+        let errors: Vec<i64> = sample.pcm.iter().zip(&pcm).map(|(decoded, original)| (*decoded as i64 - *original as i64).abs()).collect();
+        assert!(errors.iter().all(|error| *error <= 512));
+        assert!(errors[SAMPLES_PER_BLOCK..].iter().sum::<i64>() / (errors.len() - SAMPLES_PER_BLOCK) as i64 <= 64);
+    }
+
+    #[test]
+    fn scan_skips_garbage() {
+        let mut data = vec![0xff; 5];
+        data.extend(encode(&sine(SAMPLES_PER_BLOCK * 20), None));
+        data.extend_from_slice(&[0xff; 5]);
+        let samples = scan(&data);
+        assert_eq!(samples.len(), 1);
+        assert_eq!((samples[0].offset, samples[0].size), (5, 20 * BLOCK_SIZE));
+    }
+}
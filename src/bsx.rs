@@ -0,0 +1,113 @@
+// satellaview memory packs and broadcast files (.bs) have their own header in place of the usual one,
+// starting at 0x7fb0/0xffb0 and ending with the same checksum and vectors:
+//   +00 maker code, +02 program type, +10 title (shift-jis), +20 block allocation flags,
+//   +24 limited starts, +26 month, +27 day, +28 map mode, +29 execution flags, +2a 0x33,
+//   +2b version, +2c checksum complement, +2e checksum
+
+use std::char;
+
+const BLOCK_SIZE: usize = 0x20000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BsxHeader {
+    pub maker_code: String,
+    pub program_type: u32,
+    pub title: String,
+    pub blocks: u32, // one bit per 128 KiB block of the memory pack the file occupies
+    pub starts: Option<u32>, // boots left, None when it can be started any number of times
+    pub month: u8, // 0 when there is no broadcast date
+    pub day: u8,
+    pub map_mode: u8,
+    pub execution_flags: u8,
+    pub version: u8,
+}
+
+impl BsxHeader {
+    pub fn size(&self) -> usize {
+        self.blocks.count_ones() as usize * BLOCK_SIZE
+    }
+
+    pub fn date(&self) -> Option<String> {
+        if self.month == 0 {
+            return None;
+        }
+        Some(format!("{:02}/{:02}", self.month, self.day))
+    }
+}
+
+// a few fields only take a handful of values: the map mode is 20/21/30/31 (where a normal header has the
+// sram size), the date keeps its low bits clear and the fixed byte is 0x33 (0xff in some dumps)
+pub fn is_header(data: &[u8], offset: usize) -> bool {
+    let header = match data.get(offset..offset + 0x30) {
+        Some(header) => header,
+        None => return false,
+    };
+    let month = header[0x26];
+    let day = header[0x27];
+    (header[0x2a] == 0x33 || header[0x2a] == 0xff)
+        && (header[0x25] == 0 || header[0x25] & 0x83 == 0x80)
+        && month & 0x0f == 0
+        && month >> 4 <= 12
+        && day & 0x07 == 0
+        && matches!(header[0x28], 0x20 | 0x21 | 0x30 | 0x31)
+        && header[0x29] & 0x0f == 0
+}
+
+// lorom first like for normal headers, hirom at 0xffb0 otherwise
+pub fn find_header(data: &[u8]) -> Option<(usize, BsxHeader)> {
+    [0x7fb0, 0xffb0].iter().find(|offset| is_header(data, **offset)).map(|offset| (*offset, read_header(data, *offset)))
+}
+
+pub fn read_header(data: &[u8], offset: usize) -> BsxHeader {
+    let header = &data[offset..offset + 0x30];
+    let long = |at: usize| header[at] as u32 | (header[at + 1] as u32) << 8 | (header[at + 2] as u32) << 16 | (header[at + 3] as u32) << 24;
+    let limit = header[0x24] as u32 | (header[0x25] as u32) << 8;
+    BsxHeader {
+        maker_code: String::from_utf8_lossy(&header[0x00..0x02]).to_string(),
+        program_type: long(0x02),
+        title: shift_jis(&header[0x10..0x20]),
+        blocks: long(0x20),
+        starts: if limit & 0x8000 != 0 { Some((limit & 0x7fff).count_ones()) } else { None },
+        month: header[0x26] >> 4,
+        day: header[0x27] >> 3,
+        map_mode: header[0x28],
+        execution_flags: header[0x29],
+        version: header[0x2b],
+    }
+}
+
+// the titles use ascii, half width katakana and the kana rows of the double byte set. kanji would need
+// the whole jis table and come out as replacement characters
+pub fn shift_jis(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        index += 1;
+        let code = match byte {
+            0x00 => break,
+            0x20..=0x7e => byte as u32,
+            0xa1..=0xdf => 0xff61 + (byte - 0xa1) as u32,
+            0x81..=0x9f | 0xe0..=0xef if index < bytes.len() => {
+                let word = (byte as u32) << 8 | bytes[index] as u32;
+                index += 1;
+                match word {
+                    0x8140 => 0x3000,
+                    0x8141 => 0x3001,
+                    0x8142 => 0x3002,
+                    0x815b => 0x30fc,
+                    0x824f..=0x8258 => 0xff10 + (word - 0x824f),
+                    0x8260..=0x8279 => 0xff21 + (word - 0x8260),
+                    0x8281..=0x829a => 0xff41 + (word - 0x8281),
+                    0x829f..=0x82f1 => 0x3041 + (word - 0x829f),
+                    0x8340..=0x837e => 0x30a1 + (word - 0x8340),
+                    0x8380..=0x8396 => 0x30e0 + (word - 0x8380),
+                    _ => 0xfffd,
+                }
+            }
+            _ => 0xfffd,
+        };
+        text.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+    }
+    text
+}
@@ -0,0 +1,276 @@
+use cheats::{self, Cheat};
+
+// cheat lists of two emulators. snes9x keeps one .cht per game: 28 byte records up to 1.55 (flags, value,
+// little endian address, saved byte, 20 byte name), bml text since. bsnes's cheats.bml holds every game,
+// each cartridge node with the sha-256 of the headerless rom and its name. bml nests nodes by indentation,
+// a node is "name: value", "name=value" or a name followed by attributes on the same line:
+//   cartridge sha256:...          cheat
+//     name:...                      name: infinite lives
+//     cheat                         code: 7e0dbe=63
+//       description:...             enable
+//       code:7e0dbe=63+...
+
+const RECORD_SIZE: usize = 28;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    name: String,
+    value: String,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn text(&self, name: &str) -> Option<String> {
+        self.child(name).map(|child| child.value.clone())
+    }
+}
+
+fn parse_node(line: &str) -> Node {
+    let split = line.find([':', '=', ' ', '\t']).unwrap_or(line.len());
+    let name = line[..split].to_string();
+    let rest = &line[split..];
+    let mut node = Node { name, value: String::new(), children: Vec::new() };
+    match rest.chars().next() {
+        Some(':') => node.value = rest[1..].trim().to_string(),
+        Some('=') => node.value = rest[1..].trim().trim_matches('"').to_string(),
+        Some(_) => node.children = rest.split_whitespace().map(parse_node).collect(),
+        None => {}
+    }
+    node
+}
+
+fn parse_bml(text: &str) -> Vec<Node> {
+    let mut roots = Vec::new();
+    let mut stack: Vec<(usize, Node)> = Vec::new();
+    let attach = |stack: &mut Vec<(usize, Node)>, roots: &mut Vec<Node>| {
+        let (_, node) = stack.pop().unwrap();
+        match stack.last_mut() {
+            Some((_, parent)) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    };
+    for line in text.lines() {
+        let content = line.trim_start();
+        if content.is_empty() || content.starts_with("//") || content.starts_with('#') {
+            continue;
+        }
+        let depth = line.len() - content.len();
+        while stack.last().is_some_and(|(top, _)| *top >= depth) {
+            attach(&mut stack, &mut roots);
+        }
+        stack.push((depth, parse_node(content.trim_end())));
+    }
+    while !stack.is_empty() {
+        attach(&mut stack, &mut roots);
+    }
+    roots
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheatEntry {
+    pub description: String,
+    pub codes: Vec<String>,
+    pub enabled: bool,
+}
+
+impl CheatEntry {
+    pub fn cheats(&self) -> Result<Vec<Cheat>, String> {
+        self.codes.iter().map(|code| cheats::decode(code).map(|(cheat, _)| cheat)).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CartridgeCheats {
+    pub name: Option<String>, // a snes9x .cht is for one game and names none
+    pub sha256: Option<String>,
+    pub cheats: Vec<CheatEntry>,
+}
+
+fn split_codes(codes: &str) -> Vec<String> {
+    codes.split('+').map(|code| code.trim().to_string()).filter(|code| !code.is_empty()).collect()
+}
+
+fn entry(node: &Node) -> CheatEntry {
+    CheatEntry {
+        description: node.text("description").or_else(|| node.text("name")).unwrap_or_default(),
+        codes: split_codes(&node.text("code").unwrap_or_default()),
+        enabled: node.child("enable").is_some() || node.child("enabled").is_some(),
+    }
+}
+
+fn parse_records(data: &[u8]) -> Vec<CheatEntry> {
+    data.chunks(RECORD_SIZE)
+        .map(|record| {
+            let cheat = Cheat {
+                address: record[2] as u32 | (record[3] as u32) << 8 | (record[4] as u32) << 16,
+                value: record[1],
+                compare: None,
+            };
+            let name = &record[8..];
+            let end = name.iter().position(|byte| *byte == 0).unwrap_or(name.len());
+            CheatEntry {
+                description: String::from_utf8_lossy(&name[..end]).to_string(),
+                codes: vec![cheats::encode_raw(&cheat)],
+                enabled: record[0] & 4 == 0,
+            }
+        })
+        .collect()
+}
+
+// either format, told apart by the bml keywords the text ones start with
+pub fn load(data: &[u8]) -> Result<Vec<CartridgeCheats>, String> {
+    let text = String::from_utf8_lossy(data);
+    let nodes = parse_bml(&text);
+    let known = |node: &Node| node.name == "cheat" || node.name == "cartridge";
+    if nodes.is_empty() || !nodes.iter().all(known) {
+        if !data.is_empty() && data.len().is_multiple_of(RECORD_SIZE) {
+            return Ok(vec![CartridgeCheats { name: None, sha256: None, cheats: parse_records(data) }]);
+        }
+        return Err("neither a cheat list in bml nor a snes9x .cht".to_string());
+    }
+    let single: Vec<CheatEntry> = nodes.iter().filter(|node| node.name == "cheat").map(entry).collect();
+    let mut cartridges = Vec::new();
+    if !single.is_empty() {
+        cartridges.push(CartridgeCheats { name: None, sha256: None, cheats: single });
+    }
+    for node in nodes.iter().filter(|node| node.name == "cartridge") {
+        cartridges.push(CartridgeCheats {
+            name: node.text("name").or_else(|| node.text("title")),
+            sha256: node.text("sha256").map(|hash| hash.to_lowercase()),
+            cheats: node.children.iter().filter(|child| child.name == "cheat").map(entry).collect(),
+        });
+    }
+    Ok(cartridges)
+}
+
+// lowercase letters and digits, without the (region) and [flags] parts of database names
+fn normalize(title: &str) -> String {
+    let mut depth = 0;
+    let mut normalized = String::new();
+    for c in title.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            _ if depth == 0 && c.is_alphanumeric() => normalized.extend(c.to_lowercase()),
+            _ => {}
+        }
+    }
+    normalized
+}
+
+// the cartridge with the rom's sha-256, or else the one named like the internal title; lists without
+// names (snes9x's) are for whatever rom they are used with
+pub fn find<'a>(database: &'a [CartridgeCheats], sha256: &str, title: &str) -> Option<&'a CartridgeCheats> {
+    let title = normalize(title);
+    database
+        .iter()
+        .find(|cartridge| cartridge.sha256.as_deref() == Some(sha256))
+        .or_else(|| database.iter().find(|cartridge| !title.is_empty() && cartridge.name.as_ref().is_some_and(|name| normalize(name) == title)))
+        .or_else(|| database.iter().find(|cartridge| cartridge.name.is_none() && cartridge.sha256.is_none()))
+}
+
+// snes9x's bml .cht, which bsnes reads too
+pub fn export_cht(entries: &[CheatEntry]) -> String {
+    let mut text = String::new();
+    for entry in entries {
+        text.push_str("cheat\n");
+        text.push_str(&format!("  name: {}\n", entry.description));
+        text.push_str(&format!("  code: {}\n", entry.codes.join("+")));
+        if entry.enabled {
+            text.push_str("  enable\n");
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE: &str = "\
+cartridge sha256:0123ABCD
+  name:Super Mario World (USA)
+  cheat
+    description:Infinite lives
+    code:7e0dbe=63+7e0dbf=00
+  cheat
+    description:Start with cape
+    code:7e0019=02
+    enable
+
+cartridge sha256:ffff0000
+  name:Donkey Kong Country (USA) (Rev 2)
+  cheat
+    description:Infinite lives
+    code:7e0575=63
+";
+
+    #[test]
+    fn bsnes_cartridges() {
+        let database = load(DATABASE.as_bytes()).unwrap();
+        assert_eq!(database.len(), 2);
+        let mario = &database[0];
+        assert_eq!(mario.name, Some("Super Mario World (USA)".to_string()));
+        assert_eq!(mario.sha256, Some("0123abcd".to_string()));
+        assert_eq!(
+            mario.cheats,
+            vec![
+                CheatEntry { description: "Infinite lives".to_string(), codes: vec!["7e0dbe=63".to_string(), "7e0dbf=00".to_string()], enabled: false },
+                CheatEntry { description: "Start with cape".to_string(), codes: vec!["7e0019=02".to_string()], enabled: true },
+            ]
+        );
+        assert_eq!(mario.cheats[0].cheats().unwrap()[1], Cheat { address: 0x7e0dbf, value: 0x00, compare: None });
+        assert_eq!(database[1].cheats.len(), 1);
+    }
+
+    #[test]
+    fn snes9x_bml() {
+        let text = "cheat\n  name: Infinite time\n  code: 7e0f31=09+7e0f32=09\n  enable\n\ncheat\n  name: Moon jump\n  code: 7e007d=f0\n";
+        let list = load(text.as_bytes()).unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!((list[0].name.clone(), list[0].sha256.clone()), (None, None));
+        assert_eq!(list[0].cheats[0].codes, vec!["7e0f31=09", "7e0f32=09"]);
+        assert_eq!((list[0].cheats[0].enabled, list[0].cheats[1].enabled), (true, false));
+        assert_eq!(list[0].cheats[1].description, "Moon jump");
+        assert_eq!(load(export_cht(&list[0].cheats).as_bytes()).unwrap(), list);
+    }
+
+    #[test]
+    fn binary_records() {
+        let record = |flags: u8, name: &[u8]| {
+            let mut record = vec![flags, 0x63, 0xbe, 0x0d, 0x7e, 0x05, 0, 0];
+            record.extend_from_slice(name);
+            record.resize(RECORD_SIZE, 0);
+            record
+        };
+        let mut data = record(0, b"Infinite lives");
+        data.extend(record(4, b"20 character name xx"));
+        let list = load(&data).unwrap();
+        assert_eq!(
+            list[0].cheats,
+            vec![
+                CheatEntry { description: "Infinite lives".to_string(), codes: vec!["7e0dbe=63".to_string()], enabled: true },
+                CheatEntry { description: "20 character name xx".to_string(), codes: vec!["7e0dbe=63".to_string()], enabled: false },
+            ]
+        );
+        assert!(load(&data[..RECORD_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn find_by_hash_then_title() {
+        let mut database = load(DATABASE.as_bytes()).unwrap();
+        assert_eq!(find(&database, "ffff0000", "SUPER MARIOWORLD").unwrap().sha256, Some("ffff0000".to_string()));
+        // the internal title has no spaces to go by, region and revision tags are left out of the comparison
+        assert_eq!(find(&database, "", "SUPER MARIOWORLD").unwrap().sha256, Some("0123abcd".to_string()));
+        assert_eq!(find(&database, "", "DONKEY KONG COUNTRY").unwrap().sha256, Some("ffff0000".to_string()));
+        assert_eq!(find(&database, "", "ZELDA"), None);
+        assert_eq!(find(&database, "", ""), None);
+        database.push(CartridgeCheats { name: None, sha256: None, cheats: Vec::new() });
+        assert_eq!(find(&database, "", "ZELDA").unwrap().name, None);
+    }
+}
@@ -0,0 +1,186 @@
+use checksum;
+use mapper::Mapper;
+use snesutilities;
+
+// game genie codes are eight hex digits with the digits swapped for others (DF4709156BC8A23E stand for
+// 0-f): the value byte, then the address with its bits shuffled around. pro action replay codes are the
+// plain 24 bit address and the value. emulators write them as address=value, with an optional byte
+// the old value has to match (address=compare?value)
+
+const GAME_GENIE_DIGITS: &[u8] = b"DF4709156BC8A23E";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatFormat {
+    GameGenie,
+    ProActionReplay,
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cheat {
+    pub address: u32,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl Cheat {
+    // wram, either through 7e/7f or the low 8 KiB mirrored in the system banks
+    pub fn is_ram(&self) -> bool {
+        let bank = self.address >> 16;
+        bank == 0x7e || bank == 0x7f || ((bank & 0x7f) < 0x40 && self.address & 0xffff < 0x2000)
+    }
+}
+
+// bits of the address in the order the game genie stores them, each as (mask in the code, shift to its place)
+const SHUFFLE: [(u32, i32); 7] = [
+    (0x003c00, 10),
+    (0x00003c, 14),
+    (0xf00000, -8),
+    (0x000003, 10),
+    (0x00c000, -6),
+    (0x0f0000, -12),
+    (0x0003c0, -6),
+];
+
+fn shift(value: u32, by: i32) -> u32 {
+    if by >= 0 { value << by } else { value >> -by }
+}
+
+pub fn decode(code: &str) -> Result<(Cheat, CheatFormat), String> {
+    let code = code.trim();
+    if let Some(separator) = code.find(['=', ':']) {
+        return decode_raw(&code[..separator], &code[separator + 1..]).map(|cheat| (cheat, CheatFormat::Raw));
+    }
+    if code.len() == 9 && code.as_bytes()[4] == b'-' {
+        return decode_game_genie(&code.replace('-', "")).map(|cheat| (cheat, CheatFormat::GameGenie));
+    }
+    if code.len() == 8 {
+        let word = u32::from_str_radix(code, 16).map_err(|_| format!("{} is not a cheat code", code))?;
+        return Ok((Cheat { address: word >> 8, value: word as u8, compare: None }, CheatFormat::ProActionReplay));
+    }
+    Err(format!("{} is not a cheat code", code))
+}
+
+fn decode_raw(address: &str, value: &str) -> Result<Cheat, String> {
+    let hex = |text: &str| u32::from_str_radix(text.trim(), 16).map_err(|_| format!("{} is not a hex number", text));
+    let (compare, value) = match value.find('?') {
+        Some(split) => (Some(hex(&value[..split])? as u8), hex(&value[split + 1..])? as u8),
+        None => (None, hex(value)? as u8),
+    };
+    Ok(Cheat { address: hex(address)? & 0xffffff, value, compare })
+}
+
+fn decode_game_genie(code: &str) -> Result<Cheat, String> {
+    let mut word = 0u32;
+    for digit in code.to_uppercase().bytes() {
+        let nibble = GAME_GENIE_DIGITS.iter().position(|known| *known == digit).ok_or(format!("{} is not a game genie digit", digit as char))?;
+        word = word << 4 | nibble as u32;
+    }
+    let scrambled = word & 0xffffff;
+    let address = SHUFFLE.iter().fold(0, |address, &(mask, by)| address | shift(scrambled & mask, by));
+    Ok(Cheat { address, value: (word >> 24) as u8, compare: None })
+}
+
+pub fn encode_game_genie(cheat: &Cheat) -> String {
+    let scrambled = SHUFFLE.iter().fold(0, |scrambled, &(mask, by)| scrambled | (shift(cheat.address, -by) & mask));
+    let word = (cheat.value as u32) << 24 | scrambled;
+    let digits: String = (0..8).rev().map(|nibble| GAME_GENIE_DIGITS[(word >> (nibble * 4) & 15) as usize] as char).collect();
+    format!("{}-{}", &digits[..4], &digits[4..])
+}
+
+pub fn encode_pro_action_replay(cheat: &Cheat) -> String {
+    format!("{:06X}{:02X}", cheat.address, cheat.value)
+}
+
+pub fn encode_raw(cheat: &Cheat) -> String {
+    match cheat.compare {
+        Some(compare) => format!("{:06x}={:02x}?{:02x}", cheat.address, compare, cheat.value),
+        None => format!("{:06x}={:02x}", cheat.address, cheat.value),
+    }
+}
+
+// None for ram cheats and addresses the mapper doesn't put in rom
+pub fn to_offset(cheat: &Cheat, mapper: &Mapper) -> Option<usize> {
+    if cheat.is_ram() {
+        return None;
+    }
+    mapper.to_offset(cheat.address)
+}
+
+// writes the rom cheats into the image and fixes the checksum, ram cheats are left to the emulator.
+// a cheat with a compare byte only goes in where the rom has that byte
+pub fn apply(data: &[u8], cheats: &[Cheat], mapper: &Mapper) -> Result<Vec<u8>, String> {
+    let mut image = data.to_vec();
+    for cheat in cheats.iter().filter(|cheat| !cheat.is_ram()) {
+        let offset = to_offset(cheat, mapper).ok_or(format!("{} isn't in rom", encode_raw(cheat)))?;
+        if let Some(compare) = cheat.compare {
+            if image[offset] != compare {
+                return Err(format!("{}: the rom has {:02x} at {:#x}", encode_raw(cheat), image[offset], offset));
+            }
+        }
+        image[offset] = cheat.value;
+    }
+    if image.len() >= 0x8000 {
+        let header = snesutilities::find_header(&image);
+        checksum::fix_checksum(&mut image, header);
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snesutilities::{RomMakupType, RomType};
+
+    fn cheat(address: u32, value: u8) -> Cheat {
+        Cheat { address, value, compare: None }
+    }
+
+    #[test]
+    fn game_genie_vectors() {
+        assert_eq!(decode("DD62-6DAD").unwrap(), (cheat(0x0082d3, 0x00), CheatFormat::GameGenie));
+        assert_eq!(decode("c2bf-d4a7").unwrap(), (cheat(0x009c1b, 0xad), CheatFormat::GameGenie));
+        assert_eq!(encode_game_genie(&cheat(0x7e0dbf, 0x09)), "DBD8-9EE8");
+        assert_eq!(encode_game_genie(&cheat(0x808000, 0xea)), "3C6D-4DDD");
+        assert!(decode("DD62-6DAX").is_err());
+    }
+
+    #[test]
+    fn pro_action_replay_and_raw() {
+        assert_eq!(decode("7E0DBF09").unwrap(), (cheat(0x7e0dbf, 0x09), CheatFormat::ProActionReplay));
+        assert_eq!(encode_pro_action_replay(&cheat(0x7e0dbf, 0x09)), "7E0DBF09");
+        let compared = Cheat { address: 0x00c123, value: 0xea, compare: Some(0xd0) };
+        assert_eq!(decode("00c123=d0?ea").unwrap(), (compared, CheatFormat::Raw));
+        assert_eq!(encode_raw(&compared), "00c123=d0?ea");
+        assert_eq!(decode("7e0010:ff").unwrap().0, cheat(0x7e0010, 0xff));
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        for address in (0..0x1000000u32).step_by(0x1357) {
+            let original = cheat(address, (address >> 3) as u8);
+            assert_eq!(decode(&encode_game_genie(&original)).unwrap().0, original);
+            assert_eq!(decode(&encode_pro_action_replay(&original)).unwrap().0, original);
+            assert_eq!(decode(&encode_raw(&original)).unwrap().0, original);
+        }
+    }
+
+    #[test]
+    fn ram_cheats() {
+        assert!(cheat(0x7e0dbf, 0).is_ram());
+        assert!(cheat(0x801fff, 0).is_ram());
+        assert!(!cheat(0x808000, 0).is_ram());
+        assert!(!cheat(0x402000, 0).is_ram());
+    }
+
+    #[test]
+    fn apply_checks_compare_bytes() {
+        let mapper = Mapper { makeup: RomMakupType::LoROM, rom_type: RomType::ROM, size: 0x8000 };
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0123] = 0xd0;
+        let patched = apply(&rom, &[Cheat { address: 0x808123, value: 0xea, compare: Some(0xd0) }, cheat(0x7e0000, 1)], &mapper).unwrap();
+        assert_eq!(patched[0x0123], 0xea);
+        assert_eq!(checksum::read_checksum(&patched, 0x7fc0).unwrap().1, checksum::compute_checksum(&patched));
+        assert!(apply(&rom, &[Cheat { address: 0x808123, value: 0xea, compare: Some(0x10) }], &mapper).is_err());
+    }
+}
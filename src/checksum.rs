@@ -0,0 +1,62 @@
+// the checksum covers the rom as the console sees it, so images that aren't a power of two are summed
+// with their upper part mirrored up to the next power of two
+pub fn compute_checksum(data: &[u8]) -> u16 {
+    if data.is_empty() {
+        return 0;
+    }
+    mirrored_sum(data, data.len().next_power_of_two()) as u16
+}
+
+fn mirrored_sum(data: &[u8], size: usize) -> u32 {
+    let mut base = 1;
+    while base * 2 <= data.len() {
+        base *= 2;
+    }
+    let sum = data[..base].iter().fold(0u32, |acc, byte| acc.wrapping_add(*byte as u32));
+    if base == data.len() {
+        sum.wrapping_mul((size / base) as u32)
+    } else {
+        sum.wrapping_add(mirrored_sum(&data[base..], size - base))
+    }
+}
+
+// (complement, checksum) as stored in the internal header starting at offset
+pub fn read_checksum(data: &[u8], offset: usize) -> Option<(u16, u16)> {
+    let bytes = data.get(offset + 0x1c..offset + 0x20)?;
+    Some((bytes[0] as u16 | (bytes[1] as u16) << 8, bytes[2] as u16 | (bytes[3] as u16) << 8))
+}
+
+// rewrites complement and checksum of the internal header at offset, returns the old and new checksum.
+// None when the data ends before the header
+pub fn fix_checksum(data: &mut [u8], offset: usize) -> Option<(u16, u16)> {
+    let (_, old) = read_checksum(data, offset)?;
+    // a valid complement/checksum pair always adds 0x1fe to the sum, start from one
+    data[offset + 0x1c..offset + 0x20].copy_from_slice(&[0xff, 0xff, 0x00, 0x00]);
+    let checksum = compute_checksum(data);
+    let complement = !checksum;
+    data[offset + 0x1c] = complement as u8;
+    data[offset + 0x1d] = (complement >> 8) as u8;
+    data[offset + 0x1e] = checksum as u8;
+    data[offset + 0x1f] = (checksum >> 8) as u8;
+    Some((old, checksum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixes_the_header() {
+        let mut data: Vec<u8> = (0..0x8000u32).map(|offset| (offset >> 4) as u8).collect();
+        let (_, new) = fix_checksum(&mut data, 0x7fc0).unwrap();
+        assert_eq!(read_checksum(&data, 0x7fc0), Some((!new, new)));
+        assert_eq!(compute_checksum(&data), new);
+    }
+
+    #[test]
+    fn short_data_has_no_header() {
+        let mut data = vec![0x55; 0x1000];
+        assert_eq!(fix_checksum(&mut data, 0x7fc0), None);
+        assert_eq!(data, vec![0x55; 0x1000]);
+    }
+}
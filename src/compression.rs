@@ -0,0 +1,635 @@
+use std::collections::HashMap;
+
+// the compression schemes commonly found in commercial games. the lz family shares its command
+// header: ccclllll with length - 1, or 111cccll llllllll for up to 1024 bytes, 0xff ends the stream.
+//   lz1 (a link to the past):    0 copy, 1 byte fill, 2 word fill, 3 increasing fill, 4 repeat (le address)
+//   lz2 (super mario world):     like lz1 with big endian repeat addresses
+//   lz5:                         0 copy, 1 byte fill, 2 word fill, 3 zero fill, 4 repeat (le address),
+//                                5 repeat xor 0xff, 6 repeat from 1 byte back distance
+//   hal (earthbound, kirby):     like lz2 but word fills count words, 5 repeats bit reversed bytes,
+//                                6 repeats backwards
+// konami (contra iii, axelay): le word with the stream size, then 00-7f copy (c >> 2) + 2 bytes from the
+//   1 KiB ring buffer at (c & 3) << 8 | next, 80-9f c & 0x1f literals, a0-bf fill the next byte
+//   (c & 0x1f) + 2 times, c0-df (c & 0x1f) + 2 zeros, e0-ff (c & 0x1f) + 0x20 literals
+// square (final fantasy vi): le word with the stream size, then lzss with a flag byte per 8 items (lsb
+//   first, set = literal) and 2 byte references: 11 bit position in the 2 KiB ring buffer, length - 3
+//   in the top 5 bits. the ring starts out zeroed and is written from 0x7de
+// rle: count byte, 00 ends, 01-7f literals, 80-ff repeat the next byte (c & 0x7f) + 1 times
+
+pub const MAX_OUTPUT: usize = 0x10000;
+const KONAMI_RING: usize = 0x400;
+const SQUARE_RING: usize = 0x800;
+const SQUARE_START: usize = 0x7de;
+const MAX_CHAIN: usize = 64;
+const MIN_SCAN_INPUT: usize = 0x10;
+const MIN_SCAN_OUTPUT: usize = 0x200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    Lz1,
+    Lz2,
+    Lz5,
+    Hal,
+    Konami,
+    Square,
+    Rle,
+}
+
+pub const CODECS: [Codec; 7] = [Codec::Lz1, Codec::Lz2, Codec::Lz5, Codec::Hal, Codec::Konami, Codec::Square, Codec::Rle];
+
+impl Codec {
+    pub fn from_name(name: &str) -> Option<Codec> {
+        match name {
+            "lz1" => Some(Codec::Lz1),
+            "lz2" => Some(Codec::Lz2),
+            "lz5" => Some(Codec::Lz5),
+            "hal" => Some(Codec::Hal),
+            "konami" => Some(Codec::Konami),
+            "square" => Some(Codec::Square),
+            "rle" => Some(Codec::Rle),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decompressed {
+    pub data: Vec<u8>,
+    pub consumed: usize, // compressed bytes including the terminator or size word
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stream {
+    pub offset: usize,
+    pub codec: Codec,
+    pub compressed_size: usize,
+    pub decompressed_size: usize,
+}
+
+pub fn decompress(codec: Codec, data: &[u8], offset: usize) -> Result<Decompressed, String> {
+    decompress_checked(codec, data, offset, false)
+}
+
+// strict refuses ring buffer reads from before anything was written there. real streams do that at
+// times to get zeros, but garbage does it all the time, which keeps the scanner fast. rle gets the
+// same treatment for runs no encoder would write: repeats under 3 bytes and literals that repeat
+fn decompress_checked(codec: Codec, data: &[u8], offset: usize, strict: bool) -> Result<Decompressed, String> {
+    let input = data.get(offset..).ok_or("offset past the end of the data")?;
+    match codec {
+        Codec::Konami => decompress_konami(input, strict),
+        Codec::Square => decompress_square(input, strict),
+        Codec::Rle => decompress_rle(input, strict),
+        _ => decompress_lz(codec, input),
+    }
+}
+
+// only what decompress takes back: at most 64 KiB of data, and konami and square streams have to fit
+// their size word
+pub fn compress(codec: Codec, data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() > MAX_OUTPUT {
+        return Err(format!("{:#x} bytes is more than the 64 KiB a stream unpacks to", data.len()));
+    }
+    let compressed = match codec {
+        Codec::Konami => compress_konami(data),
+        Codec::Square => compress_square(data),
+        Codec::Rle => compress_rle(data),
+        _ => compress_lz(codec, data),
+    };
+    if matches!(codec, Codec::Konami | Codec::Square) && compressed.len() > 0xffff {
+        return Err(format!("{:?} stream would be {:#x} bytes, its size word only holds 0xffff", codec, compressed.len()));
+    }
+    Ok(compressed)
+}
+
+// every codec at every offset in the range, keeping streams that end cleanly and expand enough.
+// padding is skipped, nothing starts in the middle of it or with its last bytes
+pub fn scan(data: &[u8], start: usize, end: usize) -> Vec<Stream> {
+    let mut streams = Vec::new();
+    let mut offset = start;
+    while offset < end.min(data.len()) {
+        let uniform = data[offset..data.len().min(offset + MIN_SCAN_INPUT)].iter().all(|byte| *byte == data[offset]);
+        let filler = data[offset] == 0x00 || data[offset] == 0xff;
+        let padding = filler && (data.get(offset + 1) == Some(&data[offset]) || (offset > 0 && data[offset - 1] == data[offset]));
+        if uniform || padding {
+            offset += 1;
+            continue;
+        }
+        let found = CODECS.iter().find_map(|codec| {
+            let result = decompress_checked(*codec, data, offset, true).ok()?;
+            let plausible = result.consumed >= MIN_SCAN_INPUT && result.data.len() >= MIN_SCAN_OUTPUT && result.data.len() >= result.consumed * 3 / 2;
+            if plausible {
+                Some(Stream {
+                    offset,
+                    codec: *codec,
+                    compressed_size: result.consumed,
+                    decompressed_size: result.data.len(),
+                })
+            } else {
+                None
+            }
+        });
+        match found {
+            Some(stream) => {
+                offset += stream.compressed_size;
+                streams.push(stream);
+            }
+            None => offset += 1,
+        }
+    }
+    streams
+}
+
+fn next(input: &[u8], position: &mut usize) -> Result<u8, String> {
+    let byte = *input.get(*position).ok_or("compressed data ends early")?;
+    *position += 1;
+    Ok(byte)
+}
+
+fn check_size(output: &[u8], length: usize) -> Result<(), String> {
+    if output.len() + length > MAX_OUTPUT {
+        return Err("decompressed data grows past 64 KiB".to_string());
+    }
+    Ok(())
+}
+
+fn decompress_lz(codec: Codec, input: &[u8]) -> Result<Decompressed, String> {
+    let mut output = Vec::new();
+    let mut position = 0;
+    loop {
+        let header = next(input, &mut position)?;
+        if header == 0xff {
+            return Ok(Decompressed { data: output, consumed: position });
+        }
+        let (command, mut length) = if header & 0xe0 == 0xe0 {
+            ((header >> 2) & 7, ((header as usize & 3) << 8 | next(input, &mut position)? as usize) + 1)
+        } else {
+            (header >> 5, (header as usize & 0x1f) + 1)
+        };
+        if codec == Codec::Hal && command == 2 {
+            length *= 2;
+        }
+        check_size(&output, length)?;
+        match (command, codec) {
+            (0, _) => {
+                let bytes = input.get(position..position + length).ok_or("compressed data ends early")?;
+                output.extend_from_slice(bytes);
+                position += length;
+            }
+            (1, _) => {
+                let byte = next(input, &mut position)?;
+                output.extend(std::iter::repeat_n(byte, length));
+            }
+            (2, _) => {
+                let word = [next(input, &mut position)?, next(input, &mut position)?];
+                output.extend((0..length).map(|index| word[index & 1]));
+            }
+            (3, Codec::Lz5) => output.extend(std::iter::repeat_n(0, length)),
+            (3, _) => {
+                let byte = next(input, &mut position)?;
+                output.extend((0..length).map(|index| byte.wrapping_add(index as u8)));
+            }
+            (4, _) | (5, Codec::Lz5) | (5, Codec::Hal) | (6, Codec::Hal) => {
+                let (first, second) = (next(input, &mut position)? as usize, next(input, &mut position)? as usize);
+                let source = match codec {
+                    Codec::Lz1 | Codec::Lz5 => first | second << 8,
+                    _ => first << 8 | second,
+                };
+                for index in 0..length {
+                    let from = if command == 6 { source.checked_sub(index) } else { Some(source + index) };
+                    let byte = *from.and_then(|from| output.get(from)).ok_or("repeat reaches outside the decompressed data")?;
+                    output.push(match (command, codec) {
+                        (5, Codec::Lz5) => byte ^ 0xff,
+                        (5, _) => byte.reverse_bits(),
+                        _ => byte,
+                    });
+                }
+            }
+            (6, Codec::Lz5) => {
+                let distance = next(input, &mut position)? as usize;
+                if distance == 0 || distance > output.len() {
+                    return Err("repeat reaches outside the decompressed data".to_string());
+                }
+                for _ in 0..length {
+                    let byte = output[output.len() - distance];
+                    output.push(byte);
+                }
+            }
+            _ => return Err(format!("command {} isn't used by {:?}", command, codec)),
+        }
+    }
+}
+
+fn decompress_konami(input: &[u8], strict: bool) -> Result<Decompressed, String> {
+    let size = read_le16(input).ok_or("compressed data ends early")?;
+    if size < 2 || size > input.len() {
+        return Err("konami stream size out of range".to_string());
+    }
+    let mut ring = [0u8; KONAMI_RING];
+    let mut output = Vec::new();
+    let mut position = 2;
+    while position < size {
+        let command = next(input, &mut position)?;
+        match command {
+            0x00..=0x7f => {
+                let length = (command as usize >> 2) + 2;
+                let source = (command as usize & 3) << 8 | next(input, &mut position)? as usize;
+                check_size(&output, length)?;
+                if strict && output.len() < KONAMI_RING && source >= output.len() {
+                    return Err("copy from an unwritten part of the ring buffer".to_string());
+                }
+                for index in 0..length {
+                    let byte = ring[(source + index) % KONAMI_RING];
+                    emit(&mut ring, &mut output, byte);
+                }
+            }
+            0x80..=0x9f | 0xe0..=0xff => {
+                let length = if command < 0xe0 { command as usize & 0x1f } else { (command as usize & 0x1f) + 0x20 };
+                check_size(&output, length)?;
+                for _ in 0..length {
+                    let byte = next(input, &mut position)?;
+                    emit(&mut ring, &mut output, byte);
+                }
+            }
+            _ => {
+                let length = (command as usize & 0x1f) + 2;
+                let byte = if command < 0xc0 { next(input, &mut position)? } else { 0 };
+                check_size(&output, length)?;
+                for _ in 0..length {
+                    emit(&mut ring, &mut output, byte);
+                }
+            }
+        }
+    }
+    if position != size {
+        return Err("konami command runs past the stream size".to_string());
+    }
+    Ok(Decompressed { data: output, consumed: size })
+}
+
+fn emit(ring: &mut [u8; KONAMI_RING], output: &mut Vec<u8>, byte: u8) {
+    ring[output.len() % KONAMI_RING] = byte;
+    output.push(byte);
+}
+
+fn decompress_square(input: &[u8], strict: bool) -> Result<Decompressed, String> {
+    let size = read_le16(input).ok_or("compressed data ends early")?;
+    if size < 2 || size > input.len() {
+        return Err("square stream size out of range".to_string());
+    }
+    let mut ring = [0u8; SQUARE_RING];
+    let mut write = SQUARE_START;
+    let mut output = Vec::new();
+    let mut position = 2;
+    while position < size {
+        let flags = next(input, &mut position)?;
+        for bit in 0..8 {
+            if position >= size {
+                break;
+            }
+            if flags & (1 << bit) != 0 {
+                let byte = next(input, &mut position)?;
+                check_size(&output, 1)?;
+                ring[write] = byte;
+                write = (write + 1) % SQUARE_RING;
+                output.push(byte);
+            } else {
+                let (low, high) = (next(input, &mut position)? as usize, next(input, &mut position)? as usize);
+                let source = low | (high & 7) << 8;
+                let length = (high >> 3) + 3;
+                check_size(&output, length)?;
+                if strict && output.len() < SQUARE_RING && (source + SQUARE_RING - SQUARE_START) % SQUARE_RING >= output.len() {
+                    return Err("reference to an unwritten part of the ring buffer".to_string());
+                }
+                for index in 0..length {
+                    let byte = ring[(source + index) % SQUARE_RING];
+                    ring[write] = byte;
+                    write = (write + 1) % SQUARE_RING;
+                    output.push(byte);
+                }
+            }
+        }
+    }
+    if position != size {
+        return Err("square reference runs past the stream size".to_string());
+    }
+    Ok(Decompressed { data: output, consumed: size })
+}
+
+fn decompress_rle(input: &[u8], strict: bool) -> Result<Decompressed, String> {
+    let mut output = Vec::new();
+    let mut position = 0;
+    loop {
+        let count = next(input, &mut position)? as usize;
+        match count {
+            0 => return Ok(Decompressed { data: output, consumed: position }),
+            0x01..=0x7f => {
+                check_size(&output, count)?;
+                let literals = input.get(position..position + count).ok_or("compressed data ends early")?;
+                if strict && literals.windows(3).any(|run| run[0] == run[1] && run[1] == run[2]) {
+                    return Err("rle literals contain a run".to_string());
+                }
+                output.extend_from_slice(literals);
+                position += count;
+            }
+            _ => {
+                let length = (count & 0x7f) + 1;
+                if strict && length < 3 {
+                    return Err("rle run is too short".to_string());
+                }
+                check_size(&output, length)?;
+                let byte = next(input, &mut position)?;
+                output.extend(std::iter::repeat_n(byte, length));
+            }
+        }
+    }
+}
+
+fn read_le16(input: &[u8]) -> Option<usize> {
+    Some(*input.first()? as usize | (*input.get(1)? as usize) << 8)
+}
+
+// earlier positions starting with the same three bytes, newest first
+struct Matcher {
+    chains: HashMap<[u8; 3], Vec<usize>>,
+}
+
+impl Matcher {
+    fn new() -> Matcher {
+        Matcher { chains: HashMap::new() }
+    }
+
+    fn insert(&mut self, data: &[u8], position: usize) {
+        if position + 3 <= data.len() {
+            self.chains.entry([data[position], data[position + 1], data[position + 2]]).or_default().push(position);
+        }
+    }
+
+    // (length, source) of the longest match for position among sources the filter accepts
+    fn longest<F: Fn(usize) -> bool>(&self, data: &[u8], position: usize, limit: usize, accept: F) -> (usize, usize) {
+        let mut best = (0, 0);
+        if position + 3 > data.len() {
+            return best;
+        }
+        if let Some(chain) = self.chains.get(&[data[position], data[position + 1], data[position + 2]]) {
+            for &source in chain.iter().rev().filter(|source| accept(**source)).take(MAX_CHAIN) {
+                let length = (0..limit.min(data.len() - position)).take_while(|index| data[source + index] == data[position + index]).count();
+                if length > best.0 {
+                    best = (length, source);
+                }
+            }
+        }
+        best
+    }
+}
+
+fn run_length<F: Fn(usize) -> u8>(data: &[u8], position: usize, limit: usize, expected: F) -> usize {
+    (0..limit.min(data.len() - position)).take_while(|index| data[position + index] == expected(*index)).count()
+}
+
+fn lz_header(output: &mut Vec<u8>, command: u8, length: usize) {
+    let length = length - 1;
+    if length < 0x20 {
+        output.push(command << 5 | length as u8);
+    } else {
+        output.push(0xe0 | command << 2 | (length >> 8) as u8);
+        output.push(length as u8);
+    }
+}
+
+fn flush_literals(output: &mut Vec<u8>, data: &[u8], start: usize, end: usize) {
+    for chunk in data[start..end].chunks(1024) {
+        lz_header(output, 0, chunk.len());
+        output.extend_from_slice(chunk);
+    }
+}
+
+// greedy: at every position take whichever fill or repeat saves the most bytes
+fn compress_lz(codec: Codec, data: &[u8]) -> Vec<u8> {
+    const LIMIT: usize = 1024;
+    let mut output = Vec::new();
+    let mut matcher = Matcher::new();
+    let mut literals = 0;
+    let mut position = 0;
+    while position < data.len() {
+        let byte = data[position];
+        let second = data.get(position + 1).cloned().unwrap_or(byte);
+        // (bytes covered, command, payload)
+        let mut candidates = vec![(run_length(data, position, LIMIT, |_| byte), 1, vec![byte])];
+        let word_limit = if codec == Codec::Hal { LIMIT * 2 } else { LIMIT };
+        let mut word = run_length(data, position, word_limit, |index| if index & 1 == 0 { byte } else { second });
+        if codec == Codec::Hal {
+            word &= !1;
+        }
+        candidates.push((word, 2, vec![byte, second]));
+        if codec == Codec::Lz5 {
+            candidates.push((run_length(data, position, LIMIT, |_| 0), 3, vec![]));
+            let (length, source) = matcher.longest(data, position, LIMIT, |source| position - source <= 0xff);
+            candidates.push((length, 6, vec![(position - source) as u8]));
+        } else {
+            candidates.push((run_length(data, position, LIMIT, |index| byte.wrapping_add(index as u8)), 3, vec![byte]));
+        }
+        let (length, source) = matcher.longest(data, position, LIMIT, |source| source <= 0xffff);
+        let address = match codec {
+            Codec::Lz1 | Codec::Lz5 => vec![source as u8, (source >> 8) as u8],
+            _ => vec![(source >> 8) as u8, source as u8],
+        };
+        candidates.push((length, 4, address));
+
+        let gain = |candidate: &(usize, u8, Vec<u8>)| candidate.0 as isize - candidate.2.len() as isize - if candidate.0 > 32 { 2 } else { 1 };
+        let best = candidates.into_iter().filter(|candidate| candidate.0 > 0 && gain(candidate) > 1).max_by_key(|candidate| gain(candidate));
+        match best {
+            Some((length, command, payload)) => {
+                flush_literals(&mut output, data, position - literals, position);
+                literals = 0;
+                let count = if codec == Codec::Hal && command == 2 { length / 2 } else { length };
+                lz_header(&mut output, command, count);
+                output.extend(payload);
+                for inserted in position..position + length {
+                    matcher.insert(data, inserted);
+                }
+                position += length;
+            }
+            None => {
+                matcher.insert(data, position);
+                literals += 1;
+                position += 1;
+            }
+        }
+    }
+    flush_literals(&mut output, data, position - literals, position);
+    output.push(0xff);
+    output
+}
+
+fn compress_konami(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![0, 0];
+    let mut matcher = Matcher::new();
+    let mut literals: Vec<u8> = Vec::new();
+    let mut position = 0;
+    let flush = |output: &mut Vec<u8>, literals: &mut Vec<u8>| {
+        for chunk in literals.chunks(0x3f) {
+            output.push(if chunk.len() >= 0x20 { 0xe0 | (chunk.len() - 0x20) as u8 } else { 0x80 | chunk.len() as u8 });
+            output.extend_from_slice(chunk);
+        }
+        literals.clear();
+    };
+    while position < data.len() {
+        let byte = data[position];
+        let fill = run_length(data, position, 0x21, |_| byte);
+        // the ring still holds the bytes up to a window back, minus one so the copy can't overwrite its source
+        let (length, source) = matcher.longest(data, position, 0x21, |source| position - source < KONAMI_RING - 0x21);
+        if fill >= 3 && fill >= length {
+            flush(&mut output, &mut literals);
+            if byte == 0 {
+                output.push(0xc0 | (fill - 2) as u8);
+            } else {
+                output.extend_from_slice(&[0xa0 | (fill - 2) as u8, byte]);
+            }
+            (position..position + fill).for_each(|inserted| matcher.insert(data, inserted));
+            position += fill;
+        } else if length >= 3 {
+            flush(&mut output, &mut literals);
+            let ring = source % KONAMI_RING;
+            output.extend_from_slice(&[((length - 2) << 2 | ring >> 8) as u8, ring as u8]);
+            (position..position + length).for_each(|inserted| matcher.insert(data, inserted));
+            position += length;
+        } else {
+            literals.push(byte);
+            matcher.insert(data, position);
+            position += 1;
+        }
+    }
+    flush(&mut output, &mut literals);
+    let size = output.len();
+    output[0] = size as u8;
+    output[1] = (size >> 8) as u8;
+    output
+}
+
+fn compress_square(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![0, 0];
+    let mut matcher = Matcher::new();
+    let mut position = 0;
+    while position < data.len() {
+        let flags_at = output.len();
+        output.push(0);
+        for bit in 0..8 {
+            if position >= data.len() {
+                break;
+            }
+            let (length, source) = matcher.longest(data, position, 34, |source| position - source < SQUARE_RING - 34);
+            if length >= 3 {
+                let ring = (source + SQUARE_START) % SQUARE_RING;
+                output.extend_from_slice(&[ring as u8, ((length - 3) << 3 | ring >> 8) as u8]);
+                (position..position + length).for_each(|inserted| matcher.insert(data, inserted));
+                position += length;
+            } else {
+                output[flags_at] |= 1 << bit;
+                output.push(data[position]);
+                matcher.insert(data, position);
+                position += 1;
+            }
+        }
+    }
+    let size = output.len();
+    output[0] = size as u8;
+    output[1] = (size >> 8) as u8;
+    output
+}
+
+fn compress_rle(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut literals = 0;
+    let mut position = 0;
+    while position < data.len() {
+        let byte = data[position];
+        let run = run_length(data, position, 0x80, |_| byte);
+        if run >= 3 || literals == 0x7f {
+            if literals > 0 {
+                output.push(literals as u8);
+                output.extend_from_slice(&data[position - literals..position]);
+                literals = 0;
+            }
+            if run >= 3 {
+                output.extend_from_slice(&[0x80 | (run - 1) as u8, byte]);
+                position += run;
+                continue;
+            }
+        }
+        literals += 1;
+        position += 1;
+    }
+    if literals > 0 {
+        output.push(literals as u8);
+        output.extend_from_slice(&data[position - literals..position]);
+    }
+    output.push(0);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift, nothing for the matchers to find
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state = 0x2545f491u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn samples() -> Vec<Vec<u8>> {
+        let text = b"the quick brown fox jumps over the lazy dog. ".repeat(40);
+        let noise = noise(0x3000);
+        let mut tiles = Vec::new();
+        for index in 0..0x400usize {
+            tiles.extend_from_slice(&[0, 0, 0xff, index as u8, 0x55, 0xaa, 0x55, 0xaa]);
+        }
+        let mut mixed = vec![0; 0x300];
+        mixed.extend((0..0x200).map(|index| index as u8));
+        mixed.extend(vec![0x7e; 0x500]);
+        mixed.extend_from_slice(&noise[..0x800]);
+        mixed.extend_from_slice(&noise[..0x800]);
+        vec![Vec::new(), vec![0x42], text, noise, tiles, mixed, vec![0x11; MAX_OUTPUT]]
+    }
+
+    #[test]
+    fn round_trips() {
+        for codec in &CODECS {
+            for data in samples() {
+                let compressed = compress(*codec, &data).unwrap();
+                let result = decompress(*codec, &compressed, 0).unwrap_or_else(|e| panic!("{:?}, {:#x} bytes: {}", codec, data.len(), e));
+                assert_eq!(result.data, data, "{:?}", codec);
+                assert_eq!(result.consumed, compressed.len(), "{:?}", codec);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_what_decompress_would() {
+        for codec in &CODECS {
+            assert!(compress(*codec, &vec![0; MAX_OUTPUT + 1]).is_err());
+        }
+        // incompressible data grows past what the size word holds
+        let noise = noise(MAX_OUTPUT);
+        assert!(compress(Codec::Konami, &noise).is_err());
+        assert!(compress(Codec::Square, &noise).is_err());
+        assert_eq!(decompress(Codec::Lz2, &compress(Codec::Lz2, &noise).unwrap(), 0).unwrap().data, noise);
+    }
+
+    #[test]
+    fn decodes_known_commands() {
+        // lz1: 3 literals, a 4 byte fill, an increasing fill and a repeat of the first 3 bytes
+        let lz1 = [0x02, 1, 2, 3, 0x23, 9, 0x62, 5, 0x82, 0, 0, 0xff];
+        assert_eq!(decompress(Codec::Lz1, &lz1, 0).unwrap().data, vec![1, 2, 3, 9, 9, 9, 9, 5, 6, 7, 1, 2, 3]);
+        // rle: 2 literals and a run of 5
+        let rle = [0x02, 7, 8, 0x84, 0xee, 0x00];
+        assert_eq!(decompress(Codec::Rle, &rle, 0).unwrap().data, vec![7, 8, 0xee, 0xee, 0xee, 0xee, 0xee]);
+    }
+}
@@ -0,0 +1,94 @@
+use snesutilities::{RomType, SnesUtils};
+
+pub const COPIER_HEADER_SIZE: usize = 512;
+
+// copier dumps (smc/swc/fig) are prefixed by a 512 byte header, so the size is off by 512 of a 1 KiB multiple
+pub fn has_copier_header(data: &[u8]) -> bool {
+    data.len() % 1024 == COPIER_HEADER_SIZE
+}
+
+pub fn split_copier_header(data: &[u8]) -> (Option<&[u8]>, &[u8]) {
+    if has_copier_header(data) {
+        let (header, body) = data.split_at(COPIER_HEADER_SIZE);
+        (Some(header), body)
+    } else {
+        (None, data)
+    }
+}
+
+pub fn strip_copier_header(data: &[u8]) -> &[u8] {
+    split_copier_header(data).1
+}
+
+// swc and fig both keep the "more parts follow" flag in bit 6 of byte 2 and the size in 8 KiB units in bytes 0-1
+pub fn is_split(header: &[u8]) -> bool {
+    header[2] & 0x40 != 0
+}
+
+pub fn set_part(header: &mut [u8], size: usize, more_parts: bool) {
+    let units = size / 8192;
+    header[0] = units as u8;
+    header[1] = (units >> 8) as u8;
+    if more_parts {
+        header[2] |= 0x40;
+    } else {
+        header[2] &= !0x40;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CopierFormat {
+    Swc, // super wild card, also what .smc files use
+    Fig, // pro fighter
+}
+
+pub fn detect_copier_format(header: &[u8]) -> Option<CopierFormat> {
+    if header[8..11] == [0xaa, 0xbb, 0x04] {
+        return Some(CopierFormat::Swc);
+    }
+    match (header[3], header[4], header[5]) {
+        (0x00, 0x77, 0x83) | (0x80, 0x77, 0x83) | (0x00, 0x00, 0x80) | (0x80, 0xdd, 0x82) | (0x00, 0x47, 0x83) | (0x80, 0xf7, 0xfd) => Some(CopierFormat::Fig),
+        _ => None,
+    }
+}
+
+// a header describing an image of size bytes (without the header itself)
+pub fn build_header(su: &SnesUtils, format: CopierFormat, size: usize) -> Vec<u8> {
+    let mut header = vec![0; COPIER_HEADER_SIZE];
+    let hirom = su.rom_makeup_type.is_hirom();
+    match format {
+        CopierFormat::Swc => {
+            let mut emulation = if hirom { 0x30 } else { 0x00 }; // hirom program and sram mapping
+            emulation |= match su.sram_size {
+                0 => 0x0c,      // no sram
+                1 => 0x08,      // 16 kbit
+                2 | 3 => 0x04,  // 64 kbit
+                _ => 0x00,      // 256 kbit
+            };
+            header[2] = emulation;
+            header[8..11].copy_from_slice(&[0xaa, 0xbb, 0x04]);
+        }
+        CopierFormat::Fig => {
+            let dsp = matches!(su.rom_type, RomType::ROMDSP1 | RomType::ROMDSP1RAM | RomType::ROMDSP1SRAM);
+            header[3] = if hirom { 0x80 } else { 0x00 };
+            let emulation = match (dsp, su.sram_size != 0, hirom) {
+                (true, _, false) => [0x47, 0x83],
+                (true, _, true) => [0xf7, 0xfd],
+                (false, true, false) => [0x00, 0x80],
+                (false, true, true) => [0xdd, 0x82],
+                (false, false, _) => [0x77, 0x83],
+            };
+            header[4..6].copy_from_slice(&emulation);
+        }
+    }
+    set_part(&mut header, size, false);
+    header
+}
+
+// replaces any existing copier header by a freshly generated one
+pub fn add_copier_header(data: &[u8], format: CopierFormat) -> Vec<u8> {
+    let body = strip_copier_header(data);
+    let mut image = build_header(&SnesUtils::from_bytes(body), format, body.len());
+    image.extend_from_slice(body);
+    image
+}
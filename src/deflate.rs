@@ -0,0 +1,378 @@
+// deflate with the fixed huffman codes and a hash chained lz77 search, wrapped in zlib for png
+
+use crc32;
+
+const WINDOW: usize = 0x8000;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_SIZE: usize = 0x8000;
+
+pub const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+pub const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+pub const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+pub const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.bit |= value << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.bytes.push(self.bit as u8);
+            self.bit >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // huffman codes go out most significant bit first
+    fn write_code(&mut self, code: u32, bits: u32) {
+        let reversed = (0..bits).fold(0, |acc, bit| acc << 1 | (code >> bit) & 1);
+        self.write(reversed, bits);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.bit as u8);
+        }
+        self.bytes
+    }
+}
+
+fn write_symbol(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap();
+    write_symbol(writer, 257 + code as u32);
+    writer.write((length - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code] as u32);
+    let code = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap();
+    writer.write_code(code as u32, 5);
+    writer.write((distance - DISTANCE_BASE[code] as usize) as u32, DISTANCE_EXTRA[code] as u32);
+}
+
+fn hash(data: &[u8], position: usize) -> usize {
+    ((data[position] as usize) << 10 ^ (data[position + 1] as usize) << 5 ^ data[position + 2] as usize) & (HASH_SIZE - 1)
+}
+
+// raw deflate stream, a single fixed huffman block
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { bytes: Vec::new(), bit: 0, count: 0 };
+    writer.write(1, 1); // final block
+    writer.write(1, 2); // fixed codes
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut previous = vec![usize::MAX; data.len()];
+    let mut position = 0;
+    while position < data.len() {
+        let mut best = (0, 0);
+        if position + 3 <= data.len() {
+            let mut candidate = head[hash(data, position)];
+            let mut chain = 0;
+            while candidate != usize::MAX && position - candidate <= WINDOW && chain < MAX_CHAIN {
+                let limit = MAX_MATCH.min(data.len() - position);
+                let length = (0..limit).take_while(|i| data[candidate + i] == data[position + i]).count();
+                if length > best.0 {
+                    best = (length, position - candidate);
+                }
+                candidate = previous[candidate];
+                chain += 1;
+            }
+        }
+        let step = if best.0 >= 3 {
+            write_match(&mut writer, best.0, best.1);
+            best.0
+        } else {
+            write_symbol(&mut writer, data[position] as u32);
+            1
+        };
+        for (inserted, link) in previous.iter_mut().enumerate().skip(position).take(step) {
+            if inserted + 3 <= data.len() {
+                let key = hash(data, inserted);
+                *link = head[key];
+                head[key] = inserted;
+            }
+        }
+        position += step;
+    }
+    write_symbol(&mut writer, 256);
+    writer.finish()
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    stream.extend(deflate(data));
+    let adler = adler32(data);
+    stream.extend_from_slice(&[(adler >> 24) as u8, (adler >> 16) as u8, (adler >> 8) as u8, adler as u8]);
+    stream
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn read(&mut self, bits: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for index in 0..bits {
+            let byte = *self.data.get(self.position).ok_or("deflate stream ends early")?;
+            value |= ((byte as u32 >> self.bit) & 1) << index;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.position += 1;
+            }
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+}
+
+// canonical huffman code as (count per length, symbols sorted by code)
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for length in 1..16 {
+            for (symbol, _) in lengths.iter().enumerate().filter(|&(_, l)| *l as usize == length) {
+                symbols.push(symbol as u16);
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= reader.read(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid huffman code in deflate stream".to_string())
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].iter_mut().for_each(|length| *length = 9);
+    lengths[256..280].iter_mut().for_each(|length| *length = 7);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    const ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+    let literals = reader.read(5)? as usize + 257;
+    let distances = reader.read(5)? as usize + 1;
+    let code_lengths = reader.read(4)? as usize + 4;
+    let mut lengths = [0u8; 19];
+    for index in ORDER.iter().take(code_lengths) {
+        lengths[*index] = reader.read(3)? as u8;
+    }
+    let code = Huffman::new(&lengths);
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or("deflate length repeat without a previous length")?, 3 + reader.read(2)?),
+            17 => (0, 3 + reader.read(3)?),
+            _ => (0, 11 + reader.read(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literals + distances {
+        return Err("deflate code lengths overrun".to_string());
+    }
+    Ok((Huffman::new(&lengths[..literals]), Huffman::new(&lengths[literals..])))
+}
+
+// raw deflate stream, returns the data and how many bytes of input it took
+pub fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), String> {
+    let mut reader = BitReader { data, position: 0, bit: 0 };
+    let mut output = Vec::new();
+    loop {
+        let last = reader.read(1)? == 1;
+        match reader.read(2)? {
+            0 => {
+                reader.align();
+                let header = data.get(reader.position..reader.position + 4).ok_or("deflate stream ends early")?;
+                let length = header[0] as usize | (header[1] as usize) << 8;
+                reader.position += 4;
+                output.extend_from_slice(data.get(reader.position..reader.position + length).ok_or("deflate stream ends early")?);
+                reader.position += length;
+            }
+            kind @ 1..=2 => {
+                let (literals, distances) = if kind == 1 { fixed_codes() } else { dynamic_codes(&mut reader)? };
+                loop {
+                    let symbol = literals.decode(&mut reader)? as usize;
+                    if symbol < 256 {
+                        output.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let code = symbol - 257;
+                    if code >= LENGTH_BASE.len() {
+                        return Err("invalid length code in deflate stream".to_string());
+                    }
+                    let length = LENGTH_BASE[code] as usize + reader.read(LENGTH_EXTRA[code] as u32)? as usize;
+                    let code = distances.decode(&mut reader)? as usize;
+                    if code >= DISTANCE_BASE.len() {
+                        return Err("invalid distance code in deflate stream".to_string());
+                    }
+                    let distance = DISTANCE_BASE[code] as usize + reader.read(DISTANCE_EXTRA[code] as u32)? as usize;
+                    if distance > output.len() {
+                        return Err("deflate distance reaches before the start".to_string());
+                    }
+                    let start = output.len() - distance;
+                    for index in 0..length {
+                        let byte = output[start + index];
+                        output.push(byte);
+                    }
+                }
+            }
+            _ => return Err("invalid deflate block type".to_string()),
+        }
+        if last {
+            reader.align();
+            return Ok((output, reader.position));
+        }
+    }
+}
+
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 6 || data[0] & 0x0f != 8 || !(data[0] as u16 * 256 + data[1] as u16).is_multiple_of(31) {
+        return Err("not a zlib stream".to_string());
+    }
+    let (output, used) = inflate(&data[2..])?;
+    let trailer = data.get(2 + used..2 + used + 4).ok_or("zlib stream misses its checksum")?;
+    let expected = (trailer[0] as u32) << 24 | (trailer[1] as u32) << 16 | (trailer[2] as u32) << 8 | trailer[3] as u32;
+    if expected != adler32(&output) {
+        return Err("zlib checksum mismatch".to_string());
+    }
+    Ok(output)
+}
+
+// gzip member: the header with its optional fields, deflate data, then crc32 and size
+pub fn gzip_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 18 || data[0] != 0x1f || data[1] != 0x8b || data[2] != 8 {
+        return Err("not a gzip stream".to_string());
+    }
+    let flags = data[3];
+    let mut position = 10;
+    if flags & 0x04 != 0 {
+        let extra = data.get(position..position + 2).ok_or("gzip header ends early")?;
+        position += 2 + (extra[0] as usize | (extra[1] as usize) << 8);
+    }
+    for flag in &[0x08, 0x10] {
+        if flags & flag != 0 {
+            position += data.get(position..).and_then(|rest| rest.iter().position(|byte| *byte == 0)).ok_or("gzip header ends early")? + 1;
+        }
+    }
+    if flags & 0x02 != 0 {
+        position += 2;
+    }
+    let (output, used) = inflate(data.get(position..).ok_or("gzip header ends early")?)?;
+    let trailer = data.get(position + used..position + used + 8).ok_or("gzip stream misses its trailer")?;
+    let expected = trailer[0] as u32 | (trailer[1] as u32) << 8 | (trailer[2] as u32) << 16 | (trailer[3] as u32) << 24;
+    if expected != crc32::crc32(&output) {
+        return Err("gzip checksum mismatch".to_string());
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) {
+        let compressed = deflate(data);
+        assert_eq!(inflate(&compressed).unwrap(), (data.to_vec(), compressed.len()));
+        assert_eq!(zlib_decompress(&zlib_compress(data)).unwrap(), data);
+    }
+
+    #[test]
+    fn deflate_round_trip() {
+        round_trip(b"");
+        round_trip(b"a");
+        round_trip(&b"snesutils ".repeat(100));
+        // matches longer than 258 bytes and distances near the end of the window
+        let noise: Vec<u8> = (0..0x9000u32).map(|index| (index.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let mut data = noise.clone();
+        data.extend_from_slice(&noise[..0x1000]);
+        data.extend(vec![0; 1000]);
+        round_trip(&data);
+    }
+
+    #[test]
+    fn adler32_vector() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+    #[test]
+    fn inflates_zlib_streams() {
+        // fixed codes, as zlib writes short repetitive data
+        let fixed = [0x78, 0xda, 0x4b, 0x4c, 0x84, 0x81, 0x24, 0x2c, 0x20, 0x99, 0x48, 0x90, 0x38, 0x88, 0x4c, 0x01, 0x00, 0x3e, 0x82, 0x50, 0xbf];
+        let expected = [vec![b'a'; 10], vec![b'b'; 20], vec![b'c'; 40]].concat().repeat(3);
+        assert_eq!(zlib_decompress(&fixed).unwrap(), expected);
+        let mut broken = fixed.to_vec();
+        broken[20] ^= 1;
+        assert!(zlib_decompress(&broken).is_err());
+    }
+
+    #[test]
+    fn inflates_dynamic_and_stored_blocks() {
+        let dynamic = [
+            0x2d, 0x88, 0x81, 0x09, 0x00, 0x00, 0x08, 0x83, 0x6e, 0x75, 0xf6, 0xff, 0x0d, 0x0d, 0x4a, 0x04, 0x41, 0x0c, 0xd4, 0x32, 0x8d,
+            0x24, 0x72, 0x18, 0x33, 0xfc, 0x58,
+        ];
+        assert_eq!(inflate(&dynamic).unwrap(), (b"acbaabaaaaadbaacabbcaaaaaaacbcbdaabbcaaa".to_vec(), dynamic.len()));
+        let stored = [0x01, 0x03, 0x00, 0xfc, 0xff, b's', b'f', b'c', 0xaa];
+        assert_eq!(inflate(&stored).unwrap(), (b"sfc".to_vec(), 8));
+        assert!(inflate(&stored[..6]).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use mapper::Mapper;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Implied,
+    Accumulator,
+    ImmediateM, // size follows the m flag
+    ImmediateX, // size follows the x flag
+    Immediate8,
+    Direct,
+    DirectX,
+    DirectY,
+    DirectIndirect,
+    DirectIndirectX,
+    DirectIndirectY,
+    DirectIndirectLong,
+    DirectIndirectLongY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Long,
+    LongX,
+    AbsoluteIndirect,
+    AbsoluteIndirectX,
+    AbsoluteIndirectLong,
+    Stack,
+    StackIndirectY,
+    Relative,
+    RelativeLong,
+    BlockMove,
+}
+
+use self::Mode::*;
+
+pub const OPCODES: [(&str, Mode); 256] = [
+    ("brk", Immediate8), ("ora", DirectIndirectX), ("cop", Immediate8), ("ora", Stack),
+    ("tsb", Direct), ("ora", Direct), ("asl", Direct), ("ora", DirectIndirectLong),
+    ("php", Implied), ("ora", ImmediateM), ("asl", Accumulator), ("phd", Implied),
+    ("tsb", Absolute), ("ora", Absolute), ("asl", Absolute), ("ora", Long),
+    ("bpl", Relative), ("ora", DirectIndirectY), ("ora", DirectIndirect), ("ora", StackIndirectY),
+    ("trb", Direct), ("ora", DirectX), ("asl", DirectX), ("ora", DirectIndirectLongY),
+    ("clc", Implied), ("ora", AbsoluteY), ("inc", Accumulator), ("tcs", Implied),
+    ("trb", Absolute), ("ora", AbsoluteX), ("asl", AbsoluteX), ("ora", LongX),
+    ("jsr", Absolute), ("and", DirectIndirectX), ("jsl", Long), ("and", Stack),
+    ("bit", Direct), ("and", Direct), ("rol", Direct), ("and", DirectIndirectLong),
+    ("plp", Implied), ("and", ImmediateM), ("rol", Accumulator), ("pld", Implied),
+    ("bit", Absolute), ("and", Absolute), ("rol", Absolute), ("and", Long),
+    ("bmi", Relative), ("and", DirectIndirectY), ("and", DirectIndirect), ("and", StackIndirectY),
+    ("bit", DirectX), ("and", DirectX), ("rol", DirectX), ("and", DirectIndirectLongY),
+    ("sec", Implied), ("and", AbsoluteY), ("dec", Accumulator), ("tsc", Implied),
+    ("bit", AbsoluteX), ("and", AbsoluteX), ("rol", AbsoluteX), ("and", LongX),
+    ("rti", Implied), ("eor", DirectIndirectX), ("wdm", Immediate8), ("eor", Stack),
+    ("mvp", BlockMove), ("eor", Direct), ("lsr", Direct), ("eor", DirectIndirectLong),
+    ("pha", Implied), ("eor", ImmediateM), ("lsr", Accumulator), ("phk", Implied),
+    ("jmp", Absolute), ("eor", Absolute), ("lsr", Absolute), ("eor", Long),
+    ("bvc", Relative), ("eor", DirectIndirectY), ("eor", DirectIndirect), ("eor", StackIndirectY),
+    ("mvn", BlockMove), ("eor", DirectX), ("lsr", DirectX), ("eor", DirectIndirectLongY),
+    ("cli", Implied), ("eor", AbsoluteY), ("phy", Implied), ("tcd", Implied),
+    ("jml", Long), ("eor", AbsoluteX), ("lsr", AbsoluteX), ("eor", LongX),
+    ("rts", Implied), ("adc", DirectIndirectX), ("per", RelativeLong), ("adc", Stack),
+    ("stz", Direct), ("adc", Direct), ("ror", Direct), ("adc", DirectIndirectLong),
+    ("pla", Implied), ("adc", ImmediateM), ("ror", Accumulator), ("rtl", Implied),
+    ("jmp", AbsoluteIndirect), ("adc", Absolute), ("ror", Absolute), ("adc", Long),
+    ("bvs", Relative), ("adc", DirectIndirectY), ("adc", DirectIndirect), ("adc", StackIndirectY),
+    ("stz", DirectX), ("adc", DirectX), ("ror", DirectX), ("adc", DirectIndirectLongY),
+    ("sei", Implied), ("adc", AbsoluteY), ("ply", Implied), ("tdc", Implied),
+    ("jmp", AbsoluteIndirectX), ("adc", AbsoluteX), ("ror", AbsoluteX), ("adc", LongX),
+    ("bra", Relative), ("sta", DirectIndirectX), ("brl", RelativeLong), ("sta", Stack),
+    ("sty", Direct), ("sta", Direct), ("stx", Direct), ("sta", DirectIndirectLong),
+    ("dey", Implied), ("bit", ImmediateM), ("txa", Implied), ("phb", Implied),
+    ("sty", Absolute), ("sta", Absolute), ("stx", Absolute), ("sta", Long),
+    ("bcc", Relative), ("sta", DirectIndirectY), ("sta", DirectIndirect), ("sta", StackIndirectY),
+    ("sty", DirectX), ("sta", DirectX), ("stx", DirectY), ("sta", DirectIndirectLongY),
+    ("tya", Implied), ("sta", AbsoluteY), ("txs", Implied), ("txy", Implied),
+    ("stz", Absolute), ("sta", AbsoluteX), ("stz", AbsoluteX), ("sta", LongX),
+    ("ldy", ImmediateX), ("lda", DirectIndirectX), ("ldx", ImmediateX), ("lda", Stack),
+    ("ldy", Direct), ("lda", Direct), ("ldx", Direct), ("lda", DirectIndirectLong),
+    ("tay", Implied), ("lda", ImmediateM), ("tax", Implied), ("plb", Implied),
+    ("ldy", Absolute), ("lda", Absolute), ("ldx", Absolute), ("lda", Long),
+    ("bcs", Relative), ("lda", DirectIndirectY), ("lda", DirectIndirect), ("lda", StackIndirectY),
+    ("ldy", DirectX), ("lda", DirectX), ("ldx", DirectY), ("lda", DirectIndirectLongY),
+    ("clv", Implied), ("lda", AbsoluteY), ("tsx", Implied), ("tyx", Implied),
+    ("ldy", AbsoluteX), ("lda", AbsoluteX), ("ldx", AbsoluteY), ("lda", LongX),
+    ("cpy", ImmediateX), ("cmp", DirectIndirectX), ("rep", Immediate8), ("cmp", Stack),
+    ("cpy", Direct), ("cmp", Direct), ("dec", Direct), ("cmp", DirectIndirectLong),
+    ("iny", Implied), ("cmp", ImmediateM), ("dex", Implied), ("wai", Implied),
+    ("cpy", Absolute), ("cmp", Absolute), ("dec", Absolute), ("cmp", Long),
+    ("bne", Relative), ("cmp", DirectIndirectY), ("cmp", DirectIndirect), ("cmp", StackIndirectY),
+    ("pei", DirectIndirect), ("cmp", DirectX), ("dec", DirectX), ("cmp", DirectIndirectLongY),
+    ("cld", Implied), ("cmp", AbsoluteY), ("phx", Implied), ("stp", Implied),
+    ("jml", AbsoluteIndirectLong), ("cmp", AbsoluteX), ("dec", AbsoluteX), ("cmp", LongX),
+    ("cpx", ImmediateX), ("sbc", DirectIndirectX), ("sep", Immediate8), ("sbc", Stack),
+    ("cpx", Direct), ("sbc", Direct), ("inc", Direct), ("sbc", DirectIndirectLong),
+    ("inx", Implied), ("sbc", ImmediateM), ("nop", Implied), ("xba", Implied),
+    ("cpx", Absolute), ("sbc", Absolute), ("inc", Absolute), ("sbc", Long),
+    ("beq", Relative), ("sbc", DirectIndirectY), ("sbc", DirectIndirect), ("sbc", StackIndirectY),
+    ("pea", Absolute), ("sbc", DirectX), ("inc", DirectX), ("sbc", DirectIndirectLongY),
+    ("sed", Implied), ("sbc", AbsoluteY), ("plx", Implied), ("xce", Implied),
+    ("jsr", AbsoluteIndirectX), ("sbc", AbsoluteX), ("inc", AbsoluteX), ("sbc", LongX),
+];
+
+// register widths, true means 8 bit (the flag is set)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Flags {
+    pub m: bool,
+    pub x: bool,
+}
+
+impl Default for Flags {
+    // what the cpu comes out of reset with (emulation mode)
+    fn default() -> Flags {
+        Flags { m: true, x: true }
+    }
+}
+
+impl Flags {
+    // the widths after executing the instruction, only rep and sep are followed
+    pub fn after(&self, instruction: &Instruction) -> Flags {
+        let mut flags = *self;
+        match instruction.opcode {
+            0xc2 => {
+                flags.m &= instruction.operand & 0x20 == 0;
+                flags.x &= instruction.operand & 0x10 == 0;
+            }
+            0xe2 => {
+                flags.m |= instruction.operand & 0x20 != 0;
+                flags.x |= instruction.operand & 0x10 != 0;
+            }
+            _ => {}
+        }
+        flags
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub address: u32,
+    pub offset: usize,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub mode: Mode,
+    pub operand: u32,
+    pub bytes: Vec<u8>,
+}
+
+pub fn operand_size(mode: Mode, flags: Flags) -> usize {
+    match mode {
+        Implied | Accumulator => 0,
+        ImmediateM => if flags.m { 1 } else { 2 },
+        ImmediateX => if flags.x { 1 } else { 2 },
+        Immediate8 | Direct | DirectX | DirectY | DirectIndirect | DirectIndirectX | DirectIndirectY |
+        DirectIndirectLong | DirectIndirectLongY | Stack | StackIndirectY | Relative => 1,
+        Absolute | AbsoluteX | AbsoluteY | AbsoluteIndirect | AbsoluteIndirectX | AbsoluteIndirectLong |
+        RelativeLong | BlockMove => 2,
+        Long | LongX => 3,
+    }
+}
+
+pub fn decode(data: &[u8], mapper: &Mapper, address: u32, flags: Flags) -> Option<Instruction> {
+    let offset = mapper.to_offset(address)?;
+    let opcode = *data.get(offset)?;
+    let (mnemonic, mode) = OPCODES[opcode as usize];
+    let bytes = data.get(offset..offset + 1 + operand_size(mode, flags))?.to_vec();
+    let operand = bytes[1..].iter().rev().fold(0u32, |acc, byte| (acc << 8) | *byte as u32);
+    Some(Instruction {
+        address,
+        offset,
+        opcode,
+        mnemonic,
+        mode,
+        operand,
+        bytes,
+    })
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    // address of the next instruction, the program counter wraps inside the bank
+    pub fn next(&self) -> u32 {
+        (self.address & 0xff0000) | ((self.address + self.len() as u32) & 0xffff)
+    }
+
+    // destination of branches and direct jumps/calls
+    pub fn target(&self) -> Option<u32> {
+        let bank = self.address & 0xff0000;
+        match (self.mode, self.mnemonic) {
+            (Relative, _) | (RelativeLong, "brl") => Some(self.relative_target()),
+            (Absolute, "jmp") | (Absolute, "jsr") => Some(bank | self.operand),
+            (Long, "jml") | (Long, "jsl") => Some(self.operand),
+            _ => None,
+        }
+    }
+
+    fn relative_target(&self) -> u32 {
+        let displacement = if self.mode == Relative { self.operand as u8 as i8 as i32 } else { self.operand as u16 as i16 as i32 };
+        (self.address & 0xff0000) | (self.next() as i32 + displacement) as u32 & 0xffff
+    }
+
+    pub fn operand_text(&self) -> String {
+        let value = match self.len() - 1 {
+            1 => format!("${:02x}", self.operand),
+            2 => format!("${:04x}", self.operand),
+            _ => format!("${:06x}", self.operand),
+        };
+        match self.mode {
+            Implied => String::new(),
+            Accumulator => "a".to_string(),
+            ImmediateM | ImmediateX | Immediate8 => format!("#{}", value),
+            Direct | Absolute | Long => value,
+            DirectX | AbsoluteX | LongX => format!("{},x", value),
+            DirectY | AbsoluteY => format!("{},y", value),
+            DirectIndirect | AbsoluteIndirect => format!("({})", value),
+            DirectIndirectX | AbsoluteIndirectX => format!("({},x)", value),
+            DirectIndirectY => format!("({}),y", value),
+            DirectIndirectLong | AbsoluteIndirectLong => format!("[{}]", value),
+            DirectIndirectLongY => format!("[{}],y", value),
+            Stack => format!("{},s", value),
+            StackIndirectY => format!("({},s),y", value),
+            Relative | RelativeLong => format!("${:06x}", self.relative_target()),
+            // assembled as destination, source but written source, destination
+            BlockMove => format!("${:02x},${:02x}", self.operand >> 8, self.operand & 0xff),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text = format!("{} {}", self.mnemonic, self.operand_text());
+        write!(f, "{:02x}:{:04x}  {:<12} {}", self.address >> 16, self.address & 0xffff, bytes.join(" "), text.trim_end())
+    }
+}
+
+// linear disassembly of len bytes, following rep/sep for the register widths
+pub fn disassemble(data: &[u8], mapper: &Mapper, address: u32, len: usize, flags: Flags) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut address = address;
+    let mut flags = flags;
+    let mut done = 0;
+    while done < len {
+        let instruction = match decode(data, mapper, address, flags) {
+            Some(instruction) => instruction,
+            None => break,
+        };
+        flags = flags.after(&instruction);
+        address = instruction.next();
+        done += instruction.len();
+        instructions.push(instruction);
+    }
+    instructions
+}
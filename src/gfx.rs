@@ -0,0 +1,302 @@
+use checksum;
+use palette;
+use snesutilities;
+
+// 8x8 tiles as the ppu stores them: 2bpp is two interleaved bitplanes per row, 4bpp and 8bpp
+// append further 2bpp plane pairs; mode 7 is one byte per pixel, nes 2bpp keeps the planes apart
+
+pub const TILE_PIXELS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TileFormat {
+    Bpp1,
+    Bpp2,
+    Bpp4,
+    Bpp8,
+    Mode7,
+    Nes2bpp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+impl TileFormat {
+    pub fn from_name(name: &str) -> Option<TileFormat> {
+        match name {
+            "1bpp" => Some(TileFormat::Bpp1),
+            "2bpp" => Some(TileFormat::Bpp2),
+            "4bpp" => Some(TileFormat::Bpp4),
+            "8bpp" => Some(TileFormat::Bpp8),
+            "mode7" => Some(TileFormat::Mode7),
+            "nes" => Some(TileFormat::Nes2bpp),
+            _ => None,
+        }
+    }
+
+    pub fn bits(&self) -> usize {
+        match *self {
+            TileFormat::Bpp1 => 1,
+            TileFormat::Bpp2 | TileFormat::Nes2bpp => 2,
+            TileFormat::Bpp4 => 4,
+            TileFormat::Bpp8 | TileFormat::Mode7 => 8,
+        }
+    }
+
+    pub fn colors(&self) -> usize {
+        1 << self.bits()
+    }
+
+    pub fn tile_size(&self) -> usize {
+        self.bits() * 8
+    }
+}
+
+pub fn decode_tile(tile: &[u8], format: TileFormat) -> [u8; TILE_PIXELS] {
+    let mut pixels = [0u8; TILE_PIXELS];
+    for (index, pixel) in pixels.iter_mut().enumerate() {
+        let (row, column) = (index / 8, index % 8);
+        let bit = |byte: u8| (byte >> (7 - column)) & 1;
+        *pixel = match format {
+            TileFormat::Mode7 => tile[index],
+            TileFormat::Bpp1 => bit(tile[row]),
+            TileFormat::Nes2bpp => bit(tile[row]) | bit(tile[row + 8]) << 1,
+            _ => (0..format.bits()).fold(0, |value, plane| value | bit(tile[(plane / 2) * 16 + row * 2 + plane % 2]) << plane),
+        };
+    }
+    pixels
+}
+
+// every whole tile in data
+pub fn decode_tiles(data: &[u8], format: TileFormat) -> Vec<[u8; TILE_PIXELS]> {
+    data.chunks(format.tile_size())
+        .filter(|tile| tile.len() == format.tile_size())
+        .map(|tile| decode_tile(tile, format))
+        .collect()
+}
+
+// evenly spaced grays from black to white, for when the palette isn't known
+pub fn grayscale(colors: usize) -> Vec<[u8; 4]> {
+    (0..colors)
+        .map(|index| {
+            let value = (index * 255 / (colors - 1).max(1)) as u8;
+            [value, value, value, 0xff]
+        })
+        .collect()
+}
+
+// tiles laid out left to right, width tiles per row; indices past the palette come out black
+pub fn render(tiles: &[[u8; TILE_PIXELS]], width: usize, palette: &[[u8; 4]]) -> Image {
+    let width = width.max(1);
+    let rows = tiles.len().div_ceil(width).max(1);
+    let mut image = Image {
+        width: width * 8,
+        height: rows * 8,
+        rgba: vec![0; width * 8 * rows * 8 * 4],
+    };
+    for (number, tile) in tiles.iter().enumerate() {
+        let (x, y) = ((number % width) * 8, (number / width) * 8);
+        for (index, pixel) in tile.iter().enumerate() {
+            let color = palette.get(*pixel as usize).cloned().unwrap_or([0, 0, 0, 0xff]);
+            let position = ((y + index / 8) * image.width + x + index % 8) * 4;
+            image.rgba[position..position + 4].copy_from_slice(&color);
+        }
+    }
+    image
+}
+
+pub fn encode_tile(pixels: &[u8; TILE_PIXELS], format: TileFormat) -> Vec<u8> {
+    let mut tile = vec![0u8; format.tile_size()];
+    for (index, pixel) in pixels.iter().enumerate() {
+        let (row, column) = (index / 8, index % 8);
+        let bit = |value: u8| (value & 1) << (7 - column);
+        match format {
+            TileFormat::Mode7 => tile[index] = *pixel,
+            TileFormat::Bpp1 => tile[row] |= bit(*pixel),
+            TileFormat::Nes2bpp => {
+                tile[row] |= bit(*pixel);
+                tile[row + 8] |= bit(*pixel >> 1);
+            }
+            _ => {
+                for plane in 0..format.bits() {
+                    tile[(plane / 2) * 16 + row * 2 + plane % 2] |= bit(*pixel >> plane);
+                }
+            }
+        }
+    }
+    tile
+}
+
+// cuts an image of palette indices into tiles, row by row
+pub fn slice_tiles(indices: &[u8], width: usize, height: usize) -> Result<Vec<[u8; TILE_PIXELS]>, String> {
+    if !width.is_multiple_of(8) || !height.is_multiple_of(8) {
+        return Err(format!("{}x{} isn't a multiple of 8x8 tiles", width, height));
+    }
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(8) {
+        for x in (0..width).step_by(8) {
+            let mut tile = [0u8; TILE_PIXELS];
+            for (index, pixel) in tile.iter_mut().enumerate() {
+                *pixel = indices[(y + index / 8) * width + x + index % 8];
+            }
+            tiles.push(tile);
+        }
+    }
+    Ok(tiles)
+}
+
+// palette index for every rgba pixel. colors have to be in the palette exactly (compared as bgr555)
+// unless quantizing, which takes the nearest one; transparent pixels become color 0
+pub fn match_palette(rgba: &[u8], colors: &[[u8; 4]], quantize: bool) -> Result<Vec<u8>, String> {
+    let bgr = |color: &[u8]| palette::from_rgb([color[0], color[1], color[2]]);
+    let targets: Vec<u16> = colors.iter().map(|color| bgr(color)).collect();
+    rgba.chunks(4)
+        .enumerate()
+        .map(|(index, pixel)| {
+            if pixel[3] == 0 {
+                return Ok(0);
+            }
+            if let Some(found) = targets.iter().position(|target| *target == bgr(pixel)) {
+                return Ok(found as u8);
+            }
+            if !quantize {
+                return Err(format!("pixel {} has color #{:02x}{:02x}{:02x}, which isn't in the palette", index, pixel[0], pixel[1], pixel[2]));
+            }
+            let distance = |other: &[u8; 4]| (0..3).map(|channel| (pixel[channel] as i32 - other[channel] as i32).pow(2)).sum::<i32>();
+            Ok((0..colors.len()).min_by_key(|entry| distance(&colors[*entry])).unwrap_or(0) as u8)
+        })
+        .collect()
+}
+
+fn flip(tile: &[u8; TILE_PIXELS], horizontal: bool, vertical: bool) -> [u8; TILE_PIXELS] {
+    let mut flipped = [0u8; TILE_PIXELS];
+    for (index, pixel) in flipped.iter_mut().enumerate() {
+        let row = if vertical { 7 - index / 8 } else { index / 8 };
+        let column = if horizontal { 7 - index % 8 } else { index % 8 };
+        *pixel = tile[row * 8 + column];
+    }
+    flipped
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tileset {
+    pub tiles: Vec<[u8; TILE_PIXELS]>,
+    pub tilemap: Vec<u16>, // bg tilemap entries: v flip 0x8000, h flip 0x4000 and the tile number
+}
+
+// keeps the first of identical tiles, flipped copies too unless the format can't flip (mode 7)
+pub fn dedupe(tiles: &[[u8; TILE_PIXELS]], flips: bool) -> Tileset {
+    let mut tileset = Tileset {
+        tiles: Vec::new(),
+        tilemap: Vec::new(),
+    };
+    let variants: &[(bool, bool, u16)] = if flips {
+        &[(false, false, 0), (true, false, 0x4000), (false, true, 0x8000), (true, true, 0xc000)]
+    } else {
+        &[(false, false, 0)]
+    };
+    for tile in tiles {
+        let found = variants.iter().find_map(|&(horizontal, vertical, bits)| {
+            let flipped = flip(tile, horizontal, vertical);
+            tileset.tiles.iter().position(|existing| *existing == flipped).map(|number| number as u16 | bits)
+        });
+        let entry = found.unwrap_or_else(|| {
+            tileset.tiles.push(*tile);
+            tileset.tiles.len() as u16 - 1
+        });
+        tileset.tilemap.push(entry);
+    }
+    tileset
+}
+
+// writes the encoded tiles into data at offset and fixes the checksum, which graphics dumps and
+// other images shorter than a bank don't have: those come back with None
+pub fn insert(data: &mut [u8], offset: usize, tiles: &[[u8; TILE_PIXELS]], format: TileFormat) -> Result<Option<(u16, u16)>, String> {
+    let encoded: Vec<u8> = tiles.iter().flat_map(|tile| encode_tile(tile, format)).collect();
+    if offset.checked_add(encoded.len()).is_none_or(|end| end > data.len()) {
+        return Err(format!("{:#x} bytes of tiles don't fit at {:#x}", encoded.len(), offset));
+    }
+    data[offset..offset + encoded.len()].copy_from_slice(&encoded);
+    if data.len() < 0x8000 {
+        return Ok(None);
+    }
+    let header_offset = snesutilities::find_header(data);
+    Ok(checksum::fix_checksum(data, header_offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(colors: usize) -> [u8; TILE_PIXELS] {
+        let mut tile = [0u8; TILE_PIXELS];
+        for (index, pixel) in tile.iter_mut().enumerate() {
+            *pixel = ((index * 37 + index / 8) % colors) as u8;
+        }
+        tile
+    }
+
+    #[test]
+    fn tiles_round_trip() {
+        for name in &["1bpp", "2bpp", "4bpp", "8bpp", "mode7", "nes"] {
+            let format = TileFormat::from_name(name).unwrap();
+            let tile = pattern(format.colors());
+            let encoded = encode_tile(&tile, format);
+            assert_eq!(encoded.len(), format.tile_size(), "{}", name);
+            assert_eq!(decode_tile(&encoded, format)[..], tile[..], "{}", name);
+        }
+    }
+
+    #[test]
+    fn planar_2bpp_tile() {
+        // row 0 counts 0-3 twice, row 7 is all color 3, the rest is color 0
+        let mut tile = [0u8; TILE_PIXELS];
+        tile[..8].copy_from_slice(&[0, 1, 2, 3, 0, 1, 2, 3]);
+        tile[56..].copy_from_slice(&[3; 8]);
+        let mut expected = vec![0u8; 16];
+        expected[..2].copy_from_slice(&[0x55, 0x33]);
+        expected[14..].copy_from_slice(&[0xff, 0xff]);
+        assert_eq!(encode_tile(&tile, TileFormat::Bpp2), expected);
+        assert_eq!(decode_tile(&expected, TileFormat::Bpp2)[..], tile[..]);
+
+        let mut nes = vec![0u8; 16];
+        nes[0] = 0x55;
+        nes[8] = 0x33;
+        nes[7] = 0xff;
+        nes[15] = 0xff;
+        assert_eq!(encode_tile(&tile, TileFormat::Nes2bpp), nes);
+    }
+
+    #[test]
+    fn flipped_duplicates() {
+        let tile = pattern(16);
+        let other = pattern(5);
+        let tiles = [tile, flip(&tile, true, false), flip(&tile, false, true), flip(&tile, true, true), other, tile];
+        let tileset = dedupe(&tiles, true);
+        assert_eq!(tileset.tiles.len(), 2);
+        assert_eq!(tileset.tiles[..], [tile, other][..]);
+        assert_eq!(tileset.tilemap, vec![0, 0x4000, 0x8000, 0xc000, 1, 0]);
+
+        let unflipped = dedupe(&tiles, false);
+        assert_eq!(unflipped.tiles.len(), 5);
+        assert_eq!(unflipped.tilemap, vec![0, 1, 2, 3, 4, 0]);
+    }
+
+    #[test]
+    fn insert_into_a_short_buffer() {
+        let tile = pattern(4);
+        let mut patched = [0xaa; 0x40];
+        assert_eq!(insert(&mut patched, 0x10, &[tile], TileFormat::Bpp2), Ok(None));
+        assert_eq!(&patched[0x10..0x20], &encode_tile(&tile, TileFormat::Bpp2)[..]);
+        assert_eq!(&patched[..0x10], &[0xaa; 0x10][..]);
+        assert_eq!(&patched[0x20..], &[0xaa; 0x20][..]);
+        assert!(insert(&mut [0; 0x18], 0x10, &[tile], TileFormat::Bpp2).is_err());
+        assert!(insert(&mut [0; 0x18], usize::MAX, &[tile], TileFormat::Bpp2).is_err());
+
+        let checksum = insert(&mut vec![0; 0x8000], 0, &[tile], TileFormat::Bpp2).unwrap();
+        assert!(checksum.is_some());
+    }
+}
@@ -0,0 +1,372 @@
+use mapper::{self, Mapper};
+use snesutilities::{RomType, SnesUtils};
+use std::collections::HashSet;
+use std::fmt;
+use trace;
+
+// the super fx (gsu) reads its program from the game pak rom: banks 00-3f like lorom, 40-5f as
+// whole 64 KiB banks. the 65816 starts it by writing the program bank to 3034 and r15 to 301e/301f,
+// the write to 301f is what sets it going
+
+const PBR: u32 = 0x3034;
+const R15_LOW: u32 = 0x301e;
+const R15_HIGH: u32 = 0x301f;
+const MEBIBYTE: usize = 0x100000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Revision {
+    Gsu1, // mario chip and gsu-1, up to 8 mbit of rom
+    Gsu2, // addresses up to 16 mbit
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GsuInfo {
+    pub revision: Revision,
+    pub ram_size: usize,
+}
+
+// the revision isn't stored anywhere, only gsu-2 boards take more than 1 MiB of rom. the work ram
+// size comes from the extended header, star fox predates it and has 32 KiB
+pub fn info(su: &SnesUtils, size: usize) -> Option<GsuInfo> {
+    if !matches!(su.rom_type, RomType::FX | RomType::GSU | RomType::GSURAM | RomType::GSUSRAM | RomType::GSUSRAMBattery) {
+        return None;
+    }
+    let ram_size = match su.extended_header.as_ref() {
+        Some(header) if header.expansion_ram_size > 0 => 1024 << (header.expansion_ram_size & 7),
+        _ => 32 * 1024,
+    };
+    Some(GsuInfo {
+        revision: if size > MEBIBYTE { Revision::Gsu2 } else { Revision::Gsu1 },
+        ram_size,
+    })
+}
+
+// rom as the gsu sees it, None for its ram and anything else
+pub fn to_offset(address: u32, size: usize) -> Option<usize> {
+    let bank = (address >> 16) as usize & 0xff;
+    let addr = address as usize & 0xffff;
+    let offset = match bank {
+        0x00..=0x3f => bank << 15 | (addr & 0x7fff),
+        0x40..=0x5f => (bank - 0x40) << 16 | addr,
+        _ => return None,
+    };
+    if size == 0 {
+        return None;
+    }
+    Some(mapper::mirror(offset, size))
+}
+
+// alt1/alt2 flags and the register of a with prefix, all of which the next instruction consumes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Prefixes {
+    pub alt: u8,
+    pub with: Option<u8>,
+}
+
+impl Prefixes {
+    // to and from only change the registers, everything that isn't a prefix clears them
+    pub fn after(&self, instruction: &Instruction) -> Prefixes {
+        match instruction.mnemonic {
+            "alt1" => Prefixes { alt: self.alt | 1, with: None },
+            "alt2" => Prefixes { alt: self.alt | 2, with: None },
+            "alt3" => Prefixes { alt: 3, with: None },
+            "with" => Prefixes { alt: self.alt, with: Some(instruction.bytes[0] & 0x0f) },
+            "to" | "from" => *self,
+            _ => Prefixes::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub address: u32,
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    pub operands: String,
+}
+
+// mnemonic and operand of an opcode under the prefixes
+fn mnemonic(opcode: u8, prefixes: Prefixes) -> (&'static str, Operand) {
+    let n = opcode & 0x0f;
+    let alt = prefixes.alt;
+    let pick = |names: [&'static str; 4]| names[alt as usize];
+    match opcode {
+        0x00 => ("stop", Operand::None),
+        0x01 => ("nop", Operand::None),
+        0x02 => ("cache", Operand::None),
+        0x03 => ("lsr", Operand::None),
+        0x04 => ("rol", Operand::None),
+        0x05..=0x0f => (["bra", "bge", "blt", "bne", "beq", "bpl", "bmi", "bcc", "bcs", "bvc", "bvs"][opcode as usize - 5], Operand::Branch),
+        0x10..=0x1f => match prefixes.with {
+            Some(source) => ("move", Operand::Registers(n, source)),
+            None => ("to", Operand::Register(n)),
+        },
+        0x20..=0x2f => ("with", Operand::Register(n)),
+        0x30..=0x3b => (if alt & 1 != 0 { "stb" } else { "stw" }, Operand::Indirect(n)),
+        0x3c => ("loop", Operand::None),
+        0x3d => ("alt1", Operand::None),
+        0x3e => ("alt2", Operand::None),
+        0x3f => ("alt3", Operand::None),
+        0x40..=0x4b => (if alt & 1 != 0 { "ldb" } else { "ldw" }, Operand::Indirect(n)),
+        0x4c => (if alt & 1 != 0 { "rpix" } else { "plot" }, Operand::None),
+        0x4d => ("swap", Operand::None),
+        0x4e => (if alt & 1 != 0 { "cmode" } else { "color" }, Operand::None),
+        0x4f => ("not", Operand::None),
+        0x50..=0x5f => (pick(["add", "adc", "add", "adc"]), register_or_immediate(n, alt)),
+        0x60..=0x6f => (pick(["sub", "sbc", "sub", "cmp"]), if alt == 2 { Operand::Immediate(n) } else { Operand::Register(n) }),
+        0x70 => ("merge", Operand::None),
+        0x71..=0x7f => (pick(["and", "bic", "and", "bic"]), register_or_immediate(n, alt)),
+        0x80..=0x8f => (pick(["mult", "umult", "mult", "umult"]), register_or_immediate(n, alt)),
+        0x90 => ("sbk", Operand::None),
+        0x91..=0x94 => ("link", Operand::Immediate(n)),
+        0x95 => ("sex", Operand::None),
+        0x96 => (if alt & 1 != 0 { "div2" } else { "asr" }, Operand::None),
+        0x97 => ("ror", Operand::None),
+        0x98..=0x9d => (if alt & 1 != 0 { "ljmp" } else { "jmp" }, Operand::Register(n)),
+        0x9e => ("lob", Operand::None),
+        0x9f => (if alt & 1 != 0 { "lmult" } else { "fmult" }, Operand::None),
+        0xa0..=0xaf => match alt {
+            1 => ("lms", Operand::ShortLoad(n)),
+            2 => ("sms", Operand::ShortStore(n)),
+            _ => ("ibt", Operand::Byte(n)),
+        },
+        0xb0..=0xbf => match prefixes.with {
+            Some(destination) => ("moves", Operand::Registers(destination, n)),
+            None => ("from", Operand::Register(n)),
+        },
+        0xc0 => ("hib", Operand::None),
+        0xc1..=0xcf => (pick(["or", "xor", "or", "xor"]), register_or_immediate(n, alt)),
+        0xd0..=0xde => ("inc", Operand::Register(n)),
+        0xdf => (pick(["getc", "getc", "ramb", "romb"]), Operand::None),
+        0xe0..=0xee => ("dec", Operand::Register(n)),
+        0xef => (pick(["getb", "getbh", "getbl", "getbs"]), Operand::None),
+        _ => match alt {
+            1 => ("lm", Operand::Load(n)),
+            2 => ("sm", Operand::Store(n)),
+            _ => ("iwt", Operand::Word(n)),
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    None,
+    Register(u8),
+    Registers(u8, u8),
+    Immediate(u8),
+    Indirect(u8),
+    Branch,
+    Byte(u8),       // ibt rn, #pp (sign extended)
+    ShortLoad(u8),  // lms rn, (yy * 2)
+    ShortStore(u8), // sms (yy * 2), rn
+    Word(u8),       // iwt rn, #xxxx
+    Load(u8),       // lm rn, (xxxx)
+    Store(u8),      // sm (xxxx), rn
+}
+
+fn register_or_immediate(n: u8, alt: u8) -> Operand {
+    if alt & 2 != 0 { Operand::Immediate(n) } else { Operand::Register(n) }
+}
+
+fn operand_size(operand: Operand) -> usize {
+    match operand {
+        Operand::Branch | Operand::Byte(_) | Operand::ShortLoad(_) | Operand::ShortStore(_) => 1,
+        Operand::Word(_) | Operand::Load(_) | Operand::Store(_) => 2,
+        _ => 0,
+    }
+}
+
+pub fn decode(data: &[u8], address: u32, prefixes: Prefixes) -> Option<Instruction> {
+    let offset = to_offset(address, data.len())?;
+    let opcode = *data.get(offset)?;
+    let (mnemonic, operand) = mnemonic(opcode, prefixes);
+    let bytes = data.get(offset..offset + 1 + operand_size(operand))?.to_vec();
+    let byte = bytes.get(1).cloned().unwrap_or(0);
+    let word = byte as u16 | (bytes.get(2).cloned().unwrap_or(0) as u16) << 8;
+    let next = (address & 0xff0000) | ((address + bytes.len() as u32) & 0xffff);
+    let operands = match operand {
+        Operand::None => String::new(),
+        Operand::Register(n) => format!("r{}", n),
+        Operand::Registers(destination, source) => format!("r{}, r{}", destination, source),
+        Operand::Immediate(n) => format!("#{}", n),
+        Operand::Indirect(n) => format!("(r{})", n),
+        Operand::Branch => format!("${:06x}", (next & 0xff0000) | (next as i32 + byte as i8 as i32) as u32 & 0xffff),
+        Operand::Byte(n) => format!("r{}, #${:02x}", n, byte),
+        Operand::ShortLoad(n) => format!("r{}, (${:03x})", n, byte as u16 * 2),
+        Operand::ShortStore(n) => format!("(${:03x}), r{}", byte as u16 * 2, n),
+        Operand::Word(n) => format!("r{}, #${:04x}", n, word),
+        Operand::Load(n) => format!("r{}, (${:04x})", n, word),
+        Operand::Store(n) => format!("(${:04x}), r{}", word, n),
+    };
+    Some(Instruction {
+        address,
+        offset,
+        bytes,
+        mnemonic,
+        operands,
+    })
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn next(&self) -> u32 {
+        (self.address & 0xff0000) | ((self.address + self.len() as u32) & 0xffff)
+    }
+
+    pub fn is_prefix(&self) -> bool {
+        matches!(self.mnemonic, "alt1" | "alt2" | "alt3" | "to" | "with" | "from")
+    }
+
+    // branches, and iwt r15 which is how code jumps to a fixed address
+    pub fn target(&self) -> Option<u32> {
+        let bank = self.address & 0xff0000;
+        match self.bytes[0] {
+            0x05..=0x0f => Some(bank | (self.next() as i32 + self.bytes[1] as i8 as i32) as u32 & 0xffff),
+            0xff if self.mnemonic == "iwt" => Some(bank | self.bytes[1] as u32 | (self.bytes[2] as u32) << 8),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let text = format!("{} {}", self.mnemonic, self.operands);
+        write!(f, "{:02x}:{:04x}  {:<9} {}", self.address >> 16, self.address & 0xffff, bytes.join(" "), text.trim_end())
+    }
+}
+
+// linear disassembly of len bytes, carrying the prefixes over to the instructions they change
+pub fn disassemble(data: &[u8], address: u32, len: usize) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut address = address;
+    let mut prefixes = Prefixes::default();
+    let mut done = 0;
+    while done < len {
+        let instruction = match decode(data, address, prefixes) {
+            Some(instruction) => instruction,
+            None => break,
+        };
+        prefixes = prefixes.after(&instruction);
+        address = instruction.next();
+        done += instruction.len();
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+// where the 65816 starts the gsu
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Start {
+    pub caller: u32,
+    pub entry: u32,
+}
+
+// 16 bit stores to 301e and byte stores to 301f start it, the bank is 0 until pbr is seen set
+pub fn find_starts(data: &[u8], mapper: &Mapper, su: &SnesUtils) -> Vec<Start> {
+    let cdl = trace::trace(data, mapper, &trace::vector_entries(&su.vectors));
+    let mut starts = Vec::new();
+    let (mut pbr, mut r15) = (0u32, 0u32);
+    for write in trace::register_writes(data, mapper, &cdl, &[PBR, R15_LOW, R15_HIGH]) {
+        match write.register {
+            PBR => pbr = write.value & 0xff,
+            R15_LOW if write.wide => {
+                r15 = write.value & 0xffff;
+                starts.push(Start { caller: write.address, entry: pbr << 16 | r15 });
+            }
+            R15_LOW => r15 = (r15 & 0xff00) | (write.value & 0xff),
+            R15_HIGH => {
+                r15 = (r15 & 0x00ff) | (write.value & 0xff) << 8;
+                starts.push(Start { caller: write.address, entry: pbr << 16 | r15 });
+            }
+            _ => {}
+        }
+    }
+    starts
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Routine {
+    pub entry: u32,
+    pub bytes: usize, // code reached from the entry, not counting subroutines
+    pub calls: Vec<u32>,
+}
+
+// follows branches and iwt r15 jumps from every entry. iwt r15 after link is a call, which becomes a
+// routine of its own. branches and jumps execute the instruction after them before they take effect
+pub fn trace(data: &[u8], entries: &[u32]) -> Vec<Routine> {
+    let mut routines: Vec<Routine> = Vec::new();
+    let mut pending: Vec<u32> = entries.to_vec();
+    pending.reverse();
+    let mut seen = HashSet::new();
+    while let Some(entry) = pending.pop() {
+        if !seen.insert(entry) || to_offset(entry, data.len()).is_none() {
+            continue;
+        }
+        let mut routine = Routine { entry, bytes: 0, calls: Vec::new() };
+        let mut visited = HashSet::new();
+        let mut paths = vec![(entry, Prefixes::default())];
+        while let Some((start, start_prefixes)) = paths.pop() {
+            let mut address = start;
+            let mut prefixes = start_prefixes;
+            let mut linked = None;
+            let mut delay_slot = false;
+            while visited.insert(address) {
+                let instruction = match decode(data, address, prefixes) {
+                    Some(instruction) => instruction,
+                    None => break,
+                };
+                routine.bytes += instruction.len();
+                let writes_r15 = instruction.mnemonic == "to" && instruction.bytes[0] == 0x1f;
+                let next = prefixes.after(&instruction);
+                if delay_slot && !instruction.is_prefix() {
+                    break;
+                }
+                match (instruction.mnemonic, instruction.target()) {
+                    ("link", _) => linked = Some(instruction.next().wrapping_add(instruction.bytes[0] as u32 & 0x0f)),
+                    ("iwt", Some(target)) => match linked.take() {
+                        Some(back) => {
+                            routine.calls.push(target);
+                            pending.push(target);
+                            paths.push(((instruction.address & 0xff0000) | (back & 0xffff), Prefixes::default()));
+                            delay_slot = true;
+                        }
+                        None => {
+                            paths.push((target, Prefixes::default()));
+                            delay_slot = true;
+                        }
+                    },
+                    ("bra", Some(target)) => {
+                        paths.push((target, Prefixes::default()));
+                        delay_slot = true;
+                    }
+                    (_, Some(target)) => paths.push((target, Prefixes::default())),
+                    ("stop", _) => break,
+                    ("jmp", _) | ("ljmp", _) => delay_slot = true,
+                    _ if prefixes.with == Some(15) && !instruction.is_prefix() => delay_slot = true,
+                    _ => {}
+                }
+                if writes_r15 {
+                    // to r15 makes the next instruction a jump that can't be followed
+                    let after = decode(data, instruction.next(), next);
+                    if let Some(after) = after {
+                        routine.bytes += after.len();
+                    }
+                    break;
+                }
+                prefixes = next;
+                address = instruction.next();
+            }
+        }
+        routines.push(routine);
+    }
+    routines
+}
@@ -0,0 +1,165 @@
+use checksum;
+use snesutilities::header_score;
+
+const HALF_BANK: usize = 0x8000;
+const GD24_CHUNK: usize = 0x80000;
+const EXHIROM_SPLIT: usize = 0x400000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interleave {
+    // hirom dump with the upper 32 KiB of every bank stored first, then the lower halves (swc / super ufo)
+    Standard,
+    // 24 Mbit game doctor sf dump, the last three 4 Mbit chunks are rotated on top of the standard interleave
+    GameDoctor24,
+    // 48/64 Mbit exhirom, the first 32 Mbit and the extension are interleaved separately
+    ExHiROM,
+}
+
+pub fn detect_interleave(data: &[u8]) -> Option<Interleave> {
+    if !data.len().is_multiple_of(0x10000) || data.is_empty() {
+        return None;
+    }
+    if data.len() > EXHIROM_SPLIT {
+        let interleaved = header_score(data, EXHIROM_SPLIT + 0x7fc0);
+        if interleaved > header_score(data, EXHIROM_SPLIT + 0xffc0) && data[EXHIROM_SPLIT + 0x7fd5] & 0x0f == 5 {
+            return Some(Interleave::ExHiROM);
+        }
+    }
+    // a hirom header showing up where a lorom header belongs means the halves are swapped
+    let lo = header_score(data, 0x7fc0);
+    if lo <= header_score(data, 0xffc0) || data[0x7fd5] & 1 == 0 {
+        return None;
+    }
+    if data.len() == 0x300000
+        && !checksum_matches(&deinterleave(data, Interleave::Standard).unwrap())
+        && checksum_matches(&deinterleave(data, Interleave::GameDoctor24).unwrap())
+    {
+        return Some(Interleave::GameDoctor24);
+    }
+    Some(Interleave::Standard)
+}
+
+pub fn deinterleave(data: &[u8], kind: Interleave) -> Result<Vec<u8>, String> {
+    check_size(data, kind)?;
+    Ok(match kind {
+        Interleave::Standard => deinterleave_halves(data),
+        Interleave::GameDoctor24 => {
+            let mut rotated = data.to_vec();
+            rotated[3 * GD24_CHUNK..5 * GD24_CHUNK].copy_from_slice(&data[4 * GD24_CHUNK..6 * GD24_CHUNK]);
+            rotated[5 * GD24_CHUNK..].copy_from_slice(&data[3 * GD24_CHUNK..4 * GD24_CHUNK]);
+            deinterleave_halves(&rotated)
+        }
+        Interleave::ExHiROM => {
+            let mut plain = deinterleave_halves(&data[..EXHIROM_SPLIT]);
+            plain.extend(deinterleave_halves(&data[EXHIROM_SPLIT..]));
+            plain
+        }
+    })
+}
+
+pub fn interleave(data: &[u8], kind: Interleave) -> Result<Vec<u8>, String> {
+    check_size(data, kind)?;
+    Ok(match kind {
+        Interleave::Standard => interleave_halves(data),
+        Interleave::GameDoctor24 => {
+            let halves = interleave_halves(data);
+            let mut rotated = halves.clone();
+            rotated[4 * GD24_CHUNK..].copy_from_slice(&halves[3 * GD24_CHUNK..5 * GD24_CHUNK]);
+            rotated[3 * GD24_CHUNK..4 * GD24_CHUNK].copy_from_slice(&halves[5 * GD24_CHUNK..]);
+            rotated
+        }
+        Interleave::ExHiROM => {
+            let mut interleaved = interleave_halves(&data[..EXHIROM_SPLIT]);
+            interleaved.extend(interleave_halves(&data[EXHIROM_SPLIT..]));
+            interleaved
+        }
+    })
+}
+
+fn check_size(data: &[u8], kind: Interleave) -> Result<(), String> {
+    if data.is_empty() || !data.len().is_multiple_of(0x10000) {
+        return Err(format!("{:#x} bytes is not a whole number of 64 KiB banks", data.len()));
+    }
+    match kind {
+        Interleave::GameDoctor24 if data.len() != 0x300000 => Err("the game doctor layout only exists for 24 Mbit images".to_string()),
+        Interleave::ExHiROM if data.len() <= EXHIROM_SPLIT => Err("the exhirom layout needs an image above 32 Mbit".to_string()),
+        _ => Ok(()),
+    }
+}
+
+fn checksum_matches(data: &[u8]) -> bool {
+    checksum::read_checksum(data, 0xffc0).is_some_and(|(_, sum)| sum == checksum::compute_checksum(data))
+}
+
+// plain half 2n comes from interleaved half banks + n, plain half 2n + 1 from interleaved half n
+fn deinterleave_halves(data: &[u8]) -> Vec<u8> {
+    let banks = data.len() / 0x10000;
+    let mut plain = Vec::with_capacity(data.len());
+    for bank in 0..banks {
+        plain.extend_from_slice(half(data, banks + bank));
+        plain.extend_from_slice(half(data, bank));
+    }
+    plain
+}
+
+fn interleave_halves(data: &[u8]) -> Vec<u8> {
+    let banks = data.len() / 0x10000;
+    let mut interleaved = Vec::with_capacity(data.len());
+    for bank in 0..banks {
+        interleaved.extend_from_slice(half(data, bank * 2 + 1));
+    }
+    for bank in 0..banks {
+        interleaved.extend_from_slice(half(data, bank * 2));
+    }
+    interleaved
+}
+
+fn half(data: &[u8], index: usize) -> &[u8] {
+    &data[index * HALF_BANK..(index + 1) * HALF_BANK]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every half bank filled with its own index, a hirom header in bank 0
+    fn hirom(size: usize) -> Vec<u8> {
+        let mut data: Vec<u8> = (0..size).map(|offset| (offset / HALF_BANK) as u8).collect();
+        data[0xffc0..0xffd5].copy_from_slice(b"INTERLEAVE TEST      ");
+        data[0xffd5] = 0x21;
+        data[0xfffd] = 0x80;
+        checksum::fix_checksum(&mut data, 0xffc0);
+        data
+    }
+
+    #[test]
+    fn round_trips() {
+        for (kind, size) in &[(Interleave::Standard, 0x80000), (Interleave::GameDoctor24, 0x300000), (Interleave::ExHiROM, 0x600000)] {
+            let plain = hirom(*size);
+            let interleaved = interleave(&plain, *kind).unwrap();
+            assert_ne!(interleaved, plain);
+            assert_eq!(deinterleave(&interleaved, *kind).unwrap(), plain);
+        }
+    }
+
+    #[test]
+    fn upper_halves_come_first() {
+        let interleaved = interleave(&hirom(0x40000), Interleave::Standard).unwrap();
+        let order: Vec<u8> = interleaved.chunks(HALF_BANK).map(|half| half[0]).collect();
+        assert_eq!(order, vec![1, 3, 5, 7, 0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn detects_swapped_halves() {
+        let plain = hirom(0x80000);
+        assert_eq!(detect_interleave(&plain), None);
+        assert_eq!(detect_interleave(&interleave(&plain, Interleave::Standard).unwrap()), Some(Interleave::Standard));
+    }
+
+    #[test]
+    fn rejects_odd_sizes() {
+        assert!(interleave(&[0; 0x18000], Interleave::Standard).is_err());
+        assert!(interleave(&[0; 0x80000], Interleave::GameDoctor24).is_err());
+        assert!(deinterleave(&[0; 0x400000], Interleave::ExHiROM).is_err());
+    }
+}
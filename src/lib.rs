@@ -0,0 +1,34 @@
+pub mod asm;
+pub mod brr;
+pub mod bsx;
+pub mod cheatdb;
+pub mod cheats;
+pub mod checksum;
+pub mod compression;
+pub mod copier;
+pub mod crc32;
+pub mod deflate;
+pub mod disasm;
+pub mod gfx;
+pub mod gsu;
+pub mod interleave;
+pub mod mapper;
+pub mod palette;
+pub mod patch;
+pub mod pipeline;
+pub mod png;
+pub mod resize;
+pub mod sa1;
+pub mod savestate;
+pub mod sdd1;
+pub mod sha256;
+pub mod snesutilities;
+pub mod spc;
+pub mod spc700;
+pub mod spc7110;
+pub mod split;
+pub mod sram;
+pub mod sufami;
+pub mod trace;
+pub mod upload;
+pub mod wav;
@@ -1,29 +1,1026 @@
-mod snesutilities;
-use snesutilities::SnesUtils;
+extern crate snesutils;
+
+use snesutils::asm;
+use snesutils::cheatdb::{self, CheatEntry};
+use snesutils::cheats::{self, Cheat};
+use snesutils::brr;
+use snesutils::compression::{self, Codec};
+use snesutils::crc32;
+use snesutils::copier::{self, CopierFormat};
+use snesutils::disasm::{self, Flags};
+use snesutils::gfx::{self, TileFormat};
+use snesutils::gsu;
+use snesutils::interleave::{self, Interleave};
+use snesutils::mapper::Mapper;
+use snesutils::palette::{self, PaletteFormat};
+use snesutils::patch;
+use snesutils::pipeline;
+use snesutils::png;
+use snesutils::resize::{self, Fill};
+use snesutils::sa1;
+use snesutils::savestate::{self, Savestate};
+use snesutils::sdd1;
+use snesutils::sha256;
+use snesutils::snesutilities::{Media, SnesUtils};
+use snesutils::spc::{self, Id666Format, Spc, Xid6Value};
+use snesutils::spc700;
+use snesutils::spc7110;
+use snesutils::split;
+use snesutils::sram::{self, SaveFormat};
+use snesutils::trace::{self, Cdl, CdlFormat};
+use snesutils::upload;
+use snesutils::wav::{self, Wave};
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::fs;
+use std::path::Path;
+use std::process;
 
 fn main() {
-    let suz = SnesUtils::new("./zelda.sfc".to_string());
-    println!("Internal Name: {}", suz.internal_name);
-    println!("Rom Type: {:?}", suz.rom_type);
-    println!("Rom Makeup Type: {:?}", suz.rom_makeup_type);
-    println!("ROM Size: {:?}", suz.rom_size);
-    println!("SRAM Size: {:?}", suz.sram_size);
-    println!("Video Mode: {:#?}", suz.video_mode);
-    println!("License: {:#?}\n", suz.license);
-    let sus = SnesUtils::new("./sengoku.sfc".to_string());
-    println!("Internal Name: {}", sus.internal_name);
-    println!("Rom Type: {:?}", sus.rom_type);
-    println!("Rom Makeup Type: {:?}", sus.rom_makeup_type);
-    println!("ROM Size: {:?}", sus.rom_size);
-    println!("SRAM Size: {:?}", sus.sram_size);
-    println!("Video Mode: {:#?}", sus.video_mode);
-    println!("License: {:#?}\n", sus.license);
-    let suf = SnesUtils::new("./ffv.sfc".to_string());
-    println!("Internal Name: {}", suf.internal_name);
-    println!("Rom Type: {:?}", suf.rom_type);
-    println!("Rom Makeup Type: {:?}", suf.rom_makeup_type);
-    println!("ROM Size: {:?}", suf.rom_size);
-    println!("SRAM Size: {:?}", suf.sram_size);
-    println!("Video Mode: {:#?}", suf.video_mode);
-    println!("License: {:#?}\n", suf.license);
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|arg| arg.as_str()) {
+        Some("info") if args.len() > 1 => {
+            for rom in &args[1..] {
+                let data = read_rom(rom);
+                let (copier_header, body) = copier::split_copier_header(&data);
+                print_info(&SnesUtils::from_bytes(&data), body.len());
+                if let Some(header) = copier_header {
+                    println!("Copier Header: {}", copier_name(header));
+                }
+            }
+        }
+        Some("patch") if args.len() > 3 => patch(&args[1], &args[2], &args[3..]),
+        Some("convert") if args.len() > 3 => convert(&args[1], &args[2], &args[3..]),
+        Some("resize") if args.len() == 3 || args.len() == 5 => resize(&args[1], &args[2], &args[3..]),
+        Some("header") if args.len() == 4 || args.len() == 5 => header(&args[1], &args[2], &args[3..]),
+        Some("disasm") if args.len() > 1 && args.len() < 5 => disassemble(&args[1], args.get(2), args.get(3)),
+        Some("trace") if args.len() > 2 && args.len() < 6 => trace(&args[1], &args[2], &args[3..]),
+        Some("asm") if args.len() == 4 || args.len() == 5 => assemble(&args[1], &args[2], &args[3], args.get(4)),
+        Some("uploads") if args.len() == 2 || args.len() == 3 => uploads(&args[1], args.get(2)),
+        Some("spc700") if args.len() == 4 || args.len() == 5 => spc700(&args[1], &args[2], &args[3], args.get(4)),
+        Some("brr") if args.len() > 2 && args.len() < 6 => brr(&args[1], &args[2..]),
+        Some("brr-encode") if args.len() == 3 => brr_encode(&args[1], &args[2]),
+        Some("spc") if args.len() > 1 && args.len() < 5 => spc(&args[1], &args[2..]),
+        Some("gfx") if args.len() > 2 && args.len() % 2 == 1 => graphics(&args[1], &args[2], &args[3..]),
+        Some("gfx-insert") if args.len() > 5 => insert_graphics(&args[1], &args[2], &args[3], &args[4..]),
+        Some("palette") if args.len() > 3 && args[2] == "--search" => search_palette(&args[1], &args[3..]),
+        Some("palette") if args.len() == 5 || args.len() == 6 => export_palette(&args[1], &args[2], &args[3], &args[4], args.get(5)),
+        Some("palette-import") if args.len() == 5 => import_palette(&args[1], &args[2], &args[3], &args[4]),
+        Some("decompress") if args.len() > 2 && args.len() < 6 && args[2] == "--scan" => scan_compressed(&args[1], &args[3..]),
+        Some("decompress") if args.len() == 5 => decompress(&args[1], &args[2], &args[3], &args[4]),
+        Some("compress") if args.len() == 4 => compress(&args[1], &args[2], &args[3]),
+        Some("sdd1") if args.len() == 5 && args[2] == "--unpack" => unpack_sdd1(&args[1], &args[3], &args[4]),
+        Some("sdd1") if args.len() == 5 => decompress_sdd1(&args[1], &args[2], &args[3], &args[4]),
+        Some("spc7110") if args.len() == 7 && args[2] == "--table" => spc7110_table(&args[1], &args[3], &args[4], &args[5], &args[6]),
+        Some("spc7110") if args.len() == 6 => decompress_spc7110(&args[1], &args[2], &args[3], &args[4], &args[5]),
+        Some("gsu") if args.len() == 3 && args[2] == "--trace" => trace_gsu(&args[1]),
+        Some("gsu") if args.len() > 2 && args.len() < 5 => disassemble_gsu(&args[1], &args[2], args.get(3)),
+        Some("sram") if args.len() == 3 => check_save(&args[1], &args[2]),
+        Some("sram") if args.len() > 3 && args.len().is_multiple_of(2) => convert_save(&args[1], &args[2], &args[3], &args[4..]),
+        Some("savestate") if args.len() > 1 && args.len().is_multiple_of(2) => inspect_savestate(&args[1], &args[2..]),
+        Some("cheat") if args.len() > 2 => show_cheats(&args[1], &args[2..]),
+        Some("cheat-apply") if args.len() > 3 => apply_cheats(&args[1], &args[2], &args[3..]),
+        Some("cheats") if args.len() > 2 && !args.len().is_multiple_of(2) => cheat_database(&args[1], &args[2], &args[3..]),
+        Some("join") if args.len() == 3 => write_all(&args[2], &read_rom(&args[1])),
+        Some("split") if args.len() == 3 || args.len() == 4 => split(&args[1], &args[2], args.get(3)),
+        _ => usage(),
+    }
+}
+
+fn usage() -> ! {
+    println!("usage: snesutils info <rom>...");
+    println!("       snesutils patch <rom> <output> <patch>...");
+    println!("       snesutils convert <rom> <output> --deinterleave");
+    println!("       snesutils convert <rom> <output> --interleave <standard|gd24|exhirom>");
+    println!("       snesutils resize <rom> <output> [--fill <00|ff>]");
+    println!("       snesutils header <rom> <output> --add <swc|smc|fig>");
+    println!("       snesutils header <rom> <output> --strip");
+    println!("       snesutils disasm <rom> [address|reset|nmi|sa1-reset|sa1-nmi] [len]");
+    println!("       snesutils trace <rom> <output cdl> [mesen|bsnes] [cdl to merge]");
+    println!("       snesutils asm <rom> <output> <source file or text> [ips output]");
+    println!("       snesutils uploads <rom> [output dir]");
+    println!("       snesutils spc700 <file> <offset> <aram address> [len]");
+    println!("       snesutils brr <file> <offset> <output wav> [loop offset]");
+    println!("       snesutils brr <file> --scan [output dir]");
+    println!("       snesutils brr-encode <wav> <output brr>");
+    println!("       snesutils spc <file> [samples output dir]");
+    println!("       snesutils spc <file> <output> --text|--binary");
+    println!("       snesutils gfx <rom> <output png> [--offset x] [--format 1bpp|2bpp|4bpp|8bpp|mode7|nes] [--width tiles] [--tiles n] [--palette offset|file] [--codec c|sdd1]");
+    println!("       snesutils gfx-insert <rom> <output> <png> --offset x [--format f] [--palette offset|file] [--quantize] [--dedupe] [--tilemap file]");
+    println!("       snesutils palette <rom> <offset> <colors> <output> [jasc|riff|gpl|act]");
+    println!("       snesutils palette <rom> --search <rrggbb>...");
+    println!("       snesutils palette-import <rom> <output> <palette file> <offset>");
+    println!("       snesutils decompress <rom> <lz1|lz2|lz5|hal|konami|square|rle> <offset> <output>");
+    println!("       snesutils decompress <rom> --scan [start] [end]");
+    println!("       snesutils compress <file> <codec> <output>");
+    println!("       snesutils sdd1 <rom> <address> <length> <output>");
+    println!("       snesutils sdd1 <rom> --unpack <block list> <output>");
+    println!("       snesutils spc7110 <rom> <0|1|2> <data rom offset> <length> <output bin|png>");
+    println!("       snesutils spc7110 <rom> --table <table offset> <index> <length> <output bin|png>");
+    println!("       snesutils gsu <rom> <address> [len]");
+    println!("       snesutils gsu <rom> --trace");
+    println!("       snesutils sram <rom> <save>");
+    println!("       snesutils sram <rom> <save> <output> [--from <raw|copier|zsnes>] [--to <raw|copier|zsnes>] [--size <bytes>] [--fill <00|ff>] [--rtc <file>]");
+    println!("       snesutils savestate <state> [--rom <rom>] [--extract <directory>]");
+    println!("       snesutils cheat <rom> <code>...");
+    println!("       snesutils cheat-apply <rom> <output rom|ips> <code>...");
+    println!("       snesutils cheats <rom> <cht|bml> [--select <n,...>] [--export <cht>] [--ips <ips>]");
+    println!("       snesutils join <part> <output>");
+    println!("       snesutils split <rom> <first part> [mbit per part]");
+    process::exit(1);
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
+
+fn read_all(file_name: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(file_name)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .unwrap_or_else(|e| fail(format!("{}: {}", file_name, e)));
+    data
+}
+
+// roms may be split dumps, in which case all parts are joined
+fn read_rom(file_name: &str) -> Vec<u8> {
+    split::load(Path::new(file_name)).unwrap_or_else(|e| fail(e))
+}
+
+fn write_all(file_name: &str, data: &[u8]) {
+    File::create(file_name)
+        .and_then(|mut file| file.write_all(data))
+        .unwrap_or_else(|e| fail(format!("{}: {}", file_name, e)));
+}
+
+fn print_info(su: &SnesUtils, size: usize) {
+    println!("Internal Name: {}", su.internal_name);
+    println!("Rom Type: {:?}", su.rom_type);
+    println!("Rom Makeup Type: {:?}", su.rom_makeup_type);
+    println!("ROM Size: {:?}", su.rom_size);
+    println!("SRAM Size: {:?}", su.sram_size);
+    println!("Video Mode: {:#?}", su.video_mode);
+    println!("License: {:#?}", su.license);
+    if su.media() != Media::Cartridge {
+        println!("Media: {:?}", su.media());
+    }
+    if let Some(header) = su.extended_header.as_ref() {
+        println!("Maker Code: {}", header.maker_code);
+        println!("Game Code: {}", header.game_code);
+        println!("Expansion RAM Size: {:?}", header.expansion_ram_size);
+    }
+    if let Some(header) = su.bsx_header.as_ref() {
+        println!("BS-X Maker Code: {}", header.maker_code);
+        println!("BS-X Blocks: {:08x} ({} KiB)", header.blocks, header.size() / 1024);
+        println!("BS-X Starts: {}", header.starts.map(|starts| starts.to_string()).unwrap_or("unlimited".to_string()));
+        println!("BS-X Broadcast Date: {}", header.date().unwrap_or("none".to_string()));
+        println!("BS-X Map Mode: {:02x}, Execution Flags: {:02x}, Version: {:02x}", header.map_mode, header.execution_flags, header.version);
+    }
+    if let Some(header) = su.sufami_turbo_header.as_ref() {
+        println!("Sufami Turbo Game ID: {:06x}, Series Index: {}", header.game_id, header.series_index);
+        println!("Sufami Turbo ROM: {} KiB, SRAM: {} KiB", header.rom_size / 1024, header.sram_size / 1024);
+    }
+    if let Some(info) = gsu::info(su, size) {
+        println!("GSU: {:?}, {} KiB RAM", info.revision, info.ram_size / 1024);
+    }
+    if let Some(info) = sa1::info(su) {
+        println!("SA-1: {} KiB BW-RAM, {} KiB I-RAM", info.bwram_size / 1024, info.iram_size / 1024);
+    }
+    println!();
+}
+
+fn patch(rom: &str, output: &str, patches: &[String]) {
+    let patches: Vec<(String, Vec<u8>)> = patches.iter().map(|name| (name.clone(), read_all(name))).collect();
+    let (image, report) = pipeline::apply_patches(&read_rom(rom), &patches).unwrap_or_else(|e| fail(e));
+    for step in &report.steps {
+        println!("{} ({:?}, {}): {} -> {} bytes, {}",
+                 step.name,
+                 step.format,
+                 if step.headered { "headered" } else { "headerless" },
+                 step.size_before,
+                 step.size_after,
+                 step.reason);
+    }
+    if report.changes.is_empty() {
+        println!("header unchanged");
+    }
+    for change in &report.changes {
+        println!("{}: {} -> {}", change.field, change.before, change.after);
+    }
+    write_all(output, &image);
+}
+
+fn convert(rom: &str, output: &str, options: &[String]) {
+    let data = read_rom(rom);
+    let (header, body) = copier::split_copier_header(&data);
+    let converted = match options[0].as_str() {
+        "--deinterleave" => {
+            let kind = interleave::detect_interleave(body).unwrap_or_else(|| fail(format!("{}: not interleaved", rom)));
+            println!("detected {:?} interleave", kind);
+            interleave::deinterleave(body, kind)
+        }
+        "--interleave" if options.len() > 1 => {
+            let kind = match options[1].as_str() {
+                "standard" => Interleave::Standard,
+                "gd24" => Interleave::GameDoctor24,
+                "exhirom" => Interleave::ExHiROM,
+                other => fail(format!("unknown interleave {}", other)),
+            };
+            interleave::interleave(body, kind)
+        }
+        _ => usage(),
+    };
+    let mut image = header.map(|header| header.to_vec()).unwrap_or_default();
+    image.extend(converted.unwrap_or_else(|e| fail(e)));
+    write_all(output, &image);
+}
+
+fn split(rom: &str, first_part: &str, mbit: Option<&String>) {
+    let part_size = match mbit {
+        Some(mbit) => mbit.parse::<usize>().unwrap_or_else(|_| usage()) * 0x20000,
+        None => split::PART_SIZE,
+    };
+    if part_size == 0 {
+        usage();
+    }
+    let first_part = Path::new(first_part);
+    let index = split::part_index(first_part).unwrap_or_else(|| fail(format!("{}: not a part name like GAME.1 or SF16GAMA.078", first_part.display())));
+    for (offset, part) in split::split_image(&read_rom(rom), part_size).iter().enumerate() {
+        let name = split::part_name(first_part, index + offset).unwrap_or_else(|| fail("too many parts".to_string()));
+        write_all(&name.to_string_lossy(), part);
+        println!("{}: {} bytes", name.display(), part.len());
+    }
+}
+
+fn resize(rom: &str, output: &str, options: &[String]) {
+    let fill = match options.first().map(|option| option.as_str()) {
+        None => Fill::Mirror,
+        Some("--fill") => Fill::Byte(u8::from_str_radix(&options[1], 16).unwrap_or_else(|_| usage())),
+        Some(_) => usage(),
+    };
+    let data = read_rom(rom);
+    let (header, body) = copier::split_copier_header(&data);
+    let (resized, report) = resize::resize(body, fill).unwrap_or_else(|e| fail(e));
+    println!("{}: {:#x} -> {:#x} bytes", report.action, report.old_size, report.new_size);
+    println!("checksum: {:04X} -> {:04X}", report.old_checksum, report.new_checksum);
+    let mut image = header.map(|header| header.to_vec()).unwrap_or_default();
+    image.extend(resized);
+    write_all(output, &image);
+}
+
+fn header(rom: &str, output: &str, options: &[String]) {
+    let data = read_rom(rom);
+    let image = match (options[0].as_str(), options.get(1).map(|format| format.as_str())) {
+        ("--strip", None) => {
+            match copier::split_copier_header(&data).0 {
+                Some(header) => println!("removed {} copier header", copier_name(header)),
+                None => println!("{}: no copier header", rom),
+            }
+            copier::strip_copier_header(&data).to_vec()
+        }
+        ("--add", Some("swc")) | ("--add", Some("smc")) => copier::add_copier_header(&data, CopierFormat::Swc),
+        ("--add", Some("fig")) => copier::add_copier_header(&data, CopierFormat::Fig),
+        _ => usage(),
+    };
+    write_all(output, &image);
+}
+
+fn copier_name(header: &[u8]) -> String {
+    copier::detect_copier_format(header).map_or("unknown".to_string(), |format| format!("{:?}", format))
+}
+
+fn parse_number(text: &str) -> u32 {
+    let text = text.trim_start_matches('$').trim_start_matches("0x");
+    u32::from_str_radix(&text.replace(':', ""), 16).unwrap_or_else(|_| fail(format!("{} is not a hex number", text)))
+}
+
+fn disassemble(rom: &str, address: Option<&String>, len: Option<&String>) {
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    let su = SnesUtils::from_bytes(body);
+    let mapper = Mapper::new(&su, body.len());
+    let len = len.map_or(0x40, |len| parse_number(len) as usize);
+    // the sa-1's vectors are only looked for when they're asked for or nothing else is
+    let sa1_vectors = || match sa1::info(&su) {
+        Some(_) => sa1::find_vectors(body, &mapper, &su),
+        None => sa1::Sa1Vectors::default(),
+    };
+    let entries = match address.map(|address| address.as_str()) {
+        None => {
+            let mut entries = vec![("reset", su.vectors.reset as u32), ("nmi", su.vectors.native_nmi as u32)];
+            entries.extend(sa1_vectors().all().iter().filter(|&&(name, _)| name != "sa-1 irq").map(|&(name, vector)| (name, vector as u32)));
+            entries
+        }
+        Some("reset") => vec![("reset", su.vectors.reset as u32)],
+        Some("nmi") => vec![("nmi", su.vectors.native_nmi as u32)],
+        Some("sa1-reset") => vec![("sa-1 reset", sa1_vectors().reset.unwrap_or_else(|| fail(format!("{}: no sa-1 reset vector found", rom))) as u32)],
+        Some("sa1-nmi") => vec![("sa-1 nmi", sa1_vectors().nmi.unwrap_or_else(|| fail(format!("{}: no sa-1 nmi vector found", rom))) as u32)],
+        Some(address) => vec![("", parse_number(address))],
+    };
+    for (name, address) in entries {
+        if !name.is_empty() {
+            println!("{}:", name);
+        }
+        for instruction in disasm::disassemble(body, &mapper, address, len, Flags::default()) {
+            println!("{}", instruction);
+        }
+    }
+}
+
+fn trace(rom: &str, output: &str, options: &[String]) {
+    let format = match options.first().map(|format| format.as_str()) {
+        None | Some("mesen") => CdlFormat::Mesen,
+        Some("bsnes") => CdlFormat::BsnesPlus,
+        Some(_) => usage(),
+    };
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    let su = SnesUtils::from_bytes(body);
+    let mapper = Mapper::new(&su, body.len());
+    let mut entries = trace::vector_entries(&su.vectors);
+    if sa1::info(&su).is_some() {
+        let vectors = sa1::find_vectors(body, &mapper, &su);
+        for (name, vector) in vectors.all() {
+            println!("{}: {:04x}", name, vector);
+            entries.push(vector as u32);
+        }
+    }
+    let mut cdl = trace::trace(body, &mapper, &entries);
+    if let Some(existing) = options.get(1) {
+        cdl.merge(&Cdl::import(&read_all(existing), body).unwrap_or_else(|e| fail(format!("{}: {}", existing, e))));
+    }
+    println!("code: {:#x} bytes, data: {:#x} bytes, {:.2}% of the rom",
+             cdl.code_bytes(),
+             cdl.data_bytes(),
+             (cdl.code_bytes() + cdl.data_bytes()) as f64 * 100.0 / body.len() as f64);
+    write_all(output, &cdl.export(format, body));
+}
+
+fn assemble(rom: &str, output: &str, source: &str, ips: Option<&String>) {
+    let source = if Path::new(source).is_file() {
+        String::from_utf8_lossy(&read_all(source)).to_string()
+    } else {
+        source.to_string()
+    };
+    let data = read_rom(rom);
+    let (header, body) = copier::split_copier_header(&data);
+    let su = SnesUtils::from_bytes(body);
+    let assembly = asm::assemble(&source, &Mapper::new(&su, body.len())).unwrap_or_else(|e| fail(e));
+    let mut patched = body.to_vec();
+    assembly.write(&mut patched).unwrap_or_else(|e| fail(e));
+    for chunk in &assembly.chunks {
+        println!("${:06x} (offset {:#x}): {} bytes", chunk.address, chunk.offset, chunk.bytes.len());
+    }
+    let mut image = header.map(|header| header.to_vec()).unwrap_or_default();
+    image.extend(patched);
+    if let Some(ips) = ips {
+        write_all(ips, &patch::create_ips(&data, &image));
+    }
+    write_all(output, &image);
+}
+
+fn uploads(rom: &str, directory: Option<&String>) {
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    for upload in upload::find_uploads(body) {
+        println!("{:#08x}: {} blocks, {:#x} bytes, entry ${:04x}", upload.offset, upload.blocks.len(), upload.size(), upload.entry);
+        for block in &upload.blocks {
+            println!("    {:#08x}: ${:04x}-${:04x}", block.offset, block.destination, block.destination as usize + block.data.len() - 1);
+        }
+        if let Some(directory) = directory {
+            fs::create_dir_all(directory).unwrap_or_else(|e| fail(format!("{}: {}", directory, e)));
+            let path = Path::new(directory);
+            write_all(&path.join(format!("{:06x}_aram.bin", upload.offset)).to_string_lossy(), &upload.aram());
+            for block in &upload.blocks {
+                write_all(&path.join(format!("{:06x}_{:04x}.bin", block.offset, block.destination)).to_string_lossy(), &block.data);
+            }
+        }
+    }
+}
+
+fn spc700(file: &str, offset: &str, address: &str, len: Option<&String>) {
+    let data = read_all(file);
+    let offset = parse_number(offset) as usize;
+    let len = len.map_or(0x40, |len| parse_number(len) as usize);
+    if offset > data.len() {
+        fail(format!("{}: offset past the end of the file", file));
+    }
+    for instruction in spc700::disassemble(&data[offset..], parse_number(address) as u16, len) {
+        println!("{}", instruction);
+    }
+}
+
+fn brr(file: &str, options: &[String]) {
+    let data = read_all(file);
+    if options[0] == "--scan" {
+        if options.len() > 2 {
+            usage();
+        }
+        for sample in brr::scan(&data) {
+            println!("{:#08x}: {} blocks, {} samples{}",
+                     sample.offset,
+                     sample.size / brr::BLOCK_SIZE,
+                     sample.pcm.len(),
+                     if sample.loop_start.is_some() { ", looped" } else { "" });
+            if let Some(directory) = options.get(1) {
+                fs::create_dir_all(directory).unwrap_or_else(|e| fail(format!("{}: {}", directory, e)));
+                write_wave(&Path::new(directory).join(format!("{:06x}.wav", sample.offset)).to_string_lossy(), sample);
+            }
+        }
+        return;
+    }
+    if options.len() < 2 {
+        usage();
+    }
+    let offset = parse_number(&options[0]) as usize;
+    let loop_offset = options.get(2).map_or(0, |loop_offset| parse_number(loop_offset) as usize);
+    let sample = brr::decode(&data, offset, loop_offset).unwrap_or_else(|e| fail(e));
+    println!("{} blocks, {} samples{}", sample.size / brr::BLOCK_SIZE, sample.pcm.len(), if sample.loop_start.is_some() { ", looped" } else { "" });
+    write_wave(&options[1], sample);
+}
+
+fn write_wave(file_name: &str, sample: brr::Sample) {
+    write_all(file_name,
+              &wav::write_wav(&Wave {
+                  rate: wav::DSP_RATE,
+                  pcm: sample.pcm,
+                  loop_start: sample.loop_start,
+              }));
+}
+
+fn brr_encode(input: &str, output: &str) {
+    let wave = wav::read_wav(&read_all(input)).unwrap_or_else(|e| fail(format!("{}: {}", input, e)));
+    if wave.rate != wav::DSP_RATE {
+        println!("note: {} Hz, the dsp plays it at {} Hz with pitch $1000", wave.rate, wav::DSP_RATE);
+    }
+    let encoded = brr::encode(&wave.pcm, wave.loop_start);
+    println!("{} samples -> {} blocks{}", wave.pcm.len(), encoded.len() / brr::BLOCK_SIZE, match wave.loop_start {
+        Some(start) => format!(", loop at {:#x}", start / brr::SAMPLES_PER_BLOCK * brr::BLOCK_SIZE),
+        None => String::new(),
+    });
+    write_all(output, &encoded);
+}
+
+fn spc(file: &str, options: &[String]) {
+    let mut spc = Spc::parse(&read_all(file)).unwrap_or_else(|e| fail(format!("{}: {}", file, e)));
+    if options.len() == 2 {
+        spc.id666_format = match options[1].as_str() {
+            "--text" => Id666Format::Text,
+            "--binary" => Id666Format::Binary,
+            _ => usage(),
+        };
+        write_all(&options[0], &spc.write());
+        return;
+    }
+    let registers = spc.registers;
+    println!("PC: ${:04x} A: ${:02x} X: ${:02x} Y: ${:02x} PSW: ${:02x} SP: ${:02x}", registers.pc, registers.a, registers.x, registers.y, registers.psw, registers.sp);
+    if let Some(id666) = &spc.id666 {
+        println!("ID666 ({:?})", spc.id666_format);
+        println!("Song: {}", id666.song);
+        println!("Game: {}", id666.game);
+        println!("Artist: {}", id666.artist);
+        println!("Dumper: {}", id666.dumper);
+        println!("Comments: {}", id666.comments);
+        if let Some(date) = id666.date {
+            println!("Date: {:04}-{:02}-{:02}", date.year, date.month, date.day);
+        }
+        println!("Length: {}s, fade {}ms", id666.seconds, id666.fade);
+    }
+    for item in &spc.xid6 {
+        match &item.value {
+            Xid6Value::Data(data) => println!("{}: {:#x}", spc::xid6_name(item.id), data),
+            Xid6Value::Text(text) => println!("{}: {}", spc::xid6_name(item.id), text),
+            Xid6Value::Integer(value) => println!("{}: {}", spc::xid6_name(item.id), value),
+        }
+    }
+    for entry in spc.directory() {
+        println!("{:02x}: ${:04x}, loop ${:04x}, {} samples{}{}",
+                 entry.index,
+                 entry.start,
+                 entry.loop_start,
+                 entry.sample.pcm.len(),
+                 if entry.sample.loop_start.is_some() { ", looped" } else { "" },
+                 if entry.used { ", in use" } else { "" });
+        if let Some(directory) = options.first() {
+            fs::create_dir_all(directory).unwrap_or_else(|e| fail(format!("{}: {}", directory, e)));
+            write_wave(&Path::new(directory).join(format!("{:02x}.wav", entry.index)).to_string_lossy(), entry.sample);
+        }
+    }
+}
+
+fn graphics(rom: &str, output: &str, options: &[String]) {
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    let mut offset = 0;
+    let mut format = TileFormat::Bpp4;
+    let mut width = 16;
+    let mut count = 0x100;
+    let mut palette = None;
+    let mut codec = None;
+    for option in options.chunks(2) {
+        match option[0].as_str() {
+            "--offset" => offset = parse_number(&option[1]) as usize,
+            "--format" => format = TileFormat::from_name(&option[1]).unwrap_or_else(|| usage()),
+            "--width" => width = option[1].parse().unwrap_or_else(|_| usage()),
+            "--tiles" => count = option[1].parse().unwrap_or_else(|_| usage()),
+            "--palette" => palette = Some(option[1].clone()),
+            "--codec" => codec = Some(option[1].clone()),
+            _ => usage(),
+        }
+    }
+    if offset >= body.len() {
+        fail(format!("{}: offset past the end of the file", rom));
+    }
+    // compressed graphics are decoded from the start of what they decompress to, s-dd1 data has no
+    // end so exactly the tiles asked for are decompressed
+    let decompressed = match codec.as_deref() {
+        Some("sdd1") => Some(sdd1::decompress(body, offset, count * format.tile_size())),
+        Some(name) => Some(compression::decompress(Codec::from_name(name).unwrap_or_else(|| usage()), body, offset)),
+        None => None,
+    };
+    let (source, offset) = match decompressed {
+        Some(result) => (result.unwrap_or_else(|e| fail(format!("{}: {}", rom, e))).data, 0),
+        None => (body.to_vec(), offset),
+    };
+    let end = source.len().min(offset + count * format.tile_size());
+    let tiles = gfx::decode_tiles(&source[offset..end], format);
+    let palette = match palette {
+        Some(palette) => {
+            load_palette(rom, body, &palette, format.colors())
+        }
+        None => gfx::grayscale(format.colors()),
+    };
+    let image = gfx::render(&tiles, width, &palette);
+    println!("{} tiles, {}x{} pixels", tiles.len(), image.width, image.height);
+    write_all(output, &png::write_rgba(image.width, image.height, &image.rgba));
+}
+
+fn insert_graphics(rom: &str, output: &str, image: &str, options: &[String]) {
+    let mut offset = None;
+    let mut format = TileFormat::Bpp4;
+    let mut palette = None;
+    let mut quantize = false;
+    let mut dedupe = false;
+    let mut tilemap = None;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let mut value = || options.next().unwrap_or_else(|| usage());
+        match option.as_str() {
+            "--offset" => offset = Some(parse_number(value()) as usize),
+            "--format" => format = TileFormat::from_name(value()).unwrap_or_else(|| usage()),
+            "--palette" => palette = Some(value().clone()),
+            "--tilemap" => tilemap = Some(value().clone()),
+            "--quantize" => quantize = true,
+            "--dedupe" => dedupe = true,
+            _ => usage(),
+        }
+    }
+    let offset = offset.unwrap_or_else(|| usage());
+    let data = read_rom(rom);
+    let (header, body) = copier::split_copier_header(&data);
+    let png = png::read_png(&read_all(image)).unwrap_or_else(|e| fail(format!("{}: {}", image, e)));
+
+    let indices = match (palette, &png.indices) {
+        (Some(palette), _) => {
+            gfx::match_palette(&png.rgba, &load_palette(rom, body, &palette, format.colors()), quantize).unwrap_or_else(|e| fail(format!("{}: {}", image, e)))
+        }
+        (None, Some(indices)) => indices.clone(),
+        (None, None) => fail(format!("{}: not an indexed PNG, a palette is needed", image)),
+    };
+    if let Some(index) = indices.iter().find(|index| **index as usize >= format.colors()) {
+        fail(format!("{}: color {} doesn't fit in {} colors", image, index, format.colors()));
+    }
+    let tiles = gfx::slice_tiles(&indices, png.width, png.height).unwrap_or_else(|e| fail(format!("{}: {}", image, e)));
+    let tileset = if dedupe {
+        gfx::dedupe(&tiles, format != TileFormat::Mode7)
+    } else {
+        gfx::Tileset {
+            tilemap: (0..tiles.len() as u16).collect(),
+            tiles,
+        }
+    };
+    let mut patched = body.to_vec();
+    let checksum = gfx::insert(&mut patched, offset, &tileset.tiles, format).unwrap_or_else(|e| fail(format!("{}: {}", rom, e)));
+    println!("{} tiles ({} unique), {:#x} bytes at {:#x}", tileset.tilemap.len(), tileset.tiles.len(), tileset.tiles.len() * format.tile_size(), offset);
+    match checksum {
+        Some((old, new)) => println!("checksum: {:04X} -> {:04X}", old, new),
+        None => println!("checksum: not fixed, {} has no internal header", rom),
+    }
+    if let Some(tilemap) = tilemap {
+        if tileset.tiles.len() > 0x400 {
+            fail(format!("{} tiles don't fit in a tilemap", tileset.tiles.len()));
+        }
+        let entries: Vec<u8> = match format {
+            TileFormat::Mode7 => tileset.tilemap.iter().map(|entry| *entry as u8).collect(),
+            _ => tileset.tilemap.iter().flat_map(|entry| vec![*entry as u8, (*entry >> 8) as u8]).collect(),
+        };
+        write_all(&tilemap, &entries);
+    }
+    let mut image = header.map(|header| header.to_vec()).unwrap_or_default();
+    image.extend(patched);
+    write_all(output, &image);
+}
+
+// a palette file, or the bgr555 colors at an offset of the rom
+fn load_palette(rom: &str, body: &[u8], source: &str, colors: usize) -> Vec<[u8; 4]> {
+    if Path::new(source).is_file() {
+        let rgb = palette::import(&read_all(source)).unwrap_or_else(|e| fail(format!("{}: {}", source, e)));
+        return rgb.iter().map(|&[r, g, b]| [r, g, b, 0xff]).collect();
+    }
+    let offset = parse_number(source) as usize;
+    let data = body.get(offset..offset + colors * 2).unwrap_or_else(|| fail(format!("{}: palette past the end of the file", rom)));
+    palette::rgba(&palette::read_colors(data))
+}
+
+fn export_palette(rom: &str, offset: &str, count: &str, output: &str, format: Option<&String>) {
+    let format = match format {
+        Some(format) => PaletteFormat::from_name(format).unwrap_or_else(|| usage()),
+        None => Path::new(output)
+            .extension()
+            .and_then(|extension| PaletteFormat::from_name(&extension.to_string_lossy().to_lowercase()))
+            .unwrap_or(PaletteFormat::Jasc),
+    };
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    let offset = parse_number(offset) as usize;
+    let count = count.parse::<usize>().unwrap_or_else(|_| usage());
+    let colors = palette::read_colors(body.get(offset..offset + count * 2).unwrap_or_else(|| fail(format!("{}: palette past the end of the file", rom))));
+    for (index, color) in colors.iter().enumerate() {
+        let [r, g, b] = palette::to_rgb(*color);
+        println!("{:3}: ${:04x} #{:02x}{:02x}{:02x}", index, color, r, g, b);
+    }
+    write_all(output, &palette::export(&colors, format));
+}
+
+fn search_palette(rom: &str, colors: &[String]) {
+    let colors: Vec<[u8; 3]> = colors
+        .iter()
+        .map(|color| {
+            let value = u32::from_str_radix(color.trim_start_matches('#'), 16).unwrap_or_else(|_| fail(format!("{} is not a RRGGBB color", color)));
+            [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+        })
+        .collect();
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    for offset in palette::search(body, &colors) {
+        println!("{:#08x}", offset);
+    }
+}
+
+fn import_palette(rom: &str, output: &str, file: &str, offset: &str) {
+    let colors: Vec<u16> = palette::import(&read_all(file)).unwrap_or_else(|e| fail(format!("{}: {}", file, e))).iter().map(|color| palette::from_rgb(*color)).collect();
+    let data = read_rom(rom);
+    let (header, body) = copier::split_copier_header(&data);
+    let offset = parse_number(offset) as usize;
+    let mut patched = body.to_vec();
+    let checksum = palette::insert(&mut patched, offset, &colors).unwrap_or_else(|e| fail(format!("{}: {}", rom, e)));
+    println!("{} colors at {:#x}", colors.len(), offset);
+    match checksum {
+        Some((old, new)) => println!("checksum: {:04X} -> {:04X}", old, new),
+        None => println!("checksum: not fixed, {} has no internal header", rom),
+    }
+    let mut image = header.map(|header| header.to_vec()).unwrap_or_default();
+    image.extend(patched);
+    write_all(output, &image);
+}
+
+fn decompress(rom: &str, codec: &str, offset: &str, output: &str) {
+    let codec = Codec::from_name(codec).unwrap_or_else(|| usage());
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    let result = compression::decompress(codec, body, parse_number(offset) as usize).unwrap_or_else(|e| fail(format!("{}: {}", rom, e)));
+    println!("{:#x} bytes -> {:#x} bytes", result.consumed, result.data.len());
+    write_all(output, &result.data);
+}
+
+fn compress(file: &str, codec: &str, output: &str) {
+    let codec = Codec::from_name(codec).unwrap_or_else(|| usage());
+    let data = read_all(file);
+    let compressed = compression::compress(codec, &data).unwrap_or_else(|e| fail(format!("{}: {}", file, e)));
+    println!("{:#x} bytes -> {:#x} bytes", data.len(), compressed.len());
+    write_all(output, &compressed);
+}
+
+fn scan_compressed(rom: &str, range: &[String]) {
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    let start = range.first().map_or(0, |start| parse_number(start) as usize);
+    let end = range.get(1).map_or(body.len(), |end| parse_number(end) as usize);
+    for stream in compression::scan(body, start, end) {
+        println!("{:#08x}: {:?}, {:#x} bytes -> {:#x} bytes", stream.offset, stream.codec, stream.compressed_size, stream.decompressed_size);
+    }
+}
+
+fn decompress_sdd1(rom: &str, address: &str, length: &str, output: &str) {
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    let address = parse_number(address);
+    let offset = sdd1::to_offset(address, body.len()).unwrap_or_else(|| fail(format!("{}: {:06x} isn't in rom", rom, address)));
+    let result = sdd1::decompress(body, offset, parse_number(length) as usize).unwrap_or_else(|e| fail(format!("{}: {}", rom, e)));
+    println!("{:06x} (offset {:#x}): {:#x} bytes -> {:#x} bytes", address, offset, result.consumed, result.data.len());
+    write_all(output, &result.data);
+}
+
+// the block list has an address and a length (both hex) per line, as the game's dma setups use them
+fn unpack_sdd1(rom: &str, list: &str, output: &str) {
+    let data = read_rom(rom);
+    let (header, body) = copier::split_copier_header(&data);
+    let blocks: Vec<(u32, usize)> = String::from_utf8_lossy(&read_all(list))
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 2 {
+                fail(format!("{}: expected an address and a length in \"{}\"", list, line));
+            }
+            (parse_number(fields[0]), parse_number(fields[1]) as usize)
+        })
+        .collect();
+    let (unpacked, report) = sdd1::unpack(body, &blocks).unwrap_or_else(|e| fail(format!("{}: {}", rom, e)));
+    for block in &report {
+        println!("{:06x}: {:#x} bytes -> {:#x} bytes at {:#x}", block.address, block.compressed_size, block.size, block.offset);
+    }
+    let mut image = header.map(|header| header.to_vec()).unwrap_or_default();
+    image.extend(unpacked);
+    write_all(output, &image);
+}
+
+fn decompress_spc7110(rom: &str, mode: &str, offset: &str, length: &str, output: &str) {
+    let mode = mode.parse().unwrap_or_else(|_| usage());
+    let data = read_rom(rom);
+    write_spc7110(rom, spc7110_data_rom(&data), mode, parse_number(offset) as usize, length, output);
+}
+
+fn spc7110_table(rom: &str, table: &str, index: &str, length: &str, output: &str) {
+    let data = read_rom(rom);
+    let data_rom = spc7110_data_rom(&data);
+    let (mode, offset) = spc7110::table_entry(data_rom, parse_number(table) as usize, parse_number(index) as usize).unwrap_or_else(|e| fail(format!("{}: {}", rom, e)));
+    println!("mode {}, data rom offset {:#x}", mode, offset);
+    write_spc7110(rom, data_rom, mode, offset, length, output);
+}
+
+// everything after the program rom
+fn spc7110_data_rom(data: &[u8]) -> &[u8] {
+    let body = copier::strip_copier_header(data);
+    &body[body.len().min(spc7110::program_size(SnesUtils::from_bytes(body).rom_type))..]
+}
+
+// pngs show the tiles in gray
+fn write_spc7110(rom: &str, data_rom: &[u8], mode: u8, offset: usize, length: &str, output: &str) {
+    let result = spc7110::decompress(data_rom, mode, offset, parse_number(length) as usize).unwrap_or_else(|e| fail(format!("{}: {}", rom, e)));
+    println!("{:#x} bytes -> {:#x} bytes", result.consumed, result.data.len());
+    if output.ends_with(".png") {
+        let format = [TileFormat::Bpp1, TileFormat::Bpp2, TileFormat::Bpp4][mode as usize];
+        let image = gfx::render(&gfx::decode_tiles(&result.data, format), 16, &gfx::grayscale(format.colors()));
+        write_all(output, &png::write_rgba(image.width, image.height, &image.rgba));
+    } else {
+        write_all(output, &result.data);
+    }
+}
+
+fn disassemble_gsu(rom: &str, address: &str, len: Option<&String>) {
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    let len = len.map_or(0x40, |len| parse_number(len) as usize);
+    for instruction in gsu::disassemble(body, parse_number(address), len) {
+        println!("{}", instruction);
+    }
+}
+
+fn trace_gsu(rom: &str) {
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    let su = SnesUtils::from_bytes(body);
+    let starts = gsu::find_starts(body, &Mapper::new(&su, body.len()), &su);
+    for start in &starts {
+        println!("{:06x}: starts the gsu at {:06x}", start.caller, start.entry);
+    }
+    let entries: Vec<u32> = starts.iter().map(|start| start.entry).collect();
+    for routine in gsu::trace(body, &entries) {
+        let calls: Vec<String> = routine.calls.iter().map(|call| format!("{:06x}", call)).collect();
+        println!("{:06x}: {:#x} bytes{}{}", routine.entry, routine.bytes, if calls.is_empty() { "" } else { ", calls " }, calls.join(" "));
+    }
+}
+
+fn check_save(rom: &str, save: &str) {
+    let su = SnesUtils::from_bytes(&read_rom(rom));
+    let data = read_all(save);
+    println!("sram: {:#x} bytes, save: {:#x} bytes", sram::sram_size(&su), data.len());
+    if sram::rtc_size(&su) > 0 {
+        println!("rtc: {} bytes", sram::rtc_size(&su));
+    }
+    sram::validate(&data, &su).unwrap_or_else(|e| fail(format!("{}: {}", save, e)));
+    println!("ok");
+}
+
+fn convert_save(rom: &str, save: &str, output: &str, options: &[String]) {
+    let su = SnesUtils::from_bytes(&read_rom(rom));
+    let mut from = SaveFormat::Raw;
+    let mut to = SaveFormat::Raw;
+    let mut size = sram::sram_size(&su);
+    let mut fill = 0x00;
+    let mut rtc = None;
+    for option in options.chunks(2) {
+        match option[0].as_str() {
+            "--from" => from = SaveFormat::from_name(&option[1]).unwrap_or_else(|| usage()),
+            "--to" => to = SaveFormat::from_name(&option[1]).unwrap_or_else(|| usage()),
+            "--size" => size = parse_number(&option[1]) as usize,
+            "--fill" => fill = u8::from_str_radix(&option[1], 16).unwrap_or_else(|_| usage()),
+            "--rtc" => rtc = Some(read_all(&option[1])),
+            _ => usage(),
+        }
+    }
+    if size == 0 {
+        fail(format!("{}: the header declares no sram, give --size", rom));
+    }
+    let (sram, clock) = sram::to_raw(&read_all(save), from, &su).unwrap_or_else(|e| fail(format!("{}: {}", save, e)));
+    let clock = rtc.or(clock);
+    if sram.len() != size {
+        println!("resized {:#x} -> {:#x} bytes", sram.len(), size);
+    }
+    let sram = sram::resize(&sram, size, fill);
+    let converted = sram::from_raw(&sram, clock.as_deref(), to, &su).unwrap_or_else(|e| fail(e));
+    write_all(output, &converted);
+    // layouts without room for the clock get it next to them the way bsnes and snes9x keep it
+    if let Some(clock) = clock.filter(|_| to != SaveFormat::Zsnes) {
+        let name = Path::new(output).with_extension("rtc");
+        write_all(&name.to_string_lossy(), &clock);
+        println!("rtc written to {}", name.display());
+    }
+}
+
+fn inspect_savestate(state: &str, options: &[String]) {
+    let mut rom = None;
+    let mut directory = None;
+    for option in options.chunks(2) {
+        match option[0].as_str() {
+            "--rom" => rom = Some(option[1].clone()),
+            "--extract" => directory = Some(option[1].clone()),
+            _ => usage(),
+        }
+    }
+    let rom_data = rom.as_ref().map(|rom| read_rom(rom));
+    let body = rom_data.as_ref().map(|data| copier::strip_copier_header(data));
+    let sram_size = body.map_or(0, |body| sram::sram_size(&SnesUtils::from_bytes(body)));
+    let parsed = savestate::parse(&read_all(state), sram_size).unwrap_or_else(|e| fail(format!("{}: {}", state, e)));
+    println!("emulator: {:?}, version {}", parsed.emulator, parsed.version);
+    if let Some(name) = parsed.rom_name.as_ref() {
+        println!("rom name: {}", name);
+    }
+    if let Some(crc) = parsed.crc32 {
+        println!("rom crc32: {:08x}", crc);
+    }
+    if let Some(hash) = parsed.sha256.as_ref() {
+        println!("rom sha-256: {}", hash);
+    }
+    if let Some(description) = parsed.description.as_ref().filter(|description| !description.is_empty()) {
+        println!("description: {}", description);
+    }
+    if let (Some(rom), Some(body)) = (rom.as_ref(), body) {
+        // snes9x only knows the file name, bsnes the checksum
+        let matches = match (parsed.crc32, parsed.rom_name.as_ref()) {
+            (Some(crc), _) => Some(crc == crc32::crc32(body)),
+            (None, Some(name)) => Some(Path::new(name.replace('\\', "/").as_str()).file_stem() == Path::new(rom).file_stem()),
+            _ => None,
+        };
+        match matches {
+            Some(true) => println!("{}: matches", rom),
+            Some(false) => println!("{}: doesn't match", rom),
+            None => println!("{}: the state doesn't say which rom it is for", rom),
+        }
+    }
+    let blocks = [("wram", parsed.wram.as_ref()), ("vram", parsed.vram.as_ref()), ("cgram", parsed.cgram.as_ref()), ("sram", parsed.sram.as_ref())];
+    for (name, block) in blocks.iter() {
+        match block {
+            Some(block) => println!("{}: {:#x} bytes", name, block.len()),
+            None => println!("{}: not found", name),
+        }
+    }
+    if let Some(directory) = directory {
+        write_savestate_blocks(&parsed, Path::new(&directory));
+    }
+}
+
+// the blocks as files, cgram also as a palette and vram as 4bpp tiles in the first 16 colors, which
+// gfx can take further (--palette cgram.pal, other formats and offsets)
+fn write_savestate_blocks(state: &Savestate, directory: &Path) {
+    fs::create_dir_all(directory).unwrap_or_else(|e| fail(format!("{}: {}", directory.display(), e)));
+    let path = |name: &str| directory.join(name).to_string_lossy().to_string();
+    let blocks = [("wram.bin", state.wram.as_ref()), ("vram.bin", state.vram.as_ref()), ("cgram.bin", state.cgram.as_ref()), ("sram.srm", state.sram.as_ref())];
+    for (name, block) in blocks.iter() {
+        if let Some(block) = block {
+            write_all(&path(name), block);
+        }
+    }
+    let colors = state.cgram.as_ref().map(|cgram| palette::read_colors(cgram));
+    if let Some(colors) = colors.as_ref() {
+        write_all(&path("cgram.pal"), &palette::export(colors, PaletteFormat::Jasc));
+    }
+    if let Some(vram) = state.vram.as_ref() {
+        let palette = colors.map_or_else(|| gfx::grayscale(16), |colors| palette::rgba(&colors[..16]));
+        let image = gfx::render(&gfx::decode_tiles(vram, TileFormat::Bpp4), 16, &palette);
+        write_all(&path("vram.png"), &png::write_rgba(image.width, image.height, &image.rgba));
+    }
+    println!("written to {}", directory.display());
+}
+
+fn decode_cheats(codes: &[String]) -> Vec<Cheat> {
+    codes.iter().map(|code| cheats::decode(code).unwrap_or_else(|e| fail(e)).0).collect()
+}
+
+fn show_cheats(rom: &str, codes: &[String]) {
+    let data = read_rom(rom);
+    let body = copier::strip_copier_header(&data);
+    let mapper = Mapper::new(&SnesUtils::from_bytes(body), body.len());
+    for code in codes {
+        let (cheat, format) = cheats::decode(code).unwrap_or_else(|e| fail(e));
+        let target = match cheats::to_offset(&cheat, &mapper) {
+            Some(offset) => format!("offset {:#x}, was {:02x}", offset, body[offset]),
+            None if cheat.is_ram() => "ram".to_string(),
+            None => "not in rom".to_string(),
+        };
+        println!("{}: {:?}, {} ({})", code, format, cheats::encode_raw(&cheat), target);
+        if cheat.compare.is_none() {
+            println!("    game genie {}, pro action replay {}", cheats::encode_game_genie(&cheat), cheats::encode_pro_action_replay(&cheat));
+        }
+    }
+}
+
+// the rom cheats become an ips patch against the image as it is (copier header included), which is
+// written out or applied like any other patch
+fn apply_cheats(rom: &str, output: &str, codes: &[String]) {
+    let data = read_rom(rom);
+    let (header, body) = copier::split_copier_header(&data);
+    let cheats = decode_cheats(codes);
+    for cheat in cheats.iter().filter(|cheat| cheat.is_ram()) {
+        println!("{}: ram cheat, skipped", cheats::encode_raw(cheat));
+    }
+    let mapper = Mapper::new(&SnesUtils::from_bytes(body), body.len());
+    let mut modified = header.map(|header| header.to_vec()).unwrap_or_default();
+    modified.extend(cheats::apply(body, &cheats, &mapper).unwrap_or_else(|e| fail(format!("{}: {}", rom, e))));
+    let ips = patch::create_ips(&data, &modified);
+    if output.to_lowercase().ends_with(".ips") {
+        write_all(output, &ips);
+        return;
+    }
+    let (image, _) = pipeline::apply_patches(&data, &[("cheats".to_string(), ips)]).unwrap_or_else(|e| fail(e));
+    write_all(output, &image);
+}
+
+fn cheat_database(rom: &str, database: &str, options: &[String]) {
+    let mut select = None;
+    let mut export = None;
+    let mut ips = None;
+    for option in options.chunks(2) {
+        match option[0].as_str() {
+            "--select" => select = Some(option[1].split(',').map(|index| index.trim().parse::<usize>().unwrap_or_else(|_| usage())).collect::<Vec<usize>>()),
+            "--export" => export = Some(option[1].clone()),
+            "--ips" => ips = Some(option[1].clone()),
+            _ => usage(),
+        }
+    }
+    let data = read_rom(rom);
+    let (header, body) = copier::split_copier_header(&data);
+    let su = SnesUtils::from_bytes(body);
+    let hash = sha256::to_hex(&sha256::sha256(body));
+    let cartridges = cheatdb::load(&read_all(database)).unwrap_or_else(|e| fail(format!("{}: {}", database, e)));
+    let cartridge = cheatdb::find(&cartridges, &hash, &su.internal_name)
+        .unwrap_or_else(|| fail(format!("{}: no cheats for {} (sha-256 {})", database, su.internal_name.trim(), hash)));
+    if let Some(name) = cartridge.name.as_ref() {
+        println!("{}", name);
+    }
+    let mapper = Mapper::new(&su, body.len());
+    let mut ram = Vec::new();
+    let mut rom_cheats = Vec::new();
+    for (index, entry) in cartridge.cheats.iter().enumerate() {
+        let decoded = entry.cheats().unwrap_or_else(|e| fail(format!("{}: {}: {}", database, entry.description, e)));
+        let in_ram = decoded.iter().filter(|cheat| cheat.is_ram()).count();
+        let kind = match (in_ram, decoded.len() - in_ram) {
+            (_, 0) => "ram",
+            (0, _) => "rom",
+            _ => "rom+ram",
+        };
+        println!("{:3}: {} [{}] ({}{})", index, entry.description, entry.codes.join("+"), kind, if entry.enabled { ", enabled" } else { "" });
+        if select.as_ref().is_some_and(|select: &Vec<usize>| !select.contains(&index)) {
+            continue;
+        }
+        let ram_codes: Vec<String> = decoded.iter().filter(|cheat| cheat.is_ram()).map(cheats::encode_raw).collect();
+        if !ram_codes.is_empty() {
+            ram.push(CheatEntry { codes: ram_codes, ..entry.clone() });
+        }
+        rom_cheats.extend(decoded.into_iter().filter(|cheat| !cheat.is_ram()));
+    }
+    if let Some(export) = export {
+        write_all(&export, cheatdb::export_cht(&ram).as_bytes());
+        println!("{} ram cheats written to {}", ram.len(), export);
+    }
+    if let Some(ips) = ips {
+        // against the image as it is, like cheat-apply
+        let mut modified = header.map(|header| header.to_vec()).unwrap_or_default();
+        modified.extend(cheats::apply(body, &rom_cheats, &mapper).unwrap_or_else(|e| fail(format!("{}: {}", rom, e))));
+        write_all(&ips, &patch::create_ips(&data, &modified));
+        println!("{} rom codes written to {}", rom_cheats.len(), ips);
+    }
 }
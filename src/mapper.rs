@@ -0,0 +1,114 @@
+use snesutilities::{RomMakupType, RomType, SnesUtils};
+use sa1;
+use spc7110;
+
+// translates between snes bus addresses (bank << 16 | address) and offsets into the headerless image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mapper {
+    pub makeup: RomMakupType,
+    pub rom_type: RomType, // coprocessors with their own memory map
+    pub size: usize,
+}
+
+impl Mapper {
+    pub fn new(su: &SnesUtils, size: usize) -> Mapper {
+        Mapper {
+            makeup: su.rom_makeup_type,
+            rom_type: su.rom_type,
+            size,
+        }
+    }
+
+    // None for addresses that don't hit rom (wram, registers, sram)
+    pub fn to_offset(&self, address: u32) -> Option<usize> {
+        let bank = (address >> 16) as usize & 0xff;
+        let addr = address as usize & 0xffff;
+        if bank == 0x7e || bank == 0x7f {
+            return None; // wram
+        }
+        let offset = match self.makeup {
+            RomMakupType::HiROMSPC7110 => return spc7110::to_offset(address, spc7110::program_size(self.rom_type), self.size),
+            RomMakupType::LoROMSA1 => return sa1::to_offset(address, self.size),
+            RomMakupType::HiROM | RomMakupType::HiROMFastROM => {
+                if bank & 0x40 == 0 && addr < 0x8000 {
+                    return None;
+                }
+                ((bank & 0x3f) << 16) | addr
+            }
+            RomMakupType::ExHiROM => {
+                if bank & 0x40 == 0 && addr < 0x8000 {
+                    return None;
+                }
+                let upper = if bank & 0x80 == 0 { 0x400000 } else { 0 };
+                upper | ((bank & 0x3f) << 16) | addr
+            }
+            RomMakupType::ExLoROM => {
+                if addr < 0x8000 {
+                    return None;
+                }
+                let upper = if bank & 0x80 == 0 { 0x400000 } else { 0 };
+                upper | ((bank & 0x7f) << 15) | (addr & 0x7fff)
+            }
+            _ => {
+                if addr < 0x8000 {
+                    return None;
+                }
+                ((bank & 0x7f) << 15) | (addr & 0x7fff)
+            }
+        };
+        if self.size == 0 {
+            return None;
+        }
+        Some(mirror(offset, self.size))
+    }
+
+    // the address code normally runs from, fastrom images get the fast 0x80+ banks. None for rom the
+    // cartridge doesn't show on the bus without setting it up first
+    pub fn to_address(&self, offset: usize) -> Option<u32> {
+        if self.makeup == RomMakupType::HiROMSPC7110 {
+            return spc7110::to_address(offset, spc7110::program_size(self.rom_type));
+        }
+        let offset = offset as u32;
+        Some(match self.makeup {
+            RomMakupType::LoROMSA1 => sa1::to_address(offset as usize),
+            RomMakupType::HiROM | RomMakupType::HiROMFastROM => 0xc00000 | (offset & 0x3fffff),
+            RomMakupType::ExHiROM if offset >= 0x400000 => 0x400000 | (offset & 0x3fffff),
+            RomMakupType::ExHiROM => 0xc00000 | offset,
+            RomMakupType::ExLoROM if offset >= 0x400000 => ((offset - 0x400000) << 1 & 0x7f0000) | 0x8000 | (offset & 0x7fff),
+            RomMakupType::ExLoROM => 0x800000 | (offset << 1 & 0x7f0000) | 0x8000 | (offset & 0x7fff),
+            RomMakupType::LoROMFastROM => 0x800000 | (offset << 1 & 0x7f0000) | 0x8000 | (offset & 0x7fff),
+            _ => (offset << 1 & 0x7f0000) | 0x8000 | (offset & 0x7fff),
+        })
+    }
+
+    pub fn read(&self, data: &[u8], address: u32) -> Option<u8> {
+        self.to_offset(address).and_then(|offset| data.get(offset).cloned())
+    }
+
+    pub fn read_word(&self, data: &[u8], address: u32) -> Option<u16> {
+        let low = self.read(data, address)? as u16;
+        let high = self.read(data, (address & 0xff0000) | ((address + 1) & 0xffff))? as u16;
+        Some(low | high << 8)
+    }
+}
+
+// what the cartridge does with addresses past the end of the rom: the largest power of two stays,
+// the remainder repeats (same as bsnes)
+pub fn mirror(offset: usize, size: usize) -> usize {
+    let mut offset = offset;
+    let mut size = size;
+    let mut base = 0;
+    let mut mask = 1 << 24;
+    while offset >= size {
+        while offset & mask == 0 {
+            mask >>= 1;
+        }
+        offset -= mask;
+        if size > mask {
+            size -= mask;
+            base += mask;
+        }
+        mask >>= 1;
+    }
+    base + offset
+}
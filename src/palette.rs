@@ -0,0 +1,263 @@
+use checksum;
+use snesutilities;
+
+// cgram colors are little endian 0bbbbbgg gggrrrrr words
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaletteFormat {
+    Jasc, // paint shop pro text .pal
+    Riff, // microsoft binary .pal
+    Gpl,  // gimp
+    Act,  // adobe color table
+}
+
+impl PaletteFormat {
+    pub fn from_name(name: &str) -> Option<PaletteFormat> {
+        match name {
+            "jasc" | "pal" => Some(PaletteFormat::Jasc),
+            "riff" => Some(PaletteFormat::Riff),
+            "gpl" => Some(PaletteFormat::Gpl),
+            "act" => Some(PaletteFormat::Act),
+            _ => None,
+        }
+    }
+}
+
+// 5 bits stretched over the full 8 bit range, so 31 becomes 255 and not 248
+pub fn to_rgb(color: u16) -> [u8; 3] {
+    let expand = |shift: u16| {
+        let value = ((color >> shift) & 0x1f) as u8;
+        value << 3 | value >> 2
+    };
+    [expand(0), expand(5), expand(10)]
+}
+
+// rounds to the nearest 5 bit value, the inverse of to_rgb
+pub fn from_rgb(color: [u8; 3]) -> u16 {
+    let reduce = |value: u8| ((value as u32 * 31 + 127) / 255) as u16;
+    reduce(color[0]) | reduce(color[1]) << 5 | reduce(color[2]) << 10
+}
+
+pub fn rgba(colors: &[u16]) -> Vec<[u8; 4]> {
+    colors
+        .iter()
+        .map(|color| {
+            let [r, g, b] = to_rgb(*color);
+            [r, g, b, 0xff]
+        })
+        .collect()
+}
+
+pub fn read_colors(data: &[u8]) -> Vec<u16> {
+    data.chunks(2).filter(|word| word.len() == 2).map(|word| word[0] as u16 | (word[1] as u16) << 8).collect()
+}
+
+pub fn write_colors(colors: &[u16]) -> Vec<u8> {
+    colors.iter().flat_map(|color| vec![*color as u8, (*color >> 8) as u8]).collect()
+}
+
+// writes colors into data at offset and fixes the checksum, None when the image is too short to
+// have an internal header
+pub fn insert(data: &mut [u8], offset: usize, colors: &[u16]) -> Result<Option<(u16, u16)>, String> {
+    let words = write_colors(colors);
+    if offset.checked_add(words.len()).is_none_or(|end| end > data.len()) {
+        return Err(format!("{} colors don't fit at {:#x}", colors.len(), offset));
+    }
+    data[offset..offset + words.len()].copy_from_slice(&words);
+    if data.len() < 0x8000 {
+        return Ok(None);
+    }
+    let header_offset = snesutilities::find_header(data);
+    Ok(checksum::fix_checksum(data, header_offset))
+}
+
+pub fn export(colors: &[u16], format: PaletteFormat) -> Vec<u8> {
+    let rgb: Vec<[u8; 3]> = colors.iter().map(|color| to_rgb(*color)).collect();
+    match format {
+        PaletteFormat::Jasc => {
+            let mut text = format!("JASC-PAL\r\n0100\r\n{}\r\n", rgb.len());
+            for [r, g, b] in &rgb {
+                text.push_str(&format!("{} {} {}\r\n", r, g, b));
+            }
+            text.into_bytes()
+        }
+        PaletteFormat::Riff => {
+            let mut data = vec![0x00, 0x03, rgb.len() as u8, (rgb.len() >> 8) as u8];
+            for [r, g, b] in &rgb {
+                data.extend_from_slice(&[*r, *g, *b, 0]);
+            }
+            let mut file = b"RIFF".to_vec();
+            file.extend_from_slice(&le32(data.len() as u32 + 12));
+            file.extend_from_slice(b"PAL data");
+            file.extend_from_slice(&le32(data.len() as u32));
+            file.extend(data);
+            file
+        }
+        PaletteFormat::Gpl => {
+            let mut text = "GIMP Palette\nName: snesutils\nColumns: 16\n#\n".to_string();
+            for (index, [r, g, b]) in rgb.iter().enumerate() {
+                text.push_str(&format!("{:3} {:3} {:3}\tcolor {} (${:04x})\n", r, g, b, index, colors[index]));
+            }
+            text.into_bytes()
+        }
+        PaletteFormat::Act => {
+            // always 256 entries, the optional trailer says how many are used
+            let mut file = vec![0u8; 768];
+            for (index, color) in rgb.iter().take(256).enumerate() {
+                file[index * 3..index * 3 + 3].copy_from_slice(color);
+            }
+            let count = rgb.len().min(256);
+            file.extend_from_slice(&[(count >> 8) as u8, count as u8, 0xff, 0xff]);
+            file
+        }
+    }
+}
+
+pub fn detect_format(file: &[u8]) -> Option<PaletteFormat> {
+    if file.starts_with(b"JASC-PAL") {
+        Some(PaletteFormat::Jasc)
+    } else if file.starts_with(b"RIFF") && file.get(8..12) == Some(b"PAL ") {
+        Some(PaletteFormat::Riff)
+    } else if file.starts_with(b"GIMP Palette") {
+        Some(PaletteFormat::Gpl)
+    } else if file.len() == 768 || file.len() == 772 {
+        Some(PaletteFormat::Act)
+    } else {
+        None
+    }
+}
+
+// rgb colors of any of the formats, recognised by content
+pub fn import(file: &[u8]) -> Result<Vec<[u8; 3]>, String> {
+    match detect_format(file) {
+        Some(PaletteFormat::Jasc) => {
+            let text = String::from_utf8_lossy(file);
+            let mut lines = text.lines().skip(1);
+            if lines.next().map(|version| version.trim()) != Some("0100") {
+                return Err("unknown JASC-PAL version".to_string());
+            }
+            let count: usize = lines.next().and_then(|count| count.trim().parse().ok()).ok_or("JASC-PAL color count missing")?;
+            let colors: Vec<[u8; 3]> = lines.filter_map(|line| parse_rgb(line.split_whitespace())).take(count).collect();
+            if colors.len() < count {
+                return Err(format!("JASC-PAL declares {} colors but has {}", count, colors.len()));
+            }
+            Ok(colors)
+        }
+        Some(PaletteFormat::Riff) => {
+            let mut position = 12;
+            while position + 8 <= file.len() {
+                let size = read_le32(&file[position + 4..]) as usize;
+                if &file[position..position + 4] == b"data" {
+                    let data = file.get(position + 8..position + 8 + size).ok_or("RIFF palette data runs past the end")?;
+                    let count = data.get(2..4).map(|count| count[0] as usize | (count[1] as usize) << 8).ok_or("RIFF palette data is too short")?;
+                    let entries = data.get(4..4 + count * 4).ok_or("RIFF palette has fewer colors than it declares")?;
+                    return Ok(entries.chunks(4).map(|entry| [entry[0], entry[1], entry[2]]).collect());
+                }
+                position += 8 + size + (size & 1);
+            }
+            Err("RIFF palette without a data chunk".to_string())
+        }
+        Some(PaletteFormat::Gpl) => Ok(String::from_utf8_lossy(file)
+            .lines()
+            .skip(1)
+            .filter(|line| !line.starts_with('#') && !line.contains(':'))
+            .filter_map(|line| parse_rgb(line.split_whitespace()))
+            .collect()),
+        Some(PaletteFormat::Act) => {
+            let count = match file.len() {
+                772 => (file[768] as usize) << 8 | file[769] as usize,
+                _ => 256,
+            };
+            Ok(file[..768].chunks(3).take(count.clamp(1, 256)).map(|color| [color[0], color[1], color[2]]).collect())
+        }
+        None => Err("unknown palette format".to_string()),
+    }
+}
+
+fn parse_rgb<'a, I: Iterator<Item = &'a str>>(mut values: I) -> Option<[u8; 3]> {
+    let mut next = || values.next().and_then(|value| value.parse::<u8>().ok());
+    Some([next()?, next()?, next()?])
+}
+
+// places of the first color where the others show up as bgr555 words within a cgram's worth of
+// bytes around it, at the same byte alignment. conversions differ in rounding, so each channel may be one step off
+pub fn search(data: &[u8], colors: &[[u8; 3]]) -> Vec<usize> {
+    let targets: Vec<u16> = colors.iter().map(|color| from_rgb(*color)).collect();
+    let close = |word: u16, target: u16| (0..3).all(|channel| (((word >> (channel * 5)) & 0x1f) as i16 - ((target >> (channel * 5)) & 0x1f) as i16).abs() <= 1);
+    let word = |offset: usize| data[offset] as u16 | ((data[offset + 1] & 0x7f) as u16) << 8;
+    let mut found = Vec::new();
+    if targets.is_empty() || data.len() < 2 {
+        return found;
+    }
+    for offset in 0..data.len() - 1 {
+        if !close(word(offset), targets[0]) {
+            continue;
+        }
+        let start = offset - (offset.min(0x1fe) & !1);
+        let end = data.len().min(offset + 0x200);
+        let all = targets[1..].iter().all(|target| (start..end - 1).step_by(2).any(|other| close(word(other), *target)));
+        if all {
+            found.push(offset);
+        }
+    }
+    found
+}
+
+fn le32(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+fn read_le32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_into_a_short_buffer() {
+        let mut data = [0u8; 0x20];
+        assert_eq!(insert(&mut data, 0x10, &[0x7fff, 0x001f]), Ok(None));
+        assert_eq!(&data[0x10..0x14], &[0xff, 0x7f, 0x1f, 0x00]);
+        assert!(insert(&mut data, 0x1e, &[0x7fff, 0x001f]).is_err());
+        assert!(insert(&mut vec![0; 0x8000], 0, &[0x7fff]).unwrap().is_some());
+    }
+
+    #[test]
+    fn bgr555_to_rgb_and_back() {
+        assert_eq!(to_rgb(0x7fff), [255, 255, 255]);
+        assert_eq!(to_rgb(0x001f), [255, 0, 0]);
+        assert_eq!(to_rgb(0x7c00), [0, 0, 255]);
+        assert_eq!(to_rgb(0x0421), [8, 8, 8]);
+        assert_eq!(from_rgb([255, 255, 255]), 0x7fff);
+        assert_eq!(from_rgb([248, 0, 0]), 0x001e);
+        for value in 0..32 {
+            let color = value | value << 5 | value << 10;
+            assert_eq!(from_rgb(to_rgb(color)), color);
+        }
+    }
+
+    #[test]
+    fn export_and_import() {
+        let colors: Vec<u16> = (0..20).map(|index| (index * 0x0631) & 0x7fff).collect();
+        let rgb: Vec<[u8; 3]> = colors.iter().map(|color| to_rgb(*color)).collect();
+        for format in &[PaletteFormat::Jasc, PaletteFormat::Riff, PaletteFormat::Gpl, PaletteFormat::Act] {
+            let file = export(&colors, *format);
+            assert_eq!(detect_format(&file), Some(*format));
+            assert_eq!(import(&file), Ok(rgb.clone()), "{:?}", format);
+        }
+        assert!(import(b"not a palette").is_err());
+    }
+
+    #[test]
+    fn search_colors() {
+        let mut data = vec![0u8; 0x400];
+        data[0x101..0x107].copy_from_slice(&write_colors(&[0x7fff, 0x001f, 0x03e0]));
+        assert_eq!(search(&data, &[[255, 255, 255], [255, 0, 0], [0, 255, 0]]), vec![0x101]);
+        // one step off per channel still matches
+        assert_eq!(search(&data, &[[246, 255, 255], [255, 8, 0]]), vec![0x101]);
+        assert_eq!(search(&data, &[[255, 255, 255], [0, 0, 255]]), Vec::<usize>::new());
+        assert_eq!(search(&data, &[]), Vec::<usize>::new());
+    }
+}
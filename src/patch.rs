@@ -0,0 +1,465 @@
+use crc32::crc32;
+
+// more than any cartridge holds, a bigger target is taken for a broken patch
+const MAX_TARGET_SIZE: usize = 0x4000000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatchFormat {
+    Ips,
+    Bps,
+    Ups,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IpsRecord {
+    pub offset: usize,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IpsPatch {
+    pub records: Vec<IpsRecord>,
+    pub truncate: Option<usize>,
+}
+
+// what a bps/ups patch says about the file it has to be applied to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourceInfo {
+    pub source_size: usize,
+    pub target_size: usize,
+    pub source_crc: u32,
+    pub target_crc: u32,
+}
+
+pub fn detect_format(patch: &[u8]) -> Option<PatchFormat> {
+    if patch.starts_with(b"PATCH") {
+        Some(PatchFormat::Ips)
+    } else if patch.starts_with(b"BPS1") {
+        Some(PatchFormat::Bps)
+    } else if patch.starts_with(b"UPS1") {
+        Some(PatchFormat::Ups)
+    } else {
+        None
+    }
+}
+
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    match detect_format(patch) {
+        Some(PatchFormat::Ips) => apply_ips(source, patch),
+        Some(PatchFormat::Bps) => apply_bps(source, patch),
+        Some(PatchFormat::Ups) => apply_ups(source, patch),
+        None => Err("unknown patch format".to_string()),
+    }
+}
+
+pub fn parse_ips(patch: &[u8]) -> Result<IpsPatch, String> {
+    if !patch.starts_with(b"PATCH") {
+        return Err("missing IPS magic".to_string());
+    }
+    let mut records = Vec::new();
+    let mut pos = 5;
+    loop {
+        if pos + 3 > patch.len() {
+            return Err("IPS patch ends without EOF marker".to_string());
+        }
+        if &patch[pos..pos + 3] == b"EOF" {
+            pos += 3;
+            break;
+        }
+        if pos + 5 > patch.len() {
+            return Err(format!("truncated IPS record at {:#x}", pos));
+        }
+        let offset = read_be(&patch[pos..pos + 3]);
+        let size = read_be(&patch[pos + 3..pos + 5]);
+        pos += 5;
+        if size == 0 {
+            // rle record: 2 byte count followed by the fill value
+            if pos + 3 > patch.len() {
+                return Err(format!("truncated IPS RLE record at {:#x}", pos));
+            }
+            let count = read_be(&patch[pos..pos + 2]);
+            records.push(IpsRecord {
+                offset,
+                data: vec![patch[pos + 2]; count],
+            });
+            pos += 3;
+        } else {
+            if pos + size > patch.len() {
+                return Err(format!("truncated IPS record at {:#x}", pos));
+            }
+            records.push(IpsRecord {
+                offset,
+                data: patch[pos..pos + size].to_vec(),
+            });
+            pos += size;
+        }
+    }
+    let truncate = match patch.len() - pos {
+        0 => None,
+        3 => Some(read_be(&patch[pos..pos + 3])),
+        _ => return Err("trailing garbage after IPS EOF marker".to_string()),
+    };
+    Ok(IpsPatch { records, truncate })
+}
+
+pub fn apply_ips(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let ips = parse_ips(patch)?;
+    let mut target = source.to_vec();
+    for record in &ips.records {
+        let end = record.offset + record.data.len();
+        if end > target.len() {
+            target.resize(end, 0);
+        }
+        target[record.offset..end].copy_from_slice(&record.data);
+    }
+    if let Some(size) = ips.truncate {
+        target.truncate(size);
+    }
+    Ok(target)
+}
+
+pub fn source_info(patch: &[u8]) -> Result<Option<SourceInfo>, String> {
+    match detect_format(patch) {
+        Some(PatchFormat::Ips) => Ok(None),
+        Some(_) => {
+            check_footer(patch)?;
+            let mut pos = 4;
+            let source_size = decode_number(patch, &mut pos)?;
+            let target_size = decode_number(patch, &mut pos)?;
+            if target_size > MAX_TARGET_SIZE {
+                return Err(format!("patch target of {:#x} bytes is too large", target_size));
+            }
+            let footer = patch.len() - 12;
+            Ok(Some(SourceInfo {
+                source_size,
+                target_size,
+                source_crc: read_le32(&patch[footer..]),
+                target_crc: read_le32(&patch[footer + 4..]),
+            }))
+        }
+        None => Err("unknown patch format".to_string()),
+    }
+}
+
+pub fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(b"BPS1") {
+        return Err("missing BPS magic".to_string());
+    }
+    let info = source_info(patch)?.unwrap();
+    check_source(source, &info)?;
+    let end = patch.len() - 12;
+    let mut pos = 4;
+    decode_number(patch, &mut pos)?; // source size
+    decode_number(patch, &mut pos)?; // target size
+    let metadata_size = decode_number(patch, &mut pos)?;
+    pos = pos.checked_add(metadata_size).filter(|pos| *pos <= end).ok_or("BPS metadata out of range")?;
+
+    let mut target = Vec::with_capacity(info.target_size);
+    let mut source_relative = 0isize;
+    let mut target_relative = 0isize;
+    while pos < end {
+        let data = decode_number(patch, &mut pos)?;
+        let length = (data >> 2) + 1;
+        if length > info.target_size - target.len() {
+            return Err("BPS action writes past the target size".to_string());
+        }
+        match data & 3 {
+            0 => {
+                // source read
+                let start = target.len();
+                let bytes = start.checked_add(length).and_then(|end| source.get(start..end)).ok_or("BPS source read out of range")?;
+                target.extend_from_slice(bytes);
+            }
+            1 => {
+                // target read
+                let bytes = pos.checked_add(length).filter(|read| *read <= end).and_then(|read| patch.get(pos..read)).ok_or("BPS target read out of range")?;
+                target.extend_from_slice(bytes);
+                pos += length;
+            }
+            2 => {
+                // source copy
+                source_relative = source_relative.checked_add(decode_offset(patch, &mut pos)?).ok_or("BPS source copy out of range")?;
+                for _ in 0..length {
+                    let byte = *source.get(source_relative as usize).filter(|_| source_relative >= 0).ok_or("BPS source copy out of range")?;
+                    target.push(byte);
+                    source_relative += 1;
+                }
+            }
+            _ => {
+                // target copy, may overlap the bytes it produces
+                target_relative = target_relative.checked_add(decode_offset(patch, &mut pos)?).ok_or("BPS target copy out of range")?;
+                for _ in 0..length {
+                    if target_relative < 0 || target_relative as usize >= target.len() {
+                        return Err("BPS target copy out of range".to_string());
+                    }
+                    let byte = target[target_relative as usize];
+                    target.push(byte);
+                    target_relative += 1;
+                }
+            }
+        }
+    }
+    check_target(&target, &info)?;
+    Ok(target)
+}
+
+pub fn apply_ups(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if !patch.starts_with(b"UPS1") {
+        return Err("missing UPS magic".to_string());
+    }
+    let info = source_info(patch)?.unwrap();
+    check_source(source, &info)?;
+    let end = patch.len() - 12;
+    let mut pos = 4;
+    decode_number(patch, &mut pos)?; // source size
+    decode_number(patch, &mut pos)?; // target size
+
+    let mut target = vec![0u8; info.target_size];
+    let shared = source.len().min(info.target_size);
+    target[..shared].copy_from_slice(&source[..shared]);
+    let mut offset = 0usize;
+    while pos < end {
+        offset = offset.checked_add(decode_number(patch, &mut pos)?).filter(|offset| *offset <= target.len()).ok_or("UPS hunk offset out of range")?;
+        while pos < end && patch[pos] != 0 {
+            if offset >= target.len() {
+                return Err("UPS hunk writes past the target size".to_string());
+            }
+            target[offset] = source.get(offset).cloned().unwrap_or(0) ^ patch[pos];
+            offset += 1;
+            pos += 1;
+        }
+        pos += 1; // hunk terminator
+        offset += 1;
+    }
+    check_target(&target, &info)?;
+    Ok(target)
+}
+
+fn check_footer(patch: &[u8]) -> Result<(), String> {
+    if patch.len() < 16 {
+        return Err("patch is too short".to_string());
+    }
+    let footer = patch.len() - 12;
+    let expected = read_le32(&patch[footer + 8..]);
+    let actual = crc32(&patch[..footer + 8]);
+    if expected != actual {
+        return Err(format!("patch CRC mismatch (expected {:08X}, got {:08X})", expected, actual));
+    }
+    Ok(())
+}
+
+fn check_source(source: &[u8], info: &SourceInfo) -> Result<(), String> {
+    if source.len() != info.source_size {
+        return Err(format!("source size mismatch (expected {}, got {})", info.source_size, source.len()));
+    }
+    let crc = crc32(source);
+    if crc != info.source_crc {
+        return Err(format!("source CRC mismatch (expected {:08X}, got {:08X})", info.source_crc, crc));
+    }
+    Ok(())
+}
+
+fn check_target(target: &[u8], info: &SourceInfo) -> Result<(), String> {
+    if target.len() != info.target_size {
+        return Err(format!("target size mismatch (expected {}, got {})", info.target_size, target.len()));
+    }
+    let crc = crc32(target);
+    if crc != info.target_crc {
+        return Err(format!("target CRC mismatch (expected {:08X}, got {:08X})", info.target_crc, crc));
+    }
+    Ok(())
+}
+
+// variable length number shared by bps and ups
+fn decode_number(patch: &[u8], pos: &mut usize) -> Result<usize, String> {
+    let mut data = 0usize;
+    let mut shift = 1usize;
+    loop {
+        let byte = *patch.get(*pos).ok_or("truncated number in patch")?;
+        *pos += 1;
+        data = ((byte & 0x7f) as usize)
+            .checked_mul(shift)
+            .and_then(|term| data.checked_add(term))
+            .ok_or("number overflow in patch")?;
+        if byte & 0x80 != 0 {
+            return Ok(data);
+        }
+        // fails once the shift would pass the width of usize
+        shift = shift.checked_mul(0x80).ok_or("number overflow in patch")?;
+        data = data.checked_add(shift).ok_or("number overflow in patch")?;
+    }
+}
+
+fn decode_offset(patch: &[u8], pos: &mut usize) -> Result<isize, String> {
+    let data = decode_number(patch, pos)?;
+    let offset = (data >> 1) as isize;
+    Ok(if data & 1 != 0 { -offset } else { offset })
+}
+
+fn read_be(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |acc, byte| (acc << 8) | *byte as usize)
+}
+
+fn read_le32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+// records every run of changed bytes, with the truncate extension if the image got smaller
+pub fn create_ips(original: &[u8], modified: &[u8]) -> Vec<u8> {
+    let mut patch = b"PATCH".to_vec();
+    let mut offset = 0;
+    while offset < modified.len() {
+        if original.get(offset) == Some(&modified[offset]) {
+            offset += 1;
+            continue;
+        }
+        // an offset spelling "EOF" would end the patch, start one byte early instead
+        let start = if offset == 0x454f46 { offset - 1 } else { offset };
+        let mut end = offset;
+        while end < modified.len() && end - start < 0xffff && original.get(end) != Some(&modified[end]) {
+            end += 1;
+        }
+        patch.extend_from_slice(&[(start >> 16) as u8, (start >> 8) as u8, start as u8]);
+        patch.extend_from_slice(&[((end - start) >> 8) as u8, (end - start) as u8]);
+        patch.extend_from_slice(&modified[start..end]);
+        offset = end;
+    }
+    patch.extend_from_slice(b"EOF");
+    if modified.len() < original.len() {
+        let size = modified.len();
+        patch.extend_from_slice(&[(size >> 16) as u8, (size >> 8) as u8, size as u8]);
+    }
+    patch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_number(mut value: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let low = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(low | 0x80);
+                return bytes;
+            }
+            bytes.push(low);
+            value -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn ips_round_trip() {
+        let original: Vec<u8> = (0..0x200).map(|value| value as u8).collect();
+        let mut modified = original.clone();
+        modified[0x10..0x18].copy_from_slice(b"modified");
+        modified[0x1ff] = 0;
+        modified.extend_from_slice(&[0xaa; 0x20]);
+        let patch = create_ips(&original, &modified);
+        assert_eq!(detect_format(&patch), Some(PatchFormat::Ips));
+        assert_eq!(apply(&original, &patch).unwrap(), modified);
+    }
+
+    #[test]
+    fn ips_truncates() {
+        let original = vec![1u8; 0x100];
+        let modified = vec![1u8; 0x80];
+        let patch = create_ips(&original, &modified);
+        assert_eq!(parse_ips(&patch).unwrap().truncate, Some(0x80));
+        assert_eq!(apply_ips(&original, &patch).unwrap(), modified);
+    }
+
+    #[test]
+    fn ips_rle_record() {
+        let patch = b"PATCH\x00\x00\x02\x00\x00\x00\x04\x55EOF";
+        assert_eq!(apply_ips(&[0; 8], patch).unwrap(), vec![0, 0, 0x55, 0x55, 0x55, 0x55, 0, 0]);
+        assert!(parse_ips(b"PATCH\x00\x00\x02").is_err());
+    }
+
+    #[test]
+    fn bps_actions() {
+        let source = b"hello world";
+        let target = b"hello there, hello world";
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(encode_number(source.len()));
+        patch.extend(encode_number(target.len()));
+        patch.extend(encode_number(0)); // no metadata
+        patch.extend(encode_number((6 - 1) << 2)); // source read "hello "
+        patch.extend(encode_number((7 - 1) << 2 | 1)); // target read
+        patch.extend_from_slice(b"there, ");
+        patch.extend(encode_number((5 - 1) << 2 | 3)); // target copy "hello" from 0
+        patch.extend(encode_number(0));
+        patch.extend(encode_number((6 - 1) << 2 | 2)); // source copy " world" from 5
+        patch.extend(encode_number(5 << 1));
+        let patch = with_footer(patch, source, target);
+        let info = source_info(&patch).unwrap().unwrap();
+        assert_eq!((info.source_size, info.target_size), (source.len(), target.len()));
+        assert_eq!(apply(source, &patch).unwrap(), target.to_vec());
+        assert!(apply(b"hello World", &patch).is_err());
+    }
+
+    #[test]
+    fn ups_hunks() {
+        let source = b"abcdef";
+        let target = b"abXdefgh";
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(encode_number(source.len()));
+        patch.extend(encode_number(target.len()));
+        patch.extend(encode_number(2));
+        patch.extend_from_slice(&[b'c' ^ b'X', 0]);
+        patch.extend(encode_number(2));
+        patch.extend_from_slice(&[b'g', b'h', 0]);
+        let patch = with_footer(patch, source, target);
+        assert_eq!(apply(source, &patch).unwrap(), target.to_vec());
+        let mut broken = patch.clone();
+        broken[6] ^= 1;
+        assert!(apply(source, &broken).is_err());
+    }
+
+    fn bps(source: &[u8], target: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        patch.extend_from_slice(body);
+        with_footer(patch, source, target)
+    }
+
+    #[test]
+    fn crafted_numbers_fail() {
+        let source = b"source";
+        // an unterminated number runs out of bits
+        assert!(apply(source, &bps(source, b"", &[0x7f; 13])).is_err());
+        assert!(apply(source, &bps(source, b"", &[0x00; 13])).is_err());
+        // a target nobody could allocate
+        let mut huge = encode_number(source.len());
+        huge.extend(encode_number(1 << 60));
+        huge.extend(encode_number(0));
+        assert!(apply(source, &bps(source, b"", &huge)).is_err());
+    }
+
+    #[test]
+    fn crafted_lengths_fail() {
+        let source = b"source";
+        let header = |metadata: usize| [encode_number(source.len()), encode_number(4), encode_number(metadata)].concat();
+        assert!(apply(source, &bps(source, b"abcd", &header(usize::MAX >> 8))).is_err());
+        for action in &[(usize::MAX >> 8) << 2, (usize::MAX >> 8) << 2 | 1, 3 << 2 | 1] {
+            let body = [header(0), encode_number(*action)].concat();
+            assert!(apply(source, &bps(source, b"abcd", &body)).is_err());
+        }
+        // source copy with an offset far before the start
+        let body = [header(0), encode_number(3 << 2 | 2), encode_number(usize::MAX >> 8)].concat();
+        assert!(apply(source, &bps(source, b"abcd", &body)).is_err());
+        let mut ups = b"UPS1".to_vec();
+        ups.extend([encode_number(source.len()), encode_number(4), encode_number(usize::MAX >> 8)].concat());
+        ups.extend_from_slice(&[1, 0]);
+        assert!(apply(source, &with_footer(ups, source, b"abcd")).is_err());
+    }
+}
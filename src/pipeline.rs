@@ -0,0 +1,136 @@
+use copier::{self, COPIER_HEADER_SIZE};
+use crc32::crc32;
+use patch::{self, PatchFormat};
+use snesutilities::SnesUtils;
+
+#[derive(Debug, Clone)]
+pub struct PipelineStep {
+    pub name: String,
+    pub format: PatchFormat,
+    pub headered: bool, // whether the patch was applied to the image including a copier header
+    pub reason: String,
+    pub size_before: usize,
+    pub size_after: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderChange {
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+pub struct PipelineReport {
+    pub steps: Vec<PipelineStep>,
+    pub before: SnesUtils,
+    pub after: SnesUtils,
+    pub changes: Vec<HeaderChange>,
+}
+
+// applies the patches in order, keeping the copier header of the input (if any) on the output
+pub fn apply_patches(rom: &[u8], patches: &[(String, Vec<u8>)]) -> Result<(Vec<u8>, PipelineReport), String> {
+    let (header, body) = copier::split_copier_header(rom);
+    let mut header = header.map(|header| header.to_vec());
+    let mut body = body.to_vec();
+    let before = SnesUtils::from_bytes(&body);
+    let mut steps = Vec::new();
+
+    for (name, data) in patches {
+        let format = patch::detect_format(data).ok_or(format!("{}: unknown patch format", name))?;
+        let (headered, reason) = detect_header_expectation(header.as_deref(), &body, data)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        let size_before = body.len();
+        let source = if headered {
+            let mut source = header.clone().unwrap_or_else(|| vec![0; COPIER_HEADER_SIZE]);
+            source.extend_from_slice(&body);
+            source
+        } else {
+            body.clone()
+        };
+        let target = patch::apply(&source, data).map_err(|e| format!("{}: {}", name, e))?;
+        if headered {
+            if target.len() < COPIER_HEADER_SIZE {
+                return Err(format!("{}: patched image is smaller than a copier header", name));
+            }
+            if header.is_some() {
+                header = Some(target[..COPIER_HEADER_SIZE].to_vec());
+            }
+            body = target[COPIER_HEADER_SIZE..].to_vec();
+        } else {
+            body = target;
+        }
+        steps.push(PipelineStep {
+            name: name.clone(),
+            format,
+            headered,
+            reason,
+            size_before,
+            size_after: body.len(),
+        });
+    }
+
+    let after = SnesUtils::from_bytes(&body);
+    let changes = header_changes(&before, &after);
+    let mut output = header.unwrap_or_default();
+    output.extend_from_slice(&body);
+    Ok((output, PipelineReport { steps, before, after, changes }))
+}
+
+// decides whether a patch was made against a headered or a headerless image
+pub fn detect_header_expectation(header: Option<&[u8]>, body: &[u8], data: &[u8]) -> Result<(bool, String), String> {
+    if let Some(info) = patch::source_info(data)? {
+        if info.source_crc == crc32(body) {
+            return Ok((false, format!("source CRC {:08X} matches the headerless image", info.source_crc)));
+        }
+        if let Some(header) = header {
+            let mut headered = header.to_vec();
+            headered.extend_from_slice(body);
+            if info.source_crc == crc32(&headered) {
+                return Ok((true, format!("source CRC {:08X} matches the headered image", info.source_crc)));
+            }
+        }
+        return Err(format!("source CRC {:08X} matches neither the headered nor the headerless image", info.source_crc));
+    }
+
+    // ips has no checksums, look at how the patch sizes the image instead
+    let ips = patch::parse_ips(data)?;
+    if let Some(size) = ips.truncate {
+        if size % 1024 == COPIER_HEADER_SIZE {
+            return Ok((true, format!("truncates to {:#x}, a headered size", size)));
+        }
+        if size % 1024 == 0 {
+            return Ok((false, format!("truncates to {:#x}, a headerless size", size)));
+        }
+    }
+    let end = ips.records.iter().map(|r| r.offset + r.data.len()).max().unwrap_or(0);
+    if end > body.len() + header.map_or(0, |h| h.len()) {
+        if end % 0x8000 == COPIER_HEADER_SIZE {
+            return Ok((true, format!("expands to {:#x}, a headered size", end)));
+        }
+        if end % 0x8000 == 0 {
+            return Ok((false, format!("expands to {:#x}, a headerless size", end)));
+        }
+    }
+    Ok((header.is_some(), "no size hints, assuming the patch matches the image as is".to_string()))
+}
+
+pub fn header_changes(before: &SnesUtils, after: &SnesUtils) -> Vec<HeaderChange> {
+    let fields = vec![
+        ("Internal Name", before.internal_name.clone(), after.internal_name.clone()),
+        ("Rom Type", format!("{:?}", before.rom_type), format!("{:?}", after.rom_type)),
+        ("Rom Makeup Type", format!("{:?}", before.rom_makeup_type), format!("{:?}", after.rom_makeup_type)),
+        ("ROM Size", before.rom_size.to_string(), after.rom_size.to_string()),
+        ("SRAM Size", before.sram_size.to_string(), after.sram_size.to_string()),
+        ("Video Mode", format!("{} ({})", before.video_mode.country, before.video_mode.mode), format!("{} ({})", after.video_mode.country, after.video_mode.mode)),
+        ("License", before.license.clone(), after.license.clone()),
+    ];
+    fields
+        .into_iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(field, before, after)| HeaderChange {
+            field: field.to_string(),
+            before,
+            after,
+        })
+        .collect()
+}
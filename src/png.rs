@@ -0,0 +1,163 @@
+use crc32::crc32;
+use deflate;
+
+const SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+// 8-bit rgba, every scanline unfiltered
+pub fn write_rgba(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(height * (width * 4 + 1));
+    for row in rgba.chunks(width * 4).take(height) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&be32(width as u32));
+    ihdr.extend_from_slice(&be32(height as u32));
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]); // depth, rgba, deflate, adaptive filters, no interlace
+
+    let mut png = SIGNATURE.to_vec();
+    push_chunk(&mut png, b"IHDR", &ihdr);
+    push_chunk(&mut png, b"IDAT", &deflate::zlib_compress(&raw));
+    push_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn push_chunk(png: &mut Vec<u8>, id: &[u8], data: &[u8]) {
+    png.extend_from_slice(&be32(data.len() as u32));
+    let start = png.len();
+    png.extend_from_slice(id);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&be32(crc));
+}
+
+fn be32(value: u32) -> [u8; 4] {
+    [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Png {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+    pub indices: Option<Vec<u8>>, // palette indices of indexed images
+    pub palette: Vec<[u8; 4]>,
+}
+
+// every non-interlaced color type, 16-bit samples are cut to their high byte
+pub fn read_png(file: &[u8]) -> Result<Png, String> {
+    if !file.starts_with(SIGNATURE) {
+        return Err("not a PNG file".to_string());
+    }
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut idat = Vec::new();
+    let mut position = SIGNATURE.len();
+    while position + 8 <= file.len() {
+        let size = read_be32(&file[position..]) as usize;
+        let id = &file[position + 4..position + 8];
+        let data = file.get(position + 8..position + 8 + size).ok_or("PNG chunk runs past the end of the file")?;
+        match id {
+            b"IHDR" if size >= 13 => header = Some((read_be32(data) as usize, read_be32(&data[4..]) as usize, data[8], data[9], data[12])),
+            b"PLTE" => palette = data.chunks(3).filter(|color| color.len() == 3).map(|color| [color[0], color[1], color[2], 0xff]).collect(),
+            b"tRNS" => {
+                for (color, alpha) in palette.iter_mut().zip(data) {
+                    color[3] = *alpha;
+                }
+            }
+            b"IDAT" => idat.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+        position += 12 + size;
+    }
+    let (width, height, depth, color_type, interlace) = header.ok_or("missing IHDR chunk")?;
+    if interlace != 0 {
+        return Err("interlaced PNGs aren't supported".to_string());
+    }
+    let channels = match color_type {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("unknown PNG color type {}", color_type)),
+    };
+    if ![1, 2, 4, 8, 16].contains(&depth) || (channels > 1 && depth < 8) || (color_type == 3 && depth == 16) {
+        return Err(format!("invalid bit depth {} for color type {}", depth, color_type));
+    }
+    let raw = unfilter(&deflate::zlib_decompress(&idat)?, width, height, channels * depth as usize)?;
+
+    let stride = (width * channels * depth as usize).div_ceil(8);
+    let sample = |row: &[u8], index: usize| -> u8 {
+        match depth {
+            16 => row[index * 2],
+            8 => row[index],
+            _ => {
+                let bit = index * depth as usize;
+                let value = (row[bit / 8] >> (8 - depth as usize - bit % 8)) & ((1 << depth) - 1);
+                if color_type == 3 { value } else { (value as u32 * 255 / ((1 << depth) - 1)) as u8 }
+            }
+        }
+    };
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    let mut indices = Vec::new();
+    for row in raw.chunks(stride).take(height) {
+        for x in 0..width {
+            let values: Vec<u8> = (0..channels).map(|channel| sample(row, x * channels + channel)).collect();
+            let color = match color_type {
+                0 => [values[0], values[0], values[0], 0xff],
+                2 => [values[0], values[1], values[2], 0xff],
+                3 => {
+                    indices.push(values[0]);
+                    *palette.get(values[0] as usize).ok_or(format!("pixel uses color {} past the end of the palette", values[0]))?
+                }
+                4 => [values[0], values[0], values[0], values[1]],
+                _ => [values[0], values[1], values[2], values[3]],
+            };
+            rgba.extend_from_slice(&color);
+        }
+    }
+    Ok(Png {
+        width,
+        height,
+        rgba,
+        indices: if color_type == 3 { Some(indices) } else { None },
+        palette,
+    })
+}
+
+fn unfilter(data: &[u8], width: usize, height: usize, bits_per_pixel: usize) -> Result<Vec<u8>, String> {
+    let stride = (width * bits_per_pixel).div_ceil(8);
+    let left = bits_per_pixel.div_ceil(8);
+    if data.len() < height * (stride + 1) {
+        return Err("PNG image data is too short".to_string());
+    }
+    let mut raw = vec![0u8; height * stride];
+    for y in 0..height {
+        let filter = data[y * (stride + 1)];
+        let line = &data[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        for x in 0..stride {
+            let a = if x >= left { raw[y * stride + x - left] as i16 } else { 0 };
+            let b = if y > 0 { raw[(y - 1) * stride + x] as i16 } else { 0 };
+            let c = if x >= left && y > 0 { raw[(y - 1) * stride + x - left] as i16 } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => (a + b) / 2,
+                4 => {
+                    let p = a + b - c;
+                    let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+                    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+                }
+                _ => return Err(format!("unknown PNG filter {}", filter)),
+            };
+            raw[y * stride + x] = line[x].wrapping_add(predicted as u8);
+        }
+    }
+    Ok(raw)
+}
+
+fn read_be32(bytes: &[u8]) -> u32 {
+    (bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32
+}
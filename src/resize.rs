@@ -0,0 +1,102 @@
+use checksum;
+use snesutilities::{self, SnesUtils};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fill {
+    Mirror, // repeat the upper part like the address decoder of a real cartridge does
+    Byte(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResizeReport {
+    pub old_size: usize,
+    pub new_size: usize,
+    pub action: String,
+    pub old_checksum: u16,
+    pub new_checksum: u16,
+}
+
+// size from the rom_size exponent, 1 KiB << n
+pub fn declared_size(su: &SnesUtils) -> Option<usize> {
+    match su.rom_size {
+        size @ 7..=13 => Some(1024 << size),
+        _ => None,
+    }
+}
+
+// trims an overdump or fills up an underdump to the declared size and fixes the checksum
+pub fn resize(data: &[u8], fill: Fill) -> Result<(Vec<u8>, ResizeReport), String> {
+    let su = SnesUtils::from_bytes(data);
+    let declared = declared_size(&su).ok_or(format!("invalid rom size byte {:#04x}", su.rom_size))?;
+    let mut image = data.to_vec();
+    let action = if data.len() > declared {
+        trim(&mut image, declared)?
+    } else if data.len() < declared {
+        expand(&mut image, declared, fill)
+    } else {
+        "size already matches the header".to_string()
+    };
+    let offset = snesutilities::find_header(&image);
+    let (old_checksum, new_checksum) = checksum::fix_checksum(&mut image, offset).ok_or("the image ends before its internal header")?;
+    let report = ResizeReport {
+        old_size: data.len(),
+        new_size: image.len(),
+        action,
+        old_checksum,
+        new_checksum,
+    };
+    Ok((image, report))
+}
+
+fn trim(image: &mut Vec<u8>, size: usize) -> Result<String, String> {
+    let tail = &image[size..];
+    let action = if tail.iter().all(|byte| *byte == 0x00) || tail.iter().all(|byte| *byte == 0xff) {
+        format!("trimmed {:#x} bytes of {:#04x} fill", tail.len(), tail[0])
+    } else if tail.chunks(size).all(|chunk| chunk == &image[..chunk.len()]) {
+        format!("trimmed {:#x} bytes of mirrored data", tail.len())
+    } else {
+        return Err(format!("the {:#x} bytes past the declared size are neither fill nor a mirror", tail.len()));
+    };
+    image.truncate(size);
+    Ok(action)
+}
+
+fn expand(image: &mut Vec<u8>, size: usize, fill: Fill) -> String {
+    let added = size - image.len();
+    match fill {
+        Fill::Byte(byte) => {
+            image.resize(size, byte);
+            format!("padded {:#x} bytes with {:#04x}", added, byte)
+        }
+        Fill::Mirror => {
+            // first up to the next power of two, then the whole chip repeats
+            let mut mirrored = mirror(image, image.len().next_power_of_two());
+            while mirrored.len() < size {
+                mirrored.extend_from_within(..);
+            }
+            mirrored.truncate(size);
+            *image = mirrored;
+            format!("mirrored {:#x} bytes", added)
+        }
+    }
+}
+
+// the largest power of two stays put, the rest is repeated until it fills the remaining space
+fn mirror(data: &[u8], size: usize) -> Vec<u8> {
+    if data.len() >= size {
+        return data[..size].to_vec();
+    }
+    let mut base = 1;
+    while base * 2 <= data.len() {
+        base *= 2;
+    }
+    let mut mirrored = data[..base].to_vec();
+    if base == data.len() {
+        while mirrored.len() < size {
+            mirrored.extend_from_slice(data);
+        }
+    } else {
+        mirrored.extend(mirror(&data[base..], size - base));
+    }
+    mirrored
+}
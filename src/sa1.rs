@@ -0,0 +1,95 @@
+use mapper::Mapper;
+use snesutilities::{RomType, SnesUtils};
+use trace;
+
+// the sa-1's super mmc maps four 1 MiB blocks: 00-1f and c0-cf (cxb), 20-3f and d0-df (dxb),
+// 80-9f and e0-ef (exb), a0-bf and f0-ff (fxb). after reset they are blocks 0-3 in order, so the
+// lorom banks run through the first 4 MiB and c0-ff see the same 4 MiB linearly
+
+const MEBIBYTE: usize = 0x100000;
+const I_RAM_SIZE: usize = 0x800;
+
+// where the snes cpu sets the sa-1's reset, nmi and irq vectors
+const CRV: u32 = 0x2203;
+const CNV: u32 = 0x2205;
+const CIV: u32 = 0x2207;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sa1Info {
+    pub bwram_size: usize,
+    pub iram_size: usize,
+}
+
+// bw-ram is what the header calls sram, i-ram is always 2 KiB inside the chip
+pub fn info(su: &SnesUtils) -> Option<Sa1Info> {
+    if !matches!(su.rom_type, RomType::SA1 | RomType::SA1SRAM) {
+        return None;
+    }
+    Some(Sa1Info {
+        bwram_size: if su.sram_size > 0 && su.sram_size <= 8 { 1024 << su.sram_size } else { 0 },
+        iram_size: I_RAM_SIZE,
+    })
+}
+
+pub fn to_offset(address: u32, size: usize) -> Option<usize> {
+    let bank = (address >> 16) as usize & 0xff;
+    let addr = address as usize & 0xffff;
+    let offset = match bank {
+        0x00..=0x3f | 0x80..=0xbf if addr >= 0x8000 => {
+            let block = (bank >> 5 & 1) | (bank >> 6 & 2);
+            (block * MEBIBYTE) | ((bank & 0x1f) << 15) | (addr & 0x7fff)
+        }
+        0xc0..=0xff => (bank & 0x3f) << 16 | addr,
+        _ => return None,
+    };
+    Some(offset).filter(|offset| *offset < size)
+}
+
+// the lorom banks the blocks are mapped to after reset
+pub fn to_address(offset: usize) -> u32 {
+    let block = (offset / MEBIBYTE) & 3;
+    let bank = (block & 1) << 5 | (block & 2) << 6 | (offset >> 15 & 0x1f);
+    (bank << 16 | 0x8000 | (offset & 0x7fff)) as u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Sa1Vectors {
+    pub reset: Option<u16>,
+    pub nmi: Option<u16>,
+    pub irq: Option<u16>,
+}
+
+impl Sa1Vectors {
+    pub fn all(&self) -> Vec<(&'static str, u16)> {
+        let named = [("sa-1 reset", self.reset), ("sa-1 nmi", self.nmi), ("sa-1 irq", self.irq)];
+        named.iter().filter_map(|&(name, vector)| vector.map(|vector| (name, vector))).collect()
+    }
+}
+
+// the vectors aren't in the header, the snes cpu writes them to crv/cnv/civ before it lets the sa-1
+// run, so they are taken from stores of constants there. the last one written wins
+pub fn find_vectors(data: &[u8], mapper: &Mapper, su: &SnesUtils) -> Sa1Vectors {
+    let cdl = trace::trace(data, mapper, &trace::vector_entries(&su.vectors));
+    let registers = [CRV, CRV + 1, CNV, CNV + 1, CIV, CIV + 1];
+    let mut bytes = [None; 6];
+    for write in trace::register_writes(data, mapper, &cdl, &registers) {
+        let mut store = |register: u32, value: u32| {
+            if let Some(index) = registers.iter().position(|known| *known == register) {
+                bytes[index] = Some(value as u8);
+            }
+        };
+        store(write.register, write.value);
+        if write.wide {
+            store(write.register + 1, write.value >> 8);
+        }
+    }
+    let word = |index: usize| match (bytes[index], bytes[index + 1]) {
+        (Some(low), Some(high)) => Some(low as u16 | (high as u16) << 8),
+        _ => None,
+    };
+    Sa1Vectors {
+        reset: word(0),
+        nmi: word(2),
+        irq: word(4),
+    }
+}
@@ -0,0 +1,253 @@
+use deflate;
+
+// snes9x states are usually gzipped: a "#!s9xsnp:nnnn" line with the snapshot version, then blocks of a
+// three letter name, ':', the decimal length in six digits and ':'. nam holds the rom's file name,
+// ram/vra/sra are wram, vram and sram as they are, cgram is a field of the ppu block and like every
+// multi byte field stored big endian.
+// bsnes states start with "BST1", the serializer version and the rom's crc32 (sha-256 as hex text from
+// higan on) and a 512 byte description, followed by the cartridge ram. the rest isn't tagged and moves
+// between versions, so only those are read
+
+const SNES9X_SIGNATURE: &[u8] = b"#!s9xsnp:";
+const SNES9X_OLD_SIGNATURE: &[u8] = b"#!snes9x:";
+const BSNES_SIGNATURE: &[u8] = b"BST1";
+const DESCRIPTION_SIZE: usize = 512;
+const HASH_SIZE: usize = 64;
+const WRAM_SIZE: usize = 0x20000;
+const VRAM_SIZE: usize = 0x10000;
+const CGRAM_SIZE: usize = 0x200;
+
+// where cgdata sits in snes9x's ppu block: after the vram, bg and mode fields, one byte further on from
+// snapshot version 11 that added the saved cgram byte
+fn snes9x_cgram_offset(version: u32) -> usize {
+    if version >= 11 { 64 } else { 63 }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emulator {
+    Snes9x,
+    Bsnes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Savestate {
+    pub emulator: Emulator,
+    pub version: u32,
+    pub rom_name: Option<String>,
+    pub crc32: Option<u32>,
+    pub sha256: Option<String>,
+    pub description: Option<String>,
+    pub wram: Option<Vec<u8>>,
+    pub vram: Option<Vec<u8>>,
+    pub cgram: Option<Vec<u8>>, // little endian words like the ppu keeps them
+    pub sram: Option<Vec<u8>>,
+}
+
+impl Savestate {
+    fn new(emulator: Emulator, version: u32) -> Savestate {
+        Savestate {
+            emulator,
+            version,
+            rom_name: None,
+            crc32: None,
+            sha256: None,
+            description: None,
+            wram: None,
+            vram: None,
+            cgram: None,
+            sram: None,
+        }
+    }
+}
+
+// sram_size is the rom's declared sram, it says how much of snes9x's sram block is used and how much
+// cartridge ram follows a bsnes header (none is read when it's 0). gzip is undone once, a state is
+// never gzipped twice
+pub fn parse(data: &[u8], sram_size: usize) -> Result<Savestate, String> {
+    let decompressed;
+    let data = if data.starts_with(&[0x1f, 0x8b]) {
+        decompressed = deflate::gzip_decompress(data)?;
+        &decompressed[..]
+    } else {
+        data
+    };
+    if data.starts_with(SNES9X_SIGNATURE) || data.starts_with(SNES9X_OLD_SIGNATURE) {
+        return parse_snes9x(data, sram_size);
+    }
+    if data.starts_with(BSNES_SIGNATURE) {
+        return parse_bsnes(data, sram_size);
+    }
+    Err("not a snes9x or bsnes savestate".to_string())
+}
+
+fn decimal(text: &[u8]) -> Option<u32> {
+    String::from_utf8_lossy(text).trim().parse().ok()
+}
+
+// the blocks as (name, contents)
+fn snes9x_blocks(data: &[u8]) -> Result<Vec<(String, &[u8])>, String> {
+    let mut position = data.iter().position(|byte| *byte == b'\n').ok_or("snes9x state misses its signature line")? + 1;
+    let mut blocks = Vec::new();
+    while position + 11 <= data.len() {
+        let tag = &data[position..position + 11];
+        if tag[3] != b':' || tag[10] != b':' {
+            return Err(format!("broken snes9x block at {:#x}", position));
+        }
+        let length = decimal(&tag[4..10]).ok_or(format!("broken snes9x block length at {:#x}", position))? as usize;
+        let contents = data.get(position + 11..position + 11 + length).ok_or(format!("snes9x block at {:#x} ends early", position))?;
+        blocks.push((String::from_utf8_lossy(&tag[..3]).to_string(), contents));
+        position += 11 + length;
+    }
+    Ok(blocks)
+}
+
+fn parse_snes9x(data: &[u8], sram_size: usize) -> Result<Savestate, String> {
+    let version = data.get(SNES9X_SIGNATURE.len()..SNES9X_SIGNATURE.len() + 4).and_then(decimal).ok_or("broken snes9x snapshot version")?;
+    let mut state = Savestate::new(Emulator::Snes9x, version);
+    for (name, contents) in snes9x_blocks(data)? {
+        match name.as_str() {
+            "NAM" => {
+                let end = contents.iter().position(|byte| *byte == 0).unwrap_or(contents.len());
+                state.rom_name = Some(String::from_utf8_lossy(&contents[..end]).to_string());
+            }
+            "RAM" => state.wram = Some(contents[..contents.len().min(WRAM_SIZE)].to_vec()),
+            "VRA" => state.vram = Some(contents[..contents.len().min(VRAM_SIZE)].to_vec()),
+            "SRA" if sram_size > 0 => state.sram = Some(contents[..contents.len().min(sram_size)].to_vec()),
+            "SRA" => state.sram = Some(contents.to_vec()),
+            "PPU" => state.cgram = snes9x_cgram(contents, version),
+            _ => {}
+        }
+    }
+    Ok(state)
+}
+
+// colors only have 15 bits, a set top bit means the offset doesn't fit this snes9x's layout
+fn snes9x_cgram(ppu: &[u8], version: u32) -> Option<Vec<u8>> {
+    let offset = snes9x_cgram_offset(version);
+    let words = ppu.get(offset..offset + CGRAM_SIZE)?;
+    if words.chunks(2).any(|word| word[0] & 0x80 != 0) {
+        return None;
+    }
+    Some(words.chunks(2).flat_map(|word| vec![word[1], word[0]]).collect())
+}
+
+fn parse_bsnes(data: &[u8], sram_size: usize) -> Result<Savestate, String> {
+    let word = |at: usize| data[at] as u32 | (data[at + 1] as u32) << 8 | (data[at + 2] as u32) << 16 | (data[at + 3] as u32) << 24;
+    if data.len() < 12 + DESCRIPTION_SIZE {
+        return Err("bsnes state ends in its header".to_string());
+    }
+    let mut state = Savestate::new(Emulator::Bsnes, word(4));
+    let hashed = data.len() >= 8 + HASH_SIZE + DESCRIPTION_SIZE && data[8..8 + HASH_SIZE].iter().all(|byte| byte.is_ascii_hexdigit());
+    let description = if hashed {
+        state.sha256 = Some(String::from_utf8_lossy(&data[8..8 + HASH_SIZE]).to_string());
+        8 + HASH_SIZE
+    } else {
+        state.crc32 = Some(word(8));
+        12
+    };
+    let text = &data[description..description + DESCRIPTION_SIZE];
+    let end = text.iter().position(|byte| *byte == 0).unwrap_or(text.len());
+    state.description = Some(String::from_utf8_lossy(&text[..end]).to_string());
+    let sram = description + DESCRIPTION_SIZE;
+    if sram_size > 0 {
+        state.sram = Some(data.get(sram..sram + sram_size).ok_or("bsnes state ends before the cartridge ram")?.to_vec());
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crc32;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut file = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+        file.extend(deflate::deflate(data));
+        for value in &[crc32::crc32(data), data.len() as u32] {
+            file.extend_from_slice(&[*value as u8, (*value >> 8) as u8, (*value >> 16) as u8, (*value >> 24) as u8]);
+        }
+        file
+    }
+
+    fn snes9x(version: u32, blocks: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut file = format!("#!s9xsnp:{:04}\n", version).into_bytes();
+        for (name, contents) in blocks {
+            file.extend(format!("{}:{:06}:", name, contents.len()).into_bytes());
+            file.extend_from_slice(contents);
+        }
+        file
+    }
+
+    // a ppu block with the colors big endian where that version keeps them
+    fn ppu(version: u32, colors: &[u16]) -> Vec<u8> {
+        let mut block = vec![0u8; snes9x_cgram_offset(version) + CGRAM_SIZE + 16];
+        for (index, color) in colors.iter().enumerate() {
+            let at = snes9x_cgram_offset(version) + index * 2;
+            block[at..at + 2].copy_from_slice(&[(*color >> 8) as u8, *color as u8]);
+        }
+        block
+    }
+
+    #[test]
+    fn snes9x_blocks_and_cgram() {
+        let mut name = b"Game.sfc".to_vec();
+        name.extend_from_slice(&[0; 8]);
+        let file = snes9x(11, &[("NAM", name), ("CPU", vec![1; 20]), ("RAM", vec![2; 0x20000]), ("VRA", vec![3; 0x10000]), ("PPU", ppu(11, &[0x7fff, 0x001f])), ("SRA", vec![4; 0x20000])]);
+        let state = parse(&file, 0x2000).unwrap();
+        assert_eq!((state.emulator, state.version), (Emulator::Snes9x, 11));
+        assert_eq!(state.rom_name, Some("Game.sfc".to_string()));
+        assert_eq!(state.wram, Some(vec![2; WRAM_SIZE]));
+        assert_eq!(state.vram, Some(vec![3; VRAM_SIZE]));
+        assert_eq!(state.sram, Some(vec![4; 0x2000]));
+        let cgram = state.cgram.unwrap();
+        assert_eq!(cgram.len(), CGRAM_SIZE);
+        assert_eq!(&cgram[..6], &[0xff, 0x7f, 0x1f, 0x00, 0x00, 0x00]);
+        assert_eq!(parse(&gzip(&file), 0x2000).unwrap(), parse(&file, 0x2000).unwrap());
+
+        // before version 11 the colors sit a byte earlier
+        let old = parse(&snes9x(10, &[("PPU", ppu(10, &[0x1234]))]), 0).unwrap();
+        assert_eq!(&old.cgram.unwrap()[..2], &[0x34, 0x12]);
+        // read with the wrong layout the top bits are set, so there are no colors rather than wrong ones
+        assert_eq!(parse(&snes9x(11, &[("PPU", ppu(10, &[0x00ff; 256]))]), 0).unwrap().cgram, None);
+    }
+
+    #[test]
+    fn broken_snes9x_states() {
+        assert!(parse(b"#!s9xsnp:00", 0).is_err());
+        assert!(parse(b"#!s9xsnp:0011", 0).is_err());
+        let mut file = snes9x(11, &[("RAM", vec![0; 16])]);
+        file.truncate(file.len() - 1);
+        assert!(parse(&file, 0).is_err());
+        assert!(parse(b"#!s9xsnp:0011\nRAM-000016:", 0).is_err());
+        assert!(parse(b"#!s9xsnp:0011\nRAM:00x016:", 0).is_err());
+        // gzip inside gzip isn't opened a second time
+        assert!(parse(&gzip(&gzip(&snes9x(11, &[]))), 0).is_err());
+    }
+
+    #[test]
+    fn bsnes_headers() {
+        let header = |hash: &[u8]| {
+            let mut file = b"BST1".to_vec();
+            file.extend_from_slice(&[0x05, 0x01, 0x00, 0x00]);
+            file.extend_from_slice(hash);
+            let mut description = b"before the boss".to_vec();
+            description.resize(DESCRIPTION_SIZE, 0);
+            file.extend(description);
+            file.extend_from_slice(&[0x5a; 0x800]);
+            file
+        };
+        let crc = parse(&header(&[0x78, 0x56, 0x34, 0x12]), 0x800).unwrap();
+        assert_eq!((crc.emulator, crc.version, crc.crc32, crc.sha256.clone()), (Emulator::Bsnes, 0x105, Some(0x12345678), None));
+        assert_eq!(crc.description, Some("before the boss".to_string()));
+        assert_eq!(crc.sram, Some(vec![0x5a; 0x800]));
+
+        let sha = "a".repeat(HASH_SIZE);
+        let hashed = parse(&header(sha.as_bytes()), 0x800).unwrap();
+        assert_eq!((hashed.crc32, hashed.sha256), (None, Some(sha)));
+        assert_eq!(hashed.description, Some("before the boss".to_string()));
+        assert_eq!(hashed.sram, Some(vec![0x5a; 0x800]));
+
+        assert!(parse(&header(&[0x78, 0x56, 0x34, 0x12]), 0x1000).is_err());
+        assert!(parse(&header(&[0x78, 0x56, 0x34, 0x12])[..0x100], 0).is_err());
+    }
+}
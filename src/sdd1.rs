@@ -0,0 +1,355 @@
+use checksum;
+use compression::Decompressed;
+use mapper::Mapper;
+use snesutilities::{self, RomMakupType, RomType};
+
+// the s-dd1 decompresses while the cpu dmas from rom. the first byte's upper nibble picks the bitplane
+// layout (00 2bpp, 40 8bpp, 80 4bpp, c0 mode 7) and which earlier bits form the context, the rest is
+// a bit stream read by eight golomb run length decoders. every context adapts its probability state
+// through a 33 entry table. there is no end marker, the dma length says how much comes out
+
+// (code number, next state after the more probable symbol, after the less probable one)
+const EVOLUTION: [(u8, u8, u8); 33] = [
+    (0, 25, 25), (0, 2, 1), (0, 3, 1), (0, 4, 2), (0, 5, 3),
+    (1, 6, 4), (1, 7, 5), (1, 8, 6), (1, 9, 7), (2, 10, 8),
+    (2, 11, 9), (2, 12, 10), (2, 13, 11), (3, 14, 12), (3, 15, 13),
+    (3, 16, 14), (3, 17, 15), (4, 18, 16), (4, 19, 17), (5, 20, 18),
+    (5, 21, 19), (6, 22, 20), (6, 23, 21), (7, 24, 22), (7, 24, 23),
+    (0, 26, 1), (1, 27, 2), (2, 28, 4), (3, 29, 8), (4, 30, 12),
+    (5, 31, 16), (6, 32, 18), (7, 24, 22),
+];
+
+struct Input<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u32,
+}
+
+impl<'a> Input<'a> {
+    fn byte(&self, position: usize) -> u32 {
+        self.data.get(position).cloned().unwrap_or(0) as u32
+    }
+
+    // a golomb code word: a 0 bit alone, or a 1 followed by code number more bits
+    fn code_word(&mut self, code_number: u32) -> u8 {
+        let mut word = self.byte(self.position) << self.bit;
+        self.bit += 1;
+        if word & 0x80 != 0 {
+            word |= self.byte(self.position + 1) >> (9 - self.bit);
+            self.bit += code_number;
+        }
+        if self.bit & 8 != 0 {
+            self.position += 1;
+            self.bit &= 7;
+        }
+        word as u8
+    }
+}
+
+// how many more probable symbols precede the less probable one, the bits after the leading 1 inverted
+// and reversed
+fn run_count(bits: u8) -> u8 {
+    if bits < 2 {
+        return 0;
+    }
+    let length = 7 - bits.leading_zeros();
+    (0..length).fold(0, |count, bit| count << 1 | (!bits >> bit) & 1)
+}
+
+#[derive(Clone, Copy, Default)]
+struct Generator {
+    mps_count: u32,
+    lps: bool,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Context {
+    status: u8,
+    mps: u8,
+}
+
+pub struct Decoder<'a> {
+    input: Input<'a>,
+    generators: [Generator; 8],
+    contexts: [Context; 32],
+    bitplanes: u8,
+    context_bits: u8,
+    plane: usize,
+    bit_number: u32,
+    previous: [u16; 8],
+    pending: Option<u8>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8], offset: usize) -> Decoder<'a> {
+        let header = data.get(offset).cloned().unwrap_or(0);
+        Decoder {
+            input: Input { data, position: offset, bit: 4 },
+            generators: [Generator::default(); 8],
+            contexts: [Context::default(); 32],
+            bitplanes: header & 0xc0,
+            context_bits: header & 0x30,
+            plane: match header & 0xc0 {
+                0x40 => 7,
+                0x80 => 3,
+                _ => 1,
+            },
+            bit_number: 0,
+            previous: [0; 8],
+            pending: None,
+        }
+    }
+
+    fn generator_bit(&mut self, code_number: usize) -> (u8, bool) {
+        let generator = &mut self.generators[code_number];
+        if generator.mps_count == 0 && !generator.lps {
+            let word = self.input.code_word(code_number as u32);
+            if word & 0x80 != 0 {
+                generator.lps = true;
+                generator.mps_count = run_count(word >> (code_number ^ 7)) as u32;
+            } else {
+                generator.mps_count = 1 << code_number;
+            }
+        }
+        let bit = if generator.mps_count > 0 {
+            generator.mps_count -= 1;
+            0
+        } else {
+            generator.lps = false;
+            1
+        };
+        (bit, generator.mps_count == 0 && !generator.lps)
+    }
+
+    fn probability_bit(&mut self, context: usize) -> u8 {
+        let Context { status, mps } = self.contexts[context];
+        let (code_number, next_mps, next_lps) = EVOLUTION[status as usize];
+        let (bit, end_of_run) = self.generator_bit(code_number as usize);
+        if end_of_run {
+            let state = &mut self.contexts[context];
+            if bit == 1 {
+                if status < 2 {
+                    state.mps ^= 1;
+                }
+                state.status = next_lps;
+            } else {
+                state.status = next_mps;
+            }
+        }
+        bit ^ mps
+    }
+
+    fn context_bit(&mut self) -> u8 {
+        match self.bitplanes {
+            0x00 => self.plane ^= 1,
+            0x40 => {
+                self.plane ^= 1;
+                if self.bit_number & 0x7f == 0 {
+                    self.plane = (self.plane + 2) & 7;
+                }
+            }
+            0x80 => {
+                self.plane ^= 1;
+                if self.bit_number & 0x7f == 0 {
+                    self.plane ^= 2;
+                }
+            }
+            _ => self.plane = self.bit_number as usize & 7,
+        }
+        let bits = self.previous[self.plane];
+        let context = ((self.plane & 1) << 4) as u16
+            | match self.context_bits {
+                0x00 => (bits & 0x01c0) >> 5 | (bits & 0x0001),
+                0x10 => (bits & 0x0180) >> 5 | (bits & 0x0001),
+                0x20 => (bits & 0x00c0) >> 5 | (bits & 0x0001),
+                _ => (bits & 0x0180) >> 5 | (bits & 0x0003),
+            };
+        let bit = self.probability_bit(context as usize);
+        self.previous[self.plane] = bits << 1 | bit as u16;
+        self.bit_number = self.bit_number.wrapping_add(1);
+        bit
+    }
+
+    // planar layouts come out as pairs of bytes decoded together, mode 7 a byte at a time lsb first
+    pub fn next_byte(&mut self) -> u8 {
+        if let Some(byte) = self.pending.take() {
+            return byte;
+        }
+        if self.bitplanes == 0xc0 {
+            return (0..8).fold(0, |byte, bit| byte | self.context_bit() << bit);
+        }
+        let (mut low, mut high) = (0, 0);
+        for bit in (0..8).rev() {
+            low |= self.context_bit() << bit;
+            high |= self.context_bit() << bit;
+        }
+        self.pending = Some(high);
+        low
+    }
+
+    // bytes of input used so far, a partly used byte counts
+    pub fn consumed(&self, offset: usize) -> usize {
+        self.input.position - offset + if self.input.bit > 0 { 1 } else { 0 }
+    }
+}
+
+pub fn decompress(data: &[u8], offset: usize, length: usize) -> Result<Decompressed, String> {
+    if offset >= data.len() {
+        return Err("offset past the end of the data".to_string());
+    }
+    let mut decoder = Decoder::new(data, offset);
+    let output = (0..length).map(|_| decoder.next_byte()).collect();
+    Ok(Decompressed {
+        data: output,
+        consumed: decoder.consumed(offset),
+    })
+}
+
+// banks c0-ff go through the mmc's four 1 MiB windows, which start out as the first 4 MiB in order;
+// the rest is plain lorom even though the header says 0x32
+pub fn to_offset(address: u32, size: usize) -> Option<usize> {
+    if address >> 16 >= 0xc0 {
+        Some(address as usize & 0x3fffff).filter(|offset| *offset < size)
+    } else {
+        Mapper { makeup: RomMakupType::LoROM, rom_type: RomType::SDD1, size }.to_offset(address)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnpackedBlock {
+    pub address: u32,
+    pub compressed_size: usize,
+    pub offset: usize, // where the decompressed data went in the unpacked image
+    pub size: usize,
+}
+
+// appends every block decompressed after the rom, which is first padded to a whole mebibyte so the
+// blocks can be reached through an mmc window. the size byte and checksum are updated, pointing the
+// game at the new data is up to the patches made for it
+pub fn unpack(data: &[u8], blocks: &[(u32, usize)]) -> Result<(Vec<u8>, Vec<UnpackedBlock>), String> {
+    let mut image = data.to_vec();
+    image.resize(data.len().div_ceil(0x100000) * 0x100000, 0x00);
+    let mut unpacked = Vec::new();
+    for (address, length) in blocks {
+        let offset = to_offset(*address, data.len()).ok_or(format!("{:06x} isn't in rom", address))?;
+        let block = decompress(data, offset, *length)?;
+        unpacked.push(UnpackedBlock {
+            address: *address,
+            compressed_size: block.consumed,
+            offset: image.len(),
+            size: block.data.len(),
+        });
+        image.extend(block.data);
+    }
+    image.resize(image.len().div_ceil(0x100000) * 0x100000, 0x00);
+    let header = snesutilities::find_header(&image);
+    image[header + 0x17] = (image.len() / 1024).next_power_of_two().trailing_zeros() as u8;
+    checksum::fix_checksum(&mut image, header);
+    Ok((image, unpacked))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the same stream under each bitplane layout, expected output from the reference decoder (sdd1emu).
+    // 4bpp and 8bpp only part ways after 256 bits; the stream runs out early and reads on as zeros
+    const STREAM: [u8; 31] = [
+        0x95, 0xc4, 0x17, 0x8e, 0x62, 0xf0, 0x29, 0xbd, 0x53, 0x07, 0xe8, 0x71, 0x9c, 0x46, 0xab, 0x5d,
+        0x20, 0xe3, 0xf6, 0x0c, 0x8b, 0x47, 0x91, 0xd2, 0xa7, 0x6e, 0x1f, 0xf3, 0x58, 0x04, 0xbc,
+    ];
+
+    fn block(header: u8) -> Vec<u8> {
+        let mut block = vec![header];
+        block.extend_from_slice(&STREAM);
+        block
+    }
+
+    #[test]
+    fn bitplane_layouts() {
+        let cases: [(u8, [u8; 64]); 4] = [
+            // 2bpp
+            (0x0a, [
+                0xd7, 0x00, 0x90, 0x00, 0x69, 0x50, 0xab, 0xc7, 0x57, 0x76, 0x57, 0xee, 0x52, 0x2d, 0x5c, 0xeb,
+                0x48, 0xee, 0x72, 0xdd, 0x8a, 0x4b, 0xc2, 0x3a, 0xa4, 0x2f, 0x82, 0xff, 0x7c, 0xf3, 0x17, 0x8b,
+                0x61, 0x9b, 0x29, 0x0a, 0xcf, 0x18, 0xbf, 0x30, 0x01, 0x60, 0xa8, 0xc1, 0xac, 0x83, 0xa7, 0x06,
+                0x3e, 0x0c, 0xfc, 0x18, 0x06, 0x30, 0xa2, 0x60, 0xb2, 0xc1, 0x9c, 0x83, 0xfb, 0x06, 0xf0, 0x0c,
+            ]),
+            // 4bpp
+            (0x8a, [
+                0xd7, 0x00, 0x90, 0x00, 0x69, 0x50, 0xab, 0xc7, 0x57, 0x76, 0x57, 0xee, 0x52, 0x2d, 0x5c, 0xeb,
+                0x4e, 0x24, 0xb8, 0x40, 0x46, 0x20, 0xa8, 0x00, 0xab, 0xb1, 0xce, 0x88, 0x7f, 0x39, 0x38, 0xdf,
+                0x8b, 0xe0, 0x50, 0x30, 0x5a, 0x18, 0x82, 0x0c, 0xd4, 0x06, 0x16, 0x03, 0xa0, 0x01, 0xb5, 0x80,
+                0xf5, 0xbf, 0xe4, 0x7e, 0x1a, 0xfd, 0xb2, 0xfb, 0x8c, 0xf7, 0xdb, 0xef, 0x80, 0xdf, 0x55, 0xbf,
+            ]),
+            // 8bpp
+            (0x4a, [
+                0xd7, 0x00, 0x90, 0x00, 0x69, 0x50, 0xab, 0xc7, 0x57, 0x76, 0x57, 0xee, 0x52, 0x2d, 0x5c, 0xeb,
+                0x4e, 0x24, 0xb8, 0x40, 0x46, 0x20, 0xa8, 0x00, 0xab, 0xb1, 0xce, 0x88, 0x7f, 0x39, 0x38, 0xdf,
+                0xb1, 0x02, 0x0a, 0x00, 0xb2, 0x00, 0x8c, 0x00, 0xdb, 0x00, 0x80, 0x00, 0x55, 0x00, 0x94, 0x00,
+                0xab, 0x00, 0x28, 0x00, 0xcd, 0x00, 0xb8, 0x00, 0x05, 0x00, 0x59, 0x00, 0x46, 0x00, 0x6d, 0x00,
+            ]),
+            // mode 7
+            (0xca, [
+                0x01, 0x45, 0x00, 0x80, 0xa7, 0x01, 0x3a, 0xa4, 0xec, 0xe5, 0x34, 0xba, 0xb8, 0x89, 0x83, 0x56,
+                0xa7, 0xe2, 0x5e, 0x48, 0x12, 0x50, 0x75, 0xcc, 0xca, 0x4d, 0x74, 0x29, 0x14, 0x62, 0x9f, 0x92,
+                0x8a, 0xbe, 0xd2, 0x82, 0x82, 0x88, 0x10, 0x00, 0x20, 0x10, 0x00, 0x00, 0x08, 0x00, 0x00, 0x20,
+                0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x20, 0x00, 0x00,
+            ]),
+        ];
+        for (header, expected) in cases.iter() {
+            let block = decompress(&block(*header), 0, 64).unwrap();
+            assert_eq!(block.data[..], expected[..], "{:02x}", header);
+        }
+        assert_eq!(decompress(&block(0x0a), 0, 64).unwrap().consumed, 46);
+        assert!(decompress(&[0x0a], 1, 64).is_err());
+    }
+
+    #[test]
+    fn zeros_decode_to_zeros() {
+        for header in &[0x00, 0x40, 0x80, 0xc0, 0x30] {
+            assert_eq!(decompress(&[*header, 0, 0, 0], 0, 32).unwrap().data, vec![0; 32]);
+        }
+    }
+
+    #[test]
+    fn run_counts() {
+        let table: [u8; 256] = [
+            0x00, 0x00, 0x01, 0x00, 0x03, 0x01, 0x02, 0x00, 0x07, 0x03, 0x05, 0x01, 0x06, 0x02, 0x04, 0x00,
+            0x0f, 0x07, 0x0b, 0x03, 0x0d, 0x05, 0x09, 0x01, 0x0e, 0x06, 0x0a, 0x02, 0x0c, 0x04, 0x08, 0x00,
+            0x1f, 0x0f, 0x17, 0x07, 0x1b, 0x0b, 0x13, 0x03, 0x1d, 0x0d, 0x15, 0x05, 0x19, 0x09, 0x11, 0x01,
+            0x1e, 0x0e, 0x16, 0x06, 0x1a, 0x0a, 0x12, 0x02, 0x1c, 0x0c, 0x14, 0x04, 0x18, 0x08, 0x10, 0x00,
+            0x3f, 0x1f, 0x2f, 0x0f, 0x37, 0x17, 0x27, 0x07, 0x3b, 0x1b, 0x2b, 0x0b, 0x33, 0x13, 0x23, 0x03,
+            0x3d, 0x1d, 0x2d, 0x0d, 0x35, 0x15, 0x25, 0x05, 0x39, 0x19, 0x29, 0x09, 0x31, 0x11, 0x21, 0x01,
+            0x3e, 0x1e, 0x2e, 0x0e, 0x36, 0x16, 0x26, 0x06, 0x3a, 0x1a, 0x2a, 0x0a, 0x32, 0x12, 0x22, 0x02,
+            0x3c, 0x1c, 0x2c, 0x0c, 0x34, 0x14, 0x24, 0x04, 0x38, 0x18, 0x28, 0x08, 0x30, 0x10, 0x20, 0x00,
+            0x7f, 0x3f, 0x5f, 0x1f, 0x6f, 0x2f, 0x4f, 0x0f, 0x77, 0x37, 0x57, 0x17, 0x67, 0x27, 0x47, 0x07,
+            0x7b, 0x3b, 0x5b, 0x1b, 0x6b, 0x2b, 0x4b, 0x0b, 0x73, 0x33, 0x53, 0x13, 0x63, 0x23, 0x43, 0x03,
+            0x7d, 0x3d, 0x5d, 0x1d, 0x6d, 0x2d, 0x4d, 0x0d, 0x75, 0x35, 0x55, 0x15, 0x65, 0x25, 0x45, 0x05,
+            0x79, 0x39, 0x59, 0x19, 0x69, 0x29, 0x49, 0x09, 0x71, 0x31, 0x51, 0x11, 0x61, 0x21, 0x41, 0x01,
+            0x7e, 0x3e, 0x5e, 0x1e, 0x6e, 0x2e, 0x4e, 0x0e, 0x76, 0x36, 0x56, 0x16, 0x66, 0x26, 0x46, 0x06,
+            0x7a, 0x3a, 0x5a, 0x1a, 0x6a, 0x2a, 0x4a, 0x0a, 0x72, 0x32, 0x52, 0x12, 0x62, 0x22, 0x42, 0x02,
+            0x7c, 0x3c, 0x5c, 0x1c, 0x6c, 0x2c, 0x4c, 0x0c, 0x74, 0x34, 0x54, 0x14, 0x64, 0x24, 0x44, 0x04,
+            0x78, 0x38, 0x58, 0x18, 0x68, 0x28, 0x48, 0x08, 0x70, 0x30, 0x50, 0x10, 0x60, 0x20, 0x40, 0x00,
+        ];
+        for (bits, count) in table.iter().enumerate() {
+            assert_eq!(run_count(bits as u8), *count, "{:02x}", bits);
+        }
+    }
+
+    #[test]
+    fn unpack_blocks() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x7fd7] = 0x05;
+        rom[0x1000..0x1020].copy_from_slice(&block(0x0a));
+        let (image, blocks) = unpack(&rom, &[(0xc01000, 64)]).unwrap();
+        assert_eq!(image.len(), 0x200000);
+        assert_eq!(blocks, vec![UnpackedBlock { address: 0xc01000, compressed_size: 46, offset: 0x100000, size: 64 }]);
+        assert_eq!(image[0x100000..0x100040], decompress(&rom, 0x1000, 64).unwrap().data[..]);
+        assert_eq!(image[0x7fd7], 11);
+        let (complement, sum) = checksum::read_checksum(&image, 0x7fc0).unwrap();
+        assert_eq!(sum, checksum::compute_checksum(&image));
+        assert_eq!(complement, !sum);
+        assert!(unpack(&rom, &[(0xc10000, 64)]).is_err());
+    }
+}
@@ -1,20 +1,32 @@
 use std::io::Read;
 use std::fs::File;
+use std::io::Cursor;
 use std::io::Seek;
 use std::io::SeekFrom;
-use std::str;
+use bsx::{self, BsxHeader};
+use checksum;
+use copier;
+use sufami::{self, SufamiTurboHeader};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RomMakupType {
     LoROM = 32, // 32 // 32704
     HiROM = 33, // 33 // 65472
+    LoROMSA1 = 35, // 35
     LoROMFastROM = 48, // 48
     HiROMFastROM = 49, // 49
     ExLoROM = 50, // 50
     ExHiROM = 53, // 53
+    HiROMSPC7110 = 58, // 58
     Unknown,
 }
-#[derive(Debug)]
+impl RomMakupType {
+    pub fn is_hirom(&self) -> bool {
+        matches!(*self, RomMakupType::HiROM | RomMakupType::HiROMFastROM | RomMakupType::ExHiROM | RomMakupType::HiROMSPC7110)
+    }
+}
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RomType {
     ROM = 0,
     ROMRAM = 1,
@@ -23,13 +35,74 @@ pub enum RomType {
     ROMDSP1RAM = 4,
     ROMDSP1SRAM = 5,
     FX = 6,
+    GSU = 0x13,
+    GSURAM = 0x14,
+    GSUSRAM = 0x15,
+    GSUSRAMBattery = 0x1a,
+    SA1 = 0x34,
+    SA1SRAM = 0x35,
+    SDD1 = 0x43,
+    SDD1SRAM = 0x45,
+    SRTC = 0x55,
+    SPC7110 = 0xf5,
+    SPC7110RTC = 0xf9,
+    SGB = 0xe3,
     Unknown,
 }
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct VideoMode {
     pub country: String,
     pub mode: String,
 }
+// interrupt vectors at the end of the header, native mode first (0xffe4) then emulation mode (0xfff4)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Vectors {
+    pub native_cop: u16,
+    pub native_brk: u16,
+    pub native_abort: u16,
+    pub native_nmi: u16,
+    pub native_irq: u16,
+    pub emulation_cop: u16,
+    pub emulation_abort: u16,
+    pub emulation_nmi: u16,
+    pub reset: u16,
+    pub emulation_irq: u16,
+}
+impl Vectors {
+    pub fn all(&self) -> Vec<(&'static str, u16)> {
+        vec![("reset", self.reset),
+             ("nmi", self.native_nmi),
+             ("irq", self.native_irq),
+             ("brk", self.native_brk),
+             ("cop", self.native_cop),
+             ("abort", self.native_abort),
+             ("emulation nmi", self.emulation_nmi),
+             ("emulation irq", self.emulation_irq),
+             ("emulation cop", self.emulation_cop),
+             ("emulation abort", self.emulation_abort)]
+    }
+}
+// the header extension at 0x7fb0/0xffb0, there when the old license byte is 0x33
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtendedHeader {
+    pub maker_code: String,
+    pub game_code: String,
+    pub expansion_flash_size: u8, // 1 KiB << n, like the other sizes
+    pub expansion_ram_size: u8,
+    pub special_version: u8,
+    pub cartridge_subtype: u8,
+}
+// images that aren't plain cartridges, told apart so they aren't taken for broken ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Media {
+    Cartridge,
+    Satellaview,
+    SufamiTurbo,
+    SufamiTurboBase,
+    SuperGameBoy,
+    SuperGameBoy2,
+}
+#[derive(Debug, Clone)]
 pub struct SnesUtils {
     pub internal_name: String,
     pub rom_makeup_type: RomMakupType,
@@ -38,42 +111,188 @@ pub struct SnesUtils {
     pub sram_size: u8,
     pub video_mode: VideoMode,
     pub license: String,
+    pub header_offset: usize,
+    pub vectors: Vectors,
+    pub extended_header: Option<ExtendedHeader>,
+    pub bsx_header: Option<BsxHeader>,
+    pub sufami_turbo_header: Option<SufamiTurboHeader>,
 }
 impl SnesUtils {
     pub fn new(file_name: String) -> SnesUtils {
-        let mut file = &mut File::open(file_name).unwrap(); // load the file
+        let mut data = Vec::new();
+        File::open(file_name).unwrap().read_to_end(&mut data).unwrap(); // load the file
+        SnesUtils::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> SnesUtils {
+        let body = copier::strip_copier_header(data); // skip a smc/swc/fig header
+        if body.len() < 0x8000 {
+            return SnesUtils::unknown(); // not even a lorom header fits
+        }
+        let file = &mut Cursor::new(body);
         let internal_name = read_file(file); // returns the internal name
+        let header_offset = (file.position() as usize).saturating_sub(21); // read_file leaves us right after the name
         let mut buffer = [0; 1]; // create initial buffer
-        buffer_readnext(&mut file, &mut buffer); // read rom makeup byte
+        buffer_readnext(file, &mut buffer); // read rom makeup byte
         let rom_makeup_type = get_rom_makeup_type(buffer); // get rom makeup type
-        buffer_readnext(&mut file, &mut buffer); // read rom type byte
+        buffer_readnext(file, &mut buffer); // read rom type byte
         let rom_type = get_rom_type(buffer); // get rom type
-        buffer_readnext(&mut file, &mut buffer); // read rom size byte
+        buffer_readnext(file, &mut buffer); // read rom size byte
         let rom_size = buffer[0]; // get romsize
-        buffer_readnext(&mut file, &mut buffer); // read sram size byte
+        buffer_readnext(file, &mut buffer); // read sram size byte
         let sram_size = buffer[0]; // get sram size
-        buffer_readnext(&mut file, &mut buffer); // read video mode byte
+        buffer_readnext(file, &mut buffer); // read video mode byte
         let video_mode = get_location(buffer); // get video mode
-        buffer_readnext(&mut file, &mut buffer); // read license byte
+        buffer_readnext(file, &mut buffer); // read license byte
         let license = LICENSES[buffer[0] as usize].to_string(); // get developer license
+        let extended_header = if buffer[0] == 0x33 { read_extended_header(file, header_offset) } else { None };
+        let vectors = read_vectors(file, header_offset); // get interrupt vectors
+        if let Some((offset, header)) = bsx::find_header(file.get_ref()).filter(|found| header_score(file.get_ref(), found.0 + 0x10) >= header_score(file.get_ref(), header_offset)) {
+            return SnesUtils::from_bsx_header(file, offset, header);
+        }
+        if let Some(header) = sufami::read_header(file.get_ref()) {
+            return SnesUtils::from_sufami_turbo_header(header);
+        }
 
         SnesUtils {
-            internal_name: internal_name,
-            rom_makeup_type: rom_makeup_type,
-            rom_type: rom_type,
-            rom_size: rom_size,
-            sram_size: sram_size,
-            video_mode: video_mode,
-            license: license,
+            internal_name,
+            rom_makeup_type,
+            rom_type,
+            rom_size,
+            sram_size,
+            video_mode,
+            license,
+            header_offset,
+            vectors,
+            extended_header,
+            bsx_header: None,
+            sufami_turbo_header: None,
         }
     }
+
+    // what a file too short for any header reports
+    fn unknown() -> SnesUtils {
+        SnesUtils {
+            internal_name: String::new(),
+            rom_makeup_type: RomMakupType::Unknown,
+            rom_type: RomType::Unknown,
+            rom_size: 0,
+            sram_size: 0,
+            video_mode: get_location([0xff]),
+            license: "Unknown".to_string(),
+            header_offset: 0x7fc0,
+            vectors: Vectors::default(),
+            extended_header: None,
+            bsx_header: None,
+            sufami_turbo_header: None,
+        }
+    }
+
+    // the fields a satellaview header doesn't have are filled in the way the bs-x bios sees the file
+    fn from_bsx_header<R: Read + Seek>(file: &mut R, offset: usize, header: BsxHeader) -> SnesUtils {
+        let header_offset = offset + 0x10;
+        let license = u8::from_str_radix(&header.maker_code, 16).ok().and_then(|code| LICENSES.get(code as usize)).unwrap_or(&"Unknown");
+        SnesUtils {
+            internal_name: header.title.clone(),
+            rom_makeup_type: get_rom_makeup_type([header.map_mode]),
+            rom_type: RomType::ROM,
+            rom_size: (header.size() / 1024).next_power_of_two().trailing_zeros() as u8,
+            sram_size: 0,
+            video_mode: get_location([0]),
+            license: license.to_string(),
+            header_offset,
+            vectors: read_vectors(file, header_offset),
+            extended_header: None,
+            bsx_header: Some(header),
+            sufami_turbo_header: None,
+        }
+    }
+
+    // sizes are turned into the usual 1 KiB << n, the vectors are those the base cassette jumps through
+    fn from_sufami_turbo_header(header: SufamiTurboHeader) -> SnesUtils {
+        let kibibytes = |size: usize| if size == 0 { 0 } else { (size / 1024).next_power_of_two().trailing_zeros() as u8 };
+        SnesUtils {
+            internal_name: header.title.clone(),
+            rom_makeup_type: if header.fast_rom { RomMakupType::LoROMFastROM } else { RomMakupType::LoROM },
+            rom_type: if header.sram_size > 0 { RomType::ROMSRAM } else { RomType::ROM },
+            rom_size: kibibytes(header.rom_size),
+            sram_size: kibibytes(header.sram_size),
+            video_mode: get_location([0]),
+            license: "Bandai".to_string(),
+            header_offset: 0,
+            vectors: Vectors {
+                native_cop: header.cop as u16,
+                native_brk: header.brk as u16,
+                native_abort: header.abort as u16,
+                native_nmi: header.nmi as u16,
+                native_irq: header.irq as u16,
+                reset: header.entry as u16,
+                ..Vectors::default()
+            },
+            extended_header: None,
+            bsx_header: None,
+            sufami_turbo_header: Some(header),
+        }
+    }
+
+    pub fn media(&self) -> Media {
+        let sgb = self.rom_type == RomType::SGB || self.internal_name.starts_with("Super GAMEBOY");
+        if self.bsx_header.is_some() {
+            Media::Satellaview
+        } else if self.sufami_turbo_header.is_some() {
+            Media::SufamiTurbo
+        } else if self.internal_name.starts_with("ADD-ON BASE CASSETE") {
+            Media::SufamiTurboBase
+        } else if sgb && self.internal_name.starts_with("Super GAMEBOY2") {
+            Media::SuperGameBoy2
+        } else if sgb {
+            Media::SuperGameBoy
+        } else {
+            Media::Cartridge
+        }
+    }
+}
+
+// rates how much the bytes at offset look like an internal header (title at 0x7fc0/0xffc0/0x40ffc0)
+pub fn header_score(data: &[u8], offset: usize) -> i32 {
+    let header = match data.get(offset..offset + 0x40) {
+        Some(header) => header,
+        None => return -1,
+    };
+    let mut score = 0;
+    if let Some((complement, checksum)) = checksum::read_checksum(data, offset) {
+        if complement ^ checksum == 0xFFFF {
+            score += 2;
+        }
+    }
+    if header[..21].iter().all(|byte| *byte >= 0x20 && *byte < 0x7f) {
+        score += 1;
+    }
+    if get_rom_makeup_type([header[0x15]]) != RomMakupType::Unknown || (offset >= 0x10 && bsx::is_header(data, offset - 0x10)) {
+        score += 1;
+    }
+    if header[0x3d] >= 0x80 {
+        score += 1; // reset vector points into rom
+    }
+    score
+}
+
+// offset of the internal header that scores best, lorom (0x7fc0), hirom (0xffc0) or exhirom (0x40ffc0)
+pub fn find_header(data: &[u8]) -> usize {
+    let mut best = 0x7fc0;
+    for offset in &[0xffc0, 0x40ffc0] {
+        if header_score(data, *offset) > header_score(data, best) {
+            best = *offset;
+        }
+    }
+    best
 }
 
 #[allow(unused_must_use)]
-fn read_file(file: &mut File) -> String {
+fn read_file<R: Read + Seek>(file: &mut R) -> String {
     let mut vec = vec![0u8; 21];
     file.seek(SeekFrom::Start(32704));
-    file.read(vec.as_mut_slice()).unwrap();
+    file.read_exact(vec.as_mut_slice());
     let mut is_lo_rom = true;
     for byte in vec.iter() {
         if *byte <= 31 || *byte > 127 {
@@ -84,14 +303,49 @@ fn read_file(file: &mut File) -> String {
     if !is_lo_rom {
         vec = vec![0u8; 21];
         file.seek(SeekFrom::Start(65472)); // it's hirom
-        file.read(vec.as_mut_slice()).unwrap();
+        file.read_exact(vec.as_mut_slice());
+    }
+    String::from_utf8_lossy(&vec).to_string()
+}
+
+#[allow(unused_must_use)]
+fn read_vectors<R: Read + Seek>(file: &mut R, header_offset: usize) -> Vectors {
+    let mut buffer = [0u8; 28];
+    file.seek(SeekFrom::Start(header_offset as u64 + 0x24));
+    file.read_exact(&mut buffer);
+    let word = |offset: usize| buffer[offset] as u16 | (buffer[offset + 1] as u16) << 8;
+    Vectors {
+        native_cop: word(0x00),
+        native_brk: word(0x02),
+        native_abort: word(0x04),
+        native_nmi: word(0x06),
+        native_irq: word(0x0a),
+        emulation_cop: word(0x10),
+        emulation_abort: word(0x14),
+        emulation_nmi: word(0x16),
+        reset: word(0x18),
+        emulation_irq: word(0x1a),
     }
-    return str::from_utf8(&vec).unwrap().to_string();
 }
 
 #[allow(unused_must_use)]
-fn buffer_readnext(file: &mut File, buffer: &mut [u8; 1]) {
-    file.read(buffer);
+fn read_extended_header<R: Read + Seek>(file: &mut R, header_offset: usize) -> Option<ExtendedHeader> {
+    let mut buffer = [0u8; 16];
+    file.seek(SeekFrom::Start(header_offset.checked_sub(0x10)? as u64));
+    file.read_exact(&mut buffer);
+    Some(ExtendedHeader {
+        maker_code: String::from_utf8_lossy(&buffer[0..2]).to_string(),
+        game_code: String::from_utf8_lossy(&buffer[2..6]).to_string(),
+        expansion_flash_size: buffer[0x0c],
+        expansion_ram_size: buffer[0x0d],
+        special_version: buffer[0x0e],
+        cartridge_subtype: buffer[0x0f],
+    })
+}
+
+#[allow(unused_must_use)]
+fn buffer_readnext<R: Read>(file: &mut R, buffer: &mut [u8; 1]) {
+    file.read_exact(buffer);
 }
 
 fn get_rom_type(buffer: [u8; 1]) -> RomType {
@@ -103,6 +357,18 @@ fn get_rom_type(buffer: [u8; 1]) -> RomType {
         buffer if buffer[0] == RomType::ROMDSP1RAM as u8 => RomType::ROMDSP1RAM,
         buffer if buffer[0] == RomType::ROMDSP1SRAM as u8 => RomType::ROMDSP1SRAM,
         buffer if buffer[0] == RomType::FX as u8 => RomType::FX,
+        buffer if buffer[0] == RomType::GSU as u8 => RomType::GSU,
+        buffer if buffer[0] == RomType::GSURAM as u8 => RomType::GSURAM,
+        buffer if buffer[0] == RomType::GSUSRAM as u8 => RomType::GSUSRAM,
+        buffer if buffer[0] == RomType::GSUSRAMBattery as u8 => RomType::GSUSRAMBattery,
+        buffer if buffer[0] == RomType::SA1 as u8 => RomType::SA1,
+        buffer if buffer[0] == RomType::SA1SRAM as u8 => RomType::SA1SRAM,
+        buffer if buffer[0] == RomType::SDD1 as u8 => RomType::SDD1,
+        buffer if buffer[0] == RomType::SDD1SRAM as u8 => RomType::SDD1SRAM,
+        buffer if buffer[0] == RomType::SRTC as u8 => RomType::SRTC,
+        buffer if buffer[0] == RomType::SPC7110 as u8 => RomType::SPC7110,
+        buffer if buffer[0] == RomType::SPC7110RTC as u8 => RomType::SPC7110RTC,
+        buffer if buffer[0] == RomType::SGB as u8 => RomType::SGB,
         _ => RomType::Unknown,
     }
 }
@@ -111,95 +377,97 @@ fn get_rom_makeup_type(buffer: [u8; 1]) -> RomMakupType {
     match buffer {
         buffer if buffer[0] == RomMakupType::LoROM as u8 => RomMakupType::LoROM,
         buffer if buffer[0] == RomMakupType::HiROM as u8 => RomMakupType::HiROM,
+        buffer if buffer[0] == RomMakupType::LoROMSA1 as u8 => RomMakupType::LoROMSA1,
         buffer if buffer[0] == RomMakupType::LoROMFastROM as u8 => RomMakupType::LoROMFastROM,
         buffer if buffer[0] == RomMakupType::HiROMFastROM as u8 => RomMakupType::HiROMFastROM,
         buffer if buffer[0] == RomMakupType::ExLoROM as u8 => RomMakupType::ExLoROM,
         buffer if buffer[0] == RomMakupType::ExHiROM as u8 => RomMakupType::ExHiROM,
+        buffer if buffer[0] == RomMakupType::HiROMSPC7110 as u8 => RomMakupType::HiROMSPC7110,
         _ => RomMakupType::Unknown,
     }
 }
 
 fn get_location(buffer: [u8; 1]) -> VideoMode {
-    return match buffer {
-        buffer if buffer[0] == 0 as u8 => {
+    match buffer {
+        buffer if buffer[0] == 0 => {
             VideoMode {
                 country: "Japan".to_string(),
                 mode: "NTSC".to_string(),
             }
         }
-        buffer if buffer[0] == 1 as u8 => {
+        buffer if buffer[0] == 1 => {
             VideoMode {
                 country: "USA".to_string(),
                 mode: "NTSC".to_string(),
             }
         }
-        buffer if buffer[0] == 2 as u8 => {
+        buffer if buffer[0] == 2 => {
             VideoMode {
                 country: "Europe".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 3 as u8 => {
+        buffer if buffer[0] == 3 => {
             VideoMode {
                 country: "Sweden".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 4 as u8 => {
+        buffer if buffer[0] == 4 => {
             VideoMode {
                 country: "Finland".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 5 as u8 => {
+        buffer if buffer[0] == 5 => {
             VideoMode {
                 country: "Denmark".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 6 as u8 => {
+        buffer if buffer[0] == 6 => {
             VideoMode {
                 country: "France".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 7 as u8 => {
+        buffer if buffer[0] == 7 => {
             VideoMode {
                 country: "Holland".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 8 as u8 => {
+        buffer if buffer[0] == 8 => {
             VideoMode {
                 country: "Spain".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 9 as u8 => {
+        buffer if buffer[0] == 9 => {
             VideoMode {
                 country: "Germany".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 10 as u8 => {
+        buffer if buffer[0] == 10 => {
             VideoMode {
                 country: "Italy".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 11 as u8 => {
+        buffer if buffer[0] == 11 => {
             VideoMode {
                 country: "China".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 12 as u8 => {
+        buffer if buffer[0] == 12 => {
             VideoMode {
                 country: "Indonesia".to_string(),
                 mode: "PAL".to_string(),
             }
         }
-        buffer if buffer[0] == 13 as u8 => {
+        buffer if buffer[0] == 13 => {
             VideoMode {
                 country: "Korea".to_string(),
                 mode: "PAL".to_string(),
@@ -211,10 +479,10 @@ fn get_location(buffer: [u8; 1]) -> VideoMode {
                 mode: "Unknown".to_string(),
             }
         }
-    };
+    }
 }
 
-const LICENSES: &'static [&'static str] = &["Invalid",
+const LICENSES: &[&str] = &["Invalid",
                                             "Nintendo",
                                             "",
                                             "",
@@ -0,0 +1,429 @@
+use brr::{self, Sample};
+
+// .spc snapshots: the spc700 registers, id666 tags (text or binary layout), 64 KiB of apu ram,
+// the 128 dsp registers and optionally an xid6 chunk behind them
+
+const MAGIC: &[u8] = b"SNES-SPC700 Sound File Data v0.30";
+const HAS_ID666: u8 = 26;
+const NO_ID666: u8 = 27;
+const VERSION: u8 = 30;
+const RAM_OFFSET: usize = 0x100;
+const DSP_OFFSET: usize = 0x10100;
+const EXTRA_RAM_OFFSET: usize = 0x101c0;
+const XID6_OFFSET: usize = 0x10200;
+const DSP_DIR: usize = 0x5d;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Registers {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub psw: u8,
+    pub sp: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Id666Format {
+    Text,
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Id666 {
+    pub song: String,
+    pub game: String,
+    pub dumper: String,
+    pub comments: String,
+    pub date: Option<Date>,
+    pub seconds: u32, // before fading out
+    pub fade: u32,    // in ms
+    pub artist: String,
+    pub channel_disables: u8,
+    pub emulator: u8, // 0 unknown, 1 zsnes, 2 snes9x
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Xid6Value {
+    Data(u16), // stored in the sub-chunk header itself
+    Text(String),
+    Integer(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Xid6Item {
+    pub id: u8,
+    pub value: Xid6Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spc {
+    pub registers: Registers,
+    pub id666: Option<Id666>,
+    pub id666_format: Id666Format,
+    pub ram: Vec<u8>,
+    pub dsp: Vec<u8>,
+    pub extra_ram: Vec<u8>, // the ram hidden under the ipl rom at $ffc0
+    pub xid6: Vec<Xid6Item>,
+}
+
+// an entry of the sample directory the dsp's DIR register points at
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectoryEntry {
+    pub index: u8,
+    pub start: u16,
+    pub loop_start: u16,
+    pub used: bool, // selected by one of the voices at the time of the snapshot
+    pub sample: Sample,
+}
+
+pub fn xid6_name(id: u8) -> &'static str {
+    match id {
+        0x01 => "song",
+        0x02 => "game",
+        0x03 => "artist",
+        0x04 => "dumper",
+        0x05 => "date",
+        0x06 => "emulator",
+        0x07 => "comments",
+        0x10 => "ost title",
+        0x11 => "ost disc",
+        0x12 => "ost track",
+        0x13 => "publisher",
+        0x14 => "copyright year",
+        0x30 => "intro length",
+        0x31 => "loop length",
+        0x32 => "end length",
+        0x33 => "fade length",
+        0x34 => "muted voices",
+        0x35 => "loop count",
+        0x36 => "mixing level",
+        _ => "unknown",
+    }
+}
+
+impl Spc {
+    pub fn parse(file: &[u8]) -> Result<Spc, String> {
+        if !file.starts_with(MAGIC) {
+            return Err("not an SPC file".to_string());
+        }
+        if file.len() < EXTRA_RAM_OFFSET {
+            return Err(format!("SPC file is too short ({:#x} bytes)", file.len()));
+        }
+        let id666_format = detect_id666_format(file);
+        let id666 = if file[0x23] == HAS_ID666 {
+            Some(match id666_format {
+                Id666Format::Text => parse_text_id666(file),
+                Id666Format::Binary => parse_binary_id666(file),
+            })
+        } else {
+            None
+        };
+        let mut extra_ram = file.get(EXTRA_RAM_OFFSET..XID6_OFFSET).map(|extra| extra.to_vec()).unwrap_or_default();
+        extra_ram.resize(0x40, 0);
+        Ok(Spc {
+            registers: Registers {
+                pc: file[0x25] as u16 | (file[0x26] as u16) << 8,
+                a: file[0x27],
+                x: file[0x28],
+                y: file[0x29],
+                psw: file[0x2a],
+                sp: file[0x2b],
+            },
+            id666,
+            id666_format,
+            ram: file[RAM_OFFSET..DSP_OFFSET].to_vec(),
+            dsp: file[DSP_OFFSET..DSP_OFFSET + 0x80].to_vec(),
+            extra_ram,
+            xid6: parse_xid6(file.get(XID6_OFFSET..).unwrap_or_default())?,
+        })
+    }
+
+    pub fn write(&self) -> Vec<u8> {
+        let mut file = vec![0u8; XID6_OFFSET];
+        file[..MAGIC.len()].copy_from_slice(MAGIC);
+        file[0x21] = 26;
+        file[0x22] = 26;
+        file[0x23] = if self.id666.is_some() { HAS_ID666 } else { NO_ID666 };
+        file[0x24] = VERSION;
+        file[0x25] = self.registers.pc as u8;
+        file[0x26] = (self.registers.pc >> 8) as u8;
+        file[0x27] = self.registers.a;
+        file[0x28] = self.registers.x;
+        file[0x29] = self.registers.y;
+        file[0x2a] = self.registers.psw;
+        file[0x2b] = self.registers.sp;
+        if let Some(id666) = &self.id666 {
+            match self.id666_format {
+                Id666Format::Text => write_text_id666(&mut file, id666),
+                Id666Format::Binary => write_binary_id666(&mut file, id666),
+            }
+        }
+        file[RAM_OFFSET..DSP_OFFSET].copy_from_slice(&self.ram);
+        file[DSP_OFFSET..DSP_OFFSET + 0x80].copy_from_slice(&self.dsp);
+        file[EXTRA_RAM_OFFSET..XID6_OFFSET].copy_from_slice(&self.extra_ram);
+        if !self.xid6.is_empty() {
+            file.extend(write_xid6(&self.xid6));
+        }
+        file
+    }
+
+    // walks the sample directory, keeping the entries that decode to a complete sample inside apu ram
+    pub fn directory(&self) -> Vec<DirectoryEntry> {
+        let base = (self.dsp[DSP_DIR] as usize) << 8;
+        let used: Vec<u8> = (0..8).map(|voice| self.dsp[voice << 4 | 4]).collect();
+        let mut entries = Vec::new();
+        for index in 0..=255u8 {
+            let entry = base + index as usize * 4;
+            if entry + 4 > self.ram.len() {
+                break;
+            }
+            let start = self.ram[entry] as u16 | (self.ram[entry + 1] as u16) << 8;
+            let loop_start = self.ram[entry + 2] as u16 | (self.ram[entry + 3] as u16) << 8;
+            // pages 0 and 1 are the registers, direct page and stack, never sample data
+            if start < 0x200 || entries.iter().any(|other: &DirectoryEntry| other.start == start) {
+                continue;
+            }
+            let loop_offset = loop_start.wrapping_sub(start) as usize;
+            let sample = match brr::decode(&self.ram, start as usize, loop_offset) {
+                Ok(sample) => sample,
+                Err(_) => continue,
+            };
+            // garbage entries rarely have a loop point on a block inside the sample or make any noise
+            if sample.loop_start.is_some() && (loop_start < start || loop_offset >= sample.size || !loop_offset.is_multiple_of(brr::BLOCK_SIZE)) {
+                continue;
+            }
+            if sample.pcm.iter().all(|value| *value == 0) {
+                continue;
+            }
+            entries.push(DirectoryEntry {
+                index,
+                start,
+                loop_start,
+                used: used.contains(&index),
+                sample,
+            });
+        }
+        entries
+    }
+}
+
+// neither variant marks itself, so look at whether the date and length fields read as text
+fn detect_id666_format(file: &[u8]) -> Id666Format {
+    let texty = |range: &[u8]| range.iter().all(|byte| *byte == 0 || byte.is_ascii_digit() || *byte == b'/' || *byte == b'-');
+    let fields = &file[0x9e..0xb0];
+    if !texty(&file[0x9e..0xa9]) || !texty(&file[0xa9..0xb1]) {
+        return Id666Format::Binary;
+    }
+    // all zero: the artist starts one byte earlier in the binary layout
+    if fields.iter().all(|byte| *byte == 0) && file[0xb0] != 0 {
+        return Id666Format::Binary;
+    }
+    Id666Format::Text
+}
+
+fn parse_text_id666(file: &[u8]) -> Id666 {
+    let number = |range: &[u8]| read_string(range).trim().parse::<u32>().unwrap_or(0);
+    Id666 {
+        song: read_string(&file[0x2e..0x4e]),
+        game: read_string(&file[0x4e..0x6e]),
+        dumper: read_string(&file[0x6e..0x7e]),
+        comments: read_string(&file[0x7e..0x9e]),
+        date: parse_date(&read_string(&file[0x9e..0xa9])),
+        seconds: number(&file[0xa9..0xac]),
+        fade: number(&file[0xac..0xb1]),
+        artist: read_string(&file[0xb1..0xd1]),
+        channel_disables: file[0xd1],
+        emulator: if file[0xd2].is_ascii_digit() { file[0xd2] - b'0' } else { file[0xd2] }, // some text tags keep it binary
+    }
+}
+
+fn parse_binary_id666(file: &[u8]) -> Id666 {
+    let year = file[0xa0] as u16 | (file[0xa1] as u16) << 8;
+    Id666 {
+        song: read_string(&file[0x2e..0x4e]),
+        game: read_string(&file[0x4e..0x6e]),
+        dumper: read_string(&file[0x6e..0x7e]),
+        comments: read_string(&file[0x7e..0x9e]),
+        date: if year != 0 {
+            Some(Date {
+                year,
+                month: file[0x9f],
+                day: file[0x9e],
+            })
+        } else {
+            None
+        },
+        seconds: file[0xa9] as u32 | (file[0xaa] as u32) << 8 | (file[0xab] as u32) << 16,
+        fade: file[0xac] as u32 | (file[0xad] as u32) << 8 | (file[0xae] as u32) << 16 | (file[0xaf] as u32) << 24,
+        artist: read_string(&file[0xb0..0xd0]),
+        channel_disables: file[0xd0],
+        emulator: file[0xd1],
+    }
+}
+
+fn write_text_id666(file: &mut [u8], id666: &Id666) {
+    write_common_id666(file, id666);
+    if let Some(date) = id666.date {
+        write_string(&mut file[0x9e..0xa9], &format!("{:02}/{:02}/{:04}", date.month, date.day, date.year));
+    }
+    write_string(&mut file[0xa9..0xac], &id666.seconds.min(999).to_string());
+    write_string(&mut file[0xac..0xb1], &id666.fade.min(99999).to_string());
+    write_string(&mut file[0xb1..0xd1], &id666.artist);
+    file[0xd1] = id666.channel_disables;
+    file[0xd2] = b'0' + id666.emulator.min(9);
+}
+
+fn write_binary_id666(file: &mut [u8], id666: &Id666) {
+    write_common_id666(file, id666);
+    if let Some(date) = id666.date {
+        file[0x9e] = date.day;
+        file[0x9f] = date.month;
+        file[0xa0] = date.year as u8;
+        file[0xa1] = (date.year >> 8) as u8;
+    }
+    let seconds = id666.seconds.min(0xffffff);
+    file[0xa9..0xac].copy_from_slice(&[seconds as u8, (seconds >> 8) as u8, (seconds >> 16) as u8]);
+    file[0xac..0xb0].copy_from_slice(&[id666.fade as u8, (id666.fade >> 8) as u8, (id666.fade >> 16) as u8, (id666.fade >> 24) as u8]);
+    write_string(&mut file[0xb0..0xd0], &id666.artist);
+    file[0xd0] = id666.channel_disables;
+    file[0xd1] = id666.emulator;
+}
+
+fn write_common_id666(file: &mut [u8], id666: &Id666) {
+    write_string(&mut file[0x2e..0x4e], &id666.song);
+    write_string(&mut file[0x4e..0x6e], &id666.game);
+    write_string(&mut file[0x6e..0x7e], &id666.dumper);
+    write_string(&mut file[0x7e..0x9e], &id666.comments);
+}
+
+// mm/dd/yyyy as most dumpers wrote it, yyyy/mm/dd is taken too
+fn parse_date(text: &str) -> Option<Date> {
+    let parts: Vec<u32> = text.split(['/', '-']).map(|part| part.trim().parse().ok()).collect::<Option<Vec<u32>>>()?;
+    let (year, month, day) = match parts.as_slice() {
+        [month, day, year] if *year > 31 => (*year, *month, *day),
+        [year, month, day] if *year > 31 => (*year, *month, *day),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year > 0xffff {
+        return None;
+    }
+    Some(Date {
+        year: year as u16,
+        month: month as u8,
+        day: day as u8,
+    })
+}
+
+fn parse_xid6(chunk: &[u8]) -> Result<Vec<Xid6Item>, String> {
+    let mut items = Vec::new();
+    if !chunk.starts_with(b"xid6") || chunk.len() < 8 {
+        return Ok(items);
+    }
+    let size = read_le32(&chunk[4..]) as usize;
+    let end = (8 + size).min(chunk.len());
+    let mut position = 8;
+    while position + 4 <= end {
+        let id = chunk[position];
+        let kind = chunk[position + 1];
+        let data = chunk[position + 2] as u16 | (chunk[position + 3] as u16) << 8;
+        position += 4;
+        if kind == 0 {
+            items.push(Xid6Item { id, value: Xid6Value::Data(data) });
+            continue;
+        }
+        let payload = chunk
+            .get(position..position + data as usize)
+            .filter(|_| position + data as usize <= end)
+            .ok_or(format!("xid6 item {:#04x} runs past the end of the chunk", id))?;
+        let value = match kind {
+            1 => Xid6Value::Text(read_string(payload)),
+            4 if payload.len() >= 4 => Xid6Value::Integer(read_le32(payload)),
+            _ => return Err(format!("xid6 item {:#04x} has unknown type {}", id, kind)),
+        };
+        items.push(Xid6Item { id, value });
+        position += (data as usize + 3) & !3;
+    }
+    Ok(items)
+}
+
+fn write_xid6(items: &[Xid6Item]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for item in items {
+        match &item.value {
+            Xid6Value::Data(data) => body.extend_from_slice(&[item.id, 0, *data as u8, (*data >> 8) as u8]),
+            Xid6Value::Text(text) => {
+                let mut bytes: Vec<u8> = text.chars().map(latin1).take(255).collect();
+                bytes.push(0);
+                body.extend_from_slice(&[item.id, 1, bytes.len() as u8, (bytes.len() >> 8) as u8]);
+                bytes.resize((bytes.len() + 3) & !3, 0);
+                body.extend(bytes);
+            }
+            Xid6Value::Integer(value) => {
+                body.extend_from_slice(&[item.id, 4, 4, 0]);
+                body.extend_from_slice(&[*value as u8, (*value >> 8) as u8, (*value >> 16) as u8, (*value >> 24) as u8]);
+            }
+        }
+    }
+    let mut chunk = b"xid6".to_vec();
+    let size = body.len() as u32;
+    chunk.extend_from_slice(&[size as u8, (size >> 8) as u8, (size >> 16) as u8, (size >> 24) as u8]);
+    chunk.extend(body);
+    chunk
+}
+
+// tags are single byte text, mostly ascii with some latin-1 or shift-jis, kept byte for byte
+fn read_string(bytes: &[u8]) -> String {
+    bytes.iter().take_while(|byte| **byte != 0).map(|byte| *byte as char).collect()
+}
+
+fn write_string(field: &mut [u8], text: &str) {
+    for byte in field.iter_mut() {
+        *byte = 0;
+    }
+    for (byte, c) in field.iter_mut().zip(text.chars()) {
+        *byte = latin1(c);
+    }
+}
+
+// the byte read_string gives back as c, characters beyond latin-1 have none
+fn latin1(c: char) -> u8 {
+    if (c as u32) < 0x100 { c as u8 } else { b'?' }
+}
+
+fn read_le32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xid6_long_and_wide_text() {
+        let items = vec![
+            Xid6Item { id: 0x01, value: Xid6Value::Text("x".repeat(300)) },
+            Xid6Item { id: 0x02, value: Xid6Value::Text("Ys \u{2161} caf\u{e9}".to_string()) },
+            Xid6Item { id: 0x14, value: Xid6Value::Data(2) },
+        ];
+        let parsed = parse_xid6(&write_xid6(&items)).unwrap();
+        assert_eq!(parsed[0].value, Xid6Value::Text("x".repeat(255)));
+        assert_eq!(parsed[1].value, Xid6Value::Text("Ys ? caf\u{e9}".to_string()));
+        assert_eq!(parsed[2], items[2]);
+    }
+
+    #[test]
+    fn binary_tag_with_empty_date() {
+        let mut file = vec![0u8; 0x100];
+        assert_eq!(detect_id666_format(&file), Id666Format::Text);
+        file[0xb0] = b'A'; // artist one byte earlier
+        assert_eq!(detect_id666_format(&file), Id666Format::Binary);
+    }
+}
@@ -0,0 +1,252 @@
+use std::fmt;
+
+// how the operand bytes after the opcode are laid out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    Implied,
+    Direct,
+    Absolute,
+    Immediate,
+    Relative,
+    DirectRelative,
+    DirectDirect,    // source, destination
+    ImmediateDirect, // immediate, destination
+    MemoryBit,       // 13 bit address, 3 bit bit number
+    PageCall,
+}
+
+use self::Layout::*;
+
+// operands fill the {} in the order they are written, which isn't always the order of the bytes
+pub const OPCODES: [(&str, Layout); 256] = [
+    ("nop", Implied), ("tcall 0", Implied),
+    ("set1 {}.0", Direct), ("bbs {}.0,{}", DirectRelative),
+    ("or a,{}", Direct), ("or a,{}", Absolute),
+    ("or a,(x)", Implied), ("or a,[{}+x]", Direct),
+    ("or a,{}", Immediate), ("or {},{}", DirectDirect),
+    ("or1 c,{}", MemoryBit), ("asl {}", Direct),
+    ("asl {}", Absolute), ("push psw", Implied),
+    ("tset1 {}", Absolute), ("brk", Implied),
+    ("bpl {}", Relative), ("tcall 1", Implied),
+    ("clr1 {}.0", Direct), ("bbc {}.0,{}", DirectRelative),
+    ("or a,{}+x", Direct), ("or a,{}+x", Absolute),
+    ("or a,{}+y", Absolute), ("or a,[{}]+y", Direct),
+    ("or {},{}", ImmediateDirect), ("or (x),(y)", Implied),
+    ("decw {}", Direct), ("asl {}+x", Direct),
+    ("asl a", Implied), ("dec x", Implied),
+    ("cmp x,{}", Absolute), ("jmp [{}+x]", Absolute),
+    ("clrp", Implied), ("tcall 2", Implied),
+    ("set1 {}.1", Direct), ("bbs {}.1,{}", DirectRelative),
+    ("and a,{}", Direct), ("and a,{}", Absolute),
+    ("and a,(x)", Implied), ("and a,[{}+x]", Direct),
+    ("and a,{}", Immediate), ("and {},{}", DirectDirect),
+    ("or1 c,/{}", MemoryBit), ("rol {}", Direct),
+    ("rol {}", Absolute), ("push a", Implied),
+    ("cbne {},{}", DirectRelative), ("bra {}", Relative),
+    ("bmi {}", Relative), ("tcall 3", Implied),
+    ("clr1 {}.1", Direct), ("bbc {}.1,{}", DirectRelative),
+    ("and a,{}+x", Direct), ("and a,{}+x", Absolute),
+    ("and a,{}+y", Absolute), ("and a,[{}]+y", Direct),
+    ("and {},{}", ImmediateDirect), ("and (x),(y)", Implied),
+    ("incw {}", Direct), ("rol {}+x", Direct),
+    ("rol a", Implied), ("inc x", Implied),
+    ("cmp x,{}", Direct), ("call {}", Absolute),
+    ("setp", Implied), ("tcall 4", Implied),
+    ("set1 {}.2", Direct), ("bbs {}.2,{}", DirectRelative),
+    ("eor a,{}", Direct), ("eor a,{}", Absolute),
+    ("eor a,(x)", Implied), ("eor a,[{}+x]", Direct),
+    ("eor a,{}", Immediate), ("eor {},{}", DirectDirect),
+    ("and1 c,{}", MemoryBit), ("lsr {}", Direct),
+    ("lsr {}", Absolute), ("push x", Implied),
+    ("tclr1 {}", Absolute), ("pcall {}", PageCall),
+    ("bvc {}", Relative), ("tcall 5", Implied),
+    ("clr1 {}.2", Direct), ("bbc {}.2,{}", DirectRelative),
+    ("eor a,{}+x", Direct), ("eor a,{}+x", Absolute),
+    ("eor a,{}+y", Absolute), ("eor a,[{}]+y", Direct),
+    ("eor {},{}", ImmediateDirect), ("eor (x),(y)", Implied),
+    ("cmpw ya,{}", Direct), ("lsr {}+x", Direct),
+    ("lsr a", Implied), ("mov x,a", Implied),
+    ("cmp y,{}", Absolute), ("jmp {}", Absolute),
+    ("clrc", Implied), ("tcall 6", Implied),
+    ("set1 {}.3", Direct), ("bbs {}.3,{}", DirectRelative),
+    ("cmp a,{}", Direct), ("cmp a,{}", Absolute),
+    ("cmp a,(x)", Implied), ("cmp a,[{}+x]", Direct),
+    ("cmp a,{}", Immediate), ("cmp {},{}", DirectDirect),
+    ("and1 c,/{}", MemoryBit), ("ror {}", Direct),
+    ("ror {}", Absolute), ("push y", Implied),
+    ("dbnz {},{}", DirectRelative), ("ret", Implied),
+    ("bvs {}", Relative), ("tcall 7", Implied),
+    ("clr1 {}.3", Direct), ("bbc {}.3,{}", DirectRelative),
+    ("cmp a,{}+x", Direct), ("cmp a,{}+x", Absolute),
+    ("cmp a,{}+y", Absolute), ("cmp a,[{}]+y", Direct),
+    ("cmp {},{}", ImmediateDirect), ("cmp (x),(y)", Implied),
+    ("addw ya,{}", Direct), ("ror {}+x", Direct),
+    ("ror a", Implied), ("mov a,x", Implied),
+    ("cmp y,{}", Direct), ("reti", Implied),
+    ("setc", Implied), ("tcall 8", Implied),
+    ("set1 {}.4", Direct), ("bbs {}.4,{}", DirectRelative),
+    ("adc a,{}", Direct), ("adc a,{}", Absolute),
+    ("adc a,(x)", Implied), ("adc a,[{}+x]", Direct),
+    ("adc a,{}", Immediate), ("adc {},{}", DirectDirect),
+    ("eor1 c,{}", MemoryBit), ("dec {}", Direct),
+    ("dec {}", Absolute), ("mov y,{}", Immediate),
+    ("pop psw", Implied), ("mov {},{}", ImmediateDirect),
+    ("bcc {}", Relative), ("tcall 9", Implied),
+    ("clr1 {}.4", Direct), ("bbc {}.4,{}", DirectRelative),
+    ("adc a,{}+x", Direct), ("adc a,{}+x", Absolute),
+    ("adc a,{}+y", Absolute), ("adc a,[{}]+y", Direct),
+    ("adc {},{}", ImmediateDirect), ("adc (x),(y)", Implied),
+    ("subw ya,{}", Direct), ("dec {}+x", Direct),
+    ("dec a", Implied), ("mov x,sp", Implied),
+    ("div ya,x", Implied), ("xcn a", Implied),
+    ("ei", Implied), ("tcall 10", Implied),
+    ("set1 {}.5", Direct), ("bbs {}.5,{}", DirectRelative),
+    ("sbc a,{}", Direct), ("sbc a,{}", Absolute),
+    ("sbc a,(x)", Implied), ("sbc a,[{}+x]", Direct),
+    ("sbc a,{}", Immediate), ("sbc {},{}", DirectDirect),
+    ("mov1 c,{}", MemoryBit), ("inc {}", Direct),
+    ("inc {}", Absolute), ("cmp y,{}", Immediate),
+    ("pop a", Implied), ("mov (x)+,a", Implied),
+    ("bcs {}", Relative), ("tcall 11", Implied),
+    ("clr1 {}.5", Direct), ("bbc {}.5,{}", DirectRelative),
+    ("sbc a,{}+x", Direct), ("sbc a,{}+x", Absolute),
+    ("sbc a,{}+y", Absolute), ("sbc a,[{}]+y", Direct),
+    ("sbc {},{}", ImmediateDirect), ("sbc (x),(y)", Implied),
+    ("movw ya,{}", Direct), ("inc {}+x", Direct),
+    ("inc a", Implied), ("mov sp,x", Implied),
+    ("das a", Implied), ("mov a,(x)+", Implied),
+    ("di", Implied), ("tcall 12", Implied),
+    ("set1 {}.6", Direct), ("bbs {}.6,{}", DirectRelative),
+    ("mov {},a", Direct), ("mov {},a", Absolute),
+    ("mov (x),a", Implied), ("mov [{}+x],a", Direct),
+    ("cmp x,{}", Immediate), ("mov {},x", Absolute),
+    ("mov1 {},c", MemoryBit), ("mov {},y", Direct),
+    ("mov {},y", Absolute), ("mov x,{}", Immediate),
+    ("pop x", Implied), ("mul ya", Implied),
+    ("bne {}", Relative), ("tcall 13", Implied),
+    ("clr1 {}.6", Direct), ("bbc {}.6,{}", DirectRelative),
+    ("mov {}+x,a", Direct), ("mov {}+x,a", Absolute),
+    ("mov {}+y,a", Absolute), ("mov [{}]+y,a", Direct),
+    ("mov {},x", Direct), ("mov {}+y,x", Direct),
+    ("movw {},ya", Direct), ("mov {}+x,y", Direct),
+    ("dec y", Implied), ("mov a,y", Implied),
+    ("cbne {}+x,{}", DirectRelative), ("daa a", Implied),
+    ("clrv", Implied), ("tcall 14", Implied),
+    ("set1 {}.7", Direct), ("bbs {}.7,{}", DirectRelative),
+    ("mov a,{}", Direct), ("mov a,{}", Absolute),
+    ("mov a,(x)", Implied), ("mov a,[{}+x]", Direct),
+    ("mov a,{}", Immediate), ("mov x,{}", Absolute),
+    ("not1 {}", MemoryBit), ("mov y,{}", Direct),
+    ("mov y,{}", Absolute), ("notc", Implied),
+    ("pop y", Implied), ("sleep", Implied),
+    ("beq {}", Relative), ("tcall 15", Implied),
+    ("clr1 {}.7", Direct), ("bbc {}.7,{}", DirectRelative),
+    ("mov a,{}+x", Direct), ("mov a,{}+x", Absolute),
+    ("mov a,{}+y", Absolute), ("mov a,[{}]+y", Direct),
+    ("mov x,{}", Direct), ("mov x,{}+y", Direct),
+    ("mov {},{}", DirectDirect), ("mov y,{}+x", Direct),
+    ("inc y", Implied), ("mov y,a", Implied),
+    ("dbnz y,{}", Relative), ("stop", Implied),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    pub layout: Layout,
+    pub bytes: Vec<u8>,
+}
+
+pub fn operand_size(layout: Layout) -> usize {
+    match layout {
+        Implied => 0,
+        Direct | Immediate | Relative | PageCall => 1,
+        Absolute | DirectRelative | DirectDirect | ImmediateDirect | MemoryBit => 2,
+    }
+}
+
+pub fn decode(data: &[u8], address: u16) -> Option<Instruction> {
+    let opcode = *data.first()?;
+    let layout = OPCODES[opcode as usize].1;
+    Some(Instruction {
+        address,
+        opcode,
+        layout,
+        bytes: data.get(..1 + operand_size(layout))?.to_vec(),
+    })
+}
+
+pub fn disassemble(data: &[u8], address: u16, len: usize) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < len.min(data.len()) {
+        let instruction = match decode(&data[offset..], address.wrapping_add(offset as u16)) {
+            Some(instruction) => instruction,
+            None => break,
+        };
+        offset += instruction.len();
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+impl Instruction {
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        let template = OPCODES[self.opcode as usize].0;
+        template.split(' ').next().unwrap()
+    }
+
+    // relative branches count from the end of the instruction
+    pub fn target(&self) -> Option<u16> {
+        let displacement = match self.layout {
+            Relative => self.bytes[1],
+            DirectRelative => self.bytes[2],
+            _ => return None,
+        };
+        Some(self.address.wrapping_add(self.len() as u16).wrapping_add(displacement as i8 as u16))
+    }
+
+    fn operands(&self) -> Vec<String> {
+        let byte = |index: usize| self.bytes[index];
+        let word = byte(1) as u16 | (*self.bytes.get(2).unwrap_or(&0) as u16) << 8;
+        match self.layout {
+            Implied => vec![],
+            Direct => vec![format!("${:02x}", byte(1))],
+            Absolute => vec![format!("!${:04x}", word)],
+            Immediate => vec![format!("#${:02x}", byte(1))],
+            Relative => vec![format!("${:04x}", self.target().unwrap())],
+            DirectRelative => vec![format!("${:02x}", byte(1)), format!("${:04x}", self.target().unwrap())],
+            DirectDirect => vec![format!("${:02x}", byte(2)), format!("${:02x}", byte(1))],
+            ImmediateDirect => vec![format!("${:02x}", byte(2)), format!("#${:02x}", byte(1))],
+            MemoryBit => vec![format!("${:04x}.{}", word & 0x1fff, word >> 13)],
+            PageCall => vec![format!("${:02x}", byte(1))],
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        let mut operands = self.operands().into_iter();
+        for (index, part) in OPCODES[self.opcode as usize].0.split("{}").enumerate() {
+            if index > 0 {
+                text.push_str(&operands.next().unwrap_or_default());
+            }
+            text.push_str(part);
+        }
+        text
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        write!(f, "{:04x}  {:<9} {}", self.address, bytes.join(" "), self.text())
+    }
+}
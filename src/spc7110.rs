@@ -0,0 +1,275 @@
+use compression::Decompressed;
+use snesutilities::RomType;
+
+// the spc7110 sits in front of a program rom (1 MiB, 2 MiB on the rtc board of far east of eden zero)
+// and a data rom following it in the image. c0-cf and 00-0f/80-8f:8000-ffff are the first program
+// mebibyte, d0-df, e0-ef and f0-ff are windows into the data rom set through 4831-4833 (0, 1, 2 after
+// reset). the second program mebibyte only replaces the data rom there once 4834 is written, so it is
+// left out of the mapping
+
+const MEBIBYTE: usize = 0x100000;
+
+pub fn program_size(rom_type: RomType) -> usize {
+    if rom_type == RomType::SPC7110RTC { 2 * MEBIBYTE } else { MEBIBYTE }
+}
+
+pub fn to_offset(address: u32, program_size: usize, size: usize) -> Option<usize> {
+    let bank = (address >> 16) as usize & 0xff;
+    let addr = address as usize & 0xffff;
+    let window = match bank {
+        0x00..=0x0f | 0x80..=0x8f if addr >= 0x8000 => 0,
+        0xc0..=0xff => (bank >> 4) - 0x0c,
+        _ => return None,
+    };
+    let base = if window == 0 { 0 } else { program_size + (window - 1) * MEBIBYTE };
+    Some(base | (bank & 0x0f) << 16 | addr).filter(|offset| *offset < size)
+}
+
+// the inverse of to_offset: the first program mebibyte through c0-cf, the data rom through the windows
+// the way they are after reset. None for the rest of the program rom and data past the windows
+pub fn to_address(offset: usize, program_size: usize) -> Option<u32> {
+    let window = match offset {
+        _ if offset < MEBIBYTE => 0,
+        _ if offset < program_size => return None,
+        _ => (offset - program_size) / MEBIBYTE + 1,
+    };
+    if window > 3 {
+        return None;
+    }
+    Some(((0xc0 + window * 0x10) << 16 | (offset & 0xfffff)) as u32)
+}
+
+// probability of the more probable symbol and the next state after it and after the less probable one
+const EVOLUTION: [(u8, u8, u8); 53] = [
+    (0x5a, 1, 1), (0x25, 2, 6), (0x11, 3, 8), (0x08, 4, 10), (0x03, 5, 12), (0x01, 5, 15),
+    (0x5a, 7, 7), (0x3f, 8, 19), (0x2c, 9, 21), (0x20, 10, 22), (0x17, 11, 23), (0x11, 12, 25),
+    (0x0c, 13, 26), (0x09, 14, 28), (0x07, 15, 29), (0x05, 16, 31), (0x04, 17, 32), (0x03, 18, 34),
+    (0x02, 5, 35),
+    (0x5a, 20, 20), (0x48, 21, 39), (0x3a, 22, 40), (0x2e, 23, 42), (0x26, 24, 44), (0x1f, 25, 45),
+    (0x19, 26, 46), (0x15, 27, 25), (0x11, 28, 26), (0x0e, 29, 26), (0x0b, 30, 27), (0x09, 31, 28),
+    (0x08, 32, 29), (0x07, 33, 30), (0x05, 34, 31), (0x04, 35, 33), (0x04, 36, 33), (0x03, 37, 34),
+    (0x02, 38, 35), (0x02, 5, 36),
+    (0x58, 40, 39), (0x4d, 41, 47), (0x43, 42, 48), (0x3b, 43, 49), (0x34, 44, 50), (0x2e, 45, 51),
+    (0x29, 46, 44), (0x25, 24, 45),
+    (0x56, 48, 47), (0x4f, 49, 47), (0x47, 50, 48), (0x41, 51, 49), (0x3c, 52, 50), (0x37, 43, 51),
+];
+
+const HALF: u8 = 0x55;
+const MAX: u16 = 0xff;
+
+#[derive(Clone, Copy, Default)]
+struct Context {
+    prediction: u8,
+    swap: u8,
+}
+
+// modes 0, 1 and 2 are 1, 2 and 4 bits per pixel, coded with an adaptive binary arithmetic coder.
+// the contexts come from the pixels left, above and above left, and a most recently used list of
+// colors turns the decoded index into the pixel
+struct Decoder<'a> {
+    data: &'a [u8],
+    offset: usize,
+    bpp: usize,
+    contexts: [[Context; 15]; 5],
+    bits: u32,
+    range: u16,
+    input: u16,
+    output: u8,
+    pixels: u64,
+    colormap: u64,
+}
+
+// pixels packed most significant first into planes, odd bits in the low half and even ones in the high
+fn deinterleave(data: u64, bits: u32) -> u32 {
+    let mut data = data & ((1u64 << bits) - 1);
+    data = 0x5555555555555555 & (data << bits | data >> 1);
+    data = 0x3333333333333333 & (data | data >> 1);
+    data = 0x0f0f0f0f0f0f0f0f & (data | data >> 2);
+    data = 0x00ff00ff00ff00ff & (data | data >> 4);
+    data = 0x0000ffff0000ffff & (data | data >> 8);
+    (data | data >> 16) as u32
+}
+
+fn move_to_front(list: u64, nibble: u64) -> u64 {
+    let mut mask = !15u64;
+    for shift in (0..64).step_by(4) {
+        if (list >> shift) & 15 == nibble {
+            return (list & mask) + ((list << 4) & !mask) + nibble;
+        }
+        mask <<= 4;
+    }
+    list
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: &'a [u8], mode: u8, offset: usize) -> Decoder<'a> {
+        let mut decoder = Decoder {
+            data,
+            offset,
+            bpp: 1 << mode,
+            contexts: [[Context::default(); 15]; 5],
+            bits: 8,
+            range: MAX + 1,
+            input: 0,
+            output: 0,
+            pixels: 0,
+            colormap: 0xfedcba9876543210,
+        };
+        decoder.input = (decoder.read() as u16) << 8;
+        decoder.input |= decoder.read() as u16;
+        decoder
+    }
+
+    fn read(&mut self) -> u8 {
+        let byte = self.data.get(self.offset).cloned().unwrap_or(0);
+        self.offset += 1;
+        byte
+    }
+
+    // one row of a tile, the planes of 2bpp and 4bpp as consecutive bytes
+    fn decode_row(&mut self) -> u32 {
+        for pixel in 0..8 {
+            let mut map = self.colormap;
+            let mut diff = 0;
+            if self.bpp > 1 {
+                let (a, b, c) = if self.bpp == 2 {
+                    (self.pixels >> 2 & 3, self.pixels >> 14 & 3, self.pixels >> 16 & 3)
+                } else {
+                    (self.pixels & 15, self.pixels >> 28 & 15, self.pixels >> 32 & 15)
+                };
+                if a != b || b != c {
+                    let matched = a ^ b ^ c;
+                    diff = 4;
+                    if matched ^ c == 0 {
+                        diff = 3;
+                    }
+                    if matched ^ b == 0 {
+                        diff = 2;
+                    }
+                    if matched ^ a == 0 {
+                        diff = 1;
+                    }
+                }
+                self.colormap = move_to_front(self.colormap, a);
+                map = move_to_front(map, c);
+                map = move_to_front(map, b);
+                map = move_to_front(map, a);
+            }
+            for plane in 0..self.bpp {
+                let bit = if self.bpp > 1 { 1 << plane } else { 1 << (pixel & 3) };
+                let history = (bit - 1) & self.output as usize;
+                let mut set = match self.bpp {
+                    1 => (pixel >= 4) as usize,
+                    2 => diff,
+                    _ => 0,
+                };
+                if plane >= 2 && history <= 1 {
+                    set = diff;
+                }
+                let context = self.contexts[set][bit + history - 1];
+                let (probability, next_mps, next_lps) = EVOLUTION[context.prediction as usize];
+                let lps_offset = (self.range as u8).wrapping_sub(probability) as u16;
+                let symbol = self.input >= lps_offset << 8;
+                self.output = self.output << 1 | (symbol as u8 ^ context.swap);
+                if symbol {
+                    self.range -= lps_offset;
+                    self.input -= lps_offset << 8;
+                } else {
+                    self.range = lps_offset;
+                }
+                let mut prediction = context.prediction;
+                while self.range <= MAX / 2 {
+                    prediction = if symbol { next_lps } else { next_mps };
+                    self.range <<= 1;
+                    self.input <<= 1;
+                    self.bits -= 1;
+                    if self.bits == 0 {
+                        self.bits = 8;
+                        self.input = self.input.wrapping_add(self.read() as u16);
+                    }
+                }
+                let state = &mut self.contexts[set][bit + history - 1];
+                state.prediction = prediction;
+                if symbol && probability > HALF {
+                    state.swap ^= 1;
+                }
+            }
+            let mut index = self.output as u64 & ((1 << self.bpp) - 1);
+            if self.bpp == 1 {
+                index ^= self.pixels >> 15 & 1;
+            }
+            self.pixels = self.pixels << self.bpp | (map >> (4 * index) & 15);
+        }
+        match self.bpp {
+            1 => self.pixels as u32,
+            2 => deinterleave(self.pixels, 16),
+            _ => deinterleave(deinterleave(self.pixels, 32) as u64, 32),
+        }
+    }
+}
+
+// length bytes of tiles from a stream in the data rom. 4bpp tiles keep their second pair of planes in
+// the upper half of the 32 byte tile as usual
+pub fn decompress(data_rom: &[u8], mode: u8, offset: usize, length: usize) -> Result<Decompressed, String> {
+    if mode > 2 {
+        return Err(format!("invalid spc7110 compression mode {}", mode));
+    }
+    if offset >= data_rom.len() {
+        return Err("offset past the end of the data rom".to_string());
+    }
+    let mut decoder = Decoder::new(data_rom, mode, offset);
+    let tile_size = 8 * decoder.bpp;
+    let mut output = Vec::with_capacity(length.div_ceil(tile_size) * tile_size);
+    while output.len() < length {
+        let mut tile = vec![0u8; tile_size];
+        for row in 0..8 {
+            let result = decoder.decode_row();
+            match decoder.bpp {
+                1 => tile[row] = result as u8,
+                2 => tile[row * 2..row * 2 + 2].copy_from_slice(&[result as u8, (result >> 8) as u8]),
+                _ => {
+                    tile[row * 2..row * 2 + 2].copy_from_slice(&[result as u8, (result >> 8) as u8]);
+                    tile[row * 2 + 16..row * 2 + 18].copy_from_slice(&[(result >> 16) as u8, (result >> 24) as u8]);
+                }
+            }
+        }
+        output.extend(tile);
+    }
+    output.truncate(length);
+    Ok(Decompressed {
+        data: output,
+        consumed: decoder.offset - offset,
+    })
+}
+
+// games pick streams by number from a table of 4 byte entries: mode, then the big endian data rom offset
+pub fn table_entry(data_rom: &[u8], table: usize, index: usize) -> Result<(u8, usize), String> {
+    let entry = data_rom.get(table + index * 4..table + index * 4 + 4).ok_or("table entry past the end of the data rom")?;
+    Ok((entry[0], (entry[1] as usize) << 16 | (entry[2] as usize) << 8 | entry[3] as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_after_reset() {
+        let size = 2 * MEBIBYTE + 3 * MEBIBYTE;
+        assert_eq!(to_offset(0xd01234, 2 * MEBIBYTE, size), Some(2 * MEBIBYTE + 0x1234));
+        assert_eq!(to_offset(0xd01234, MEBIBYTE, size), Some(MEBIBYTE + 0x1234));
+        assert_eq!(to_offset(0x08c000, 2 * MEBIBYTE, size), Some(0x8c000));
+        assert_eq!(to_address(MEBIBYTE + 0x1234, 2 * MEBIBYTE), None);
+    }
+
+    #[test]
+    fn to_address_inverts_to_offset() {
+        for &program_size in &[MEBIBYTE, 2 * MEBIBYTE] {
+            let size = program_size + 3 * MEBIBYTE;
+            for offset in (0..size).step_by(0x8123) {
+                if let Some(address) = to_address(offset, program_size) {
+                    assert_eq!(to_offset(address, program_size, size), Some(offset), "{:#x}", offset);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use copier::{self, CopierFormat};
+use snesutilities::SnesUtils;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub const PART_SIZE: usize = 0x80000; // 4 Mbit, what fits on a floppy
+
+// finds GAME.1, GAME.2, ... or SF16GAMA.078, SF16GAMB.078, ... starting from any one of the parts
+pub fn discover_parts(path: &Path) -> Vec<PathBuf> {
+    let mut index = match part_index(path) {
+        Some(index) => index,
+        None => return vec![path.to_path_buf()],
+    };
+    while index > 0 && part_name(path, index - 1).is_some_and(|previous| previous.is_file()) {
+        index -= 1;
+    }
+    let mut parts = Vec::new();
+    while let Some(part) = part_name(path, index) {
+        if !part.is_file() {
+            break;
+        }
+        parts.push(part);
+        index += 1;
+    }
+    if parts.is_empty() {
+        parts.push(path.to_path_buf());
+    }
+    parts
+}
+
+// zero based position of a part in its set, according to the file name
+pub fn part_index(path: &Path) -> Option<usize> {
+    let extension = path.extension()?.to_str()?;
+    if extension == "078" {
+        let last = path.file_stem()?.to_str()?.chars().last()?.to_ascii_uppercase();
+        if last.is_ascii_uppercase() {
+            return Some(last as usize - 'A' as usize);
+        }
+        return None;
+    }
+    match extension.parse::<usize>() {
+        Ok(number) if number > 0 => Some(number - 1),
+        _ => None,
+    }
+}
+
+// name of the part at index, in the naming scheme of path
+pub fn part_name(path: &Path, index: usize) -> Option<PathBuf> {
+    let extension = path.extension()?.to_str()?;
+    if extension == "078" {
+        let stem = path.file_stem()?.to_str()?;
+        let last = stem.chars().last()?;
+        if index >= 26 {
+            return None;
+        }
+        let letter = (b'A' + index as u8) as char;
+        let letter = if last.is_ascii_lowercase() { letter.to_ascii_lowercase() } else { letter };
+        return Some(path.with_file_name(format!("{}{}.078", &stem[..stem.len() - last.len_utf8()], letter)));
+    }
+    Some(path.with_extension((index + 1).to_string()))
+}
+
+// joins the parts into one image, keeping the first part's copier header (fixed up for the whole image)
+pub fn join_parts(parts: &[Vec<u8>]) -> Result<Vec<u8>, String> {
+    let mut header = None;
+    let mut body = Vec::new();
+    for (index, part) in parts.iter().enumerate() {
+        let (part_header, part_body) = copier::split_copier_header(part);
+        if let Some(part_header) = part_header {
+            let last = index + 1 == parts.len();
+            if copier::is_split(part_header) == last {
+                return Err(format!("part {} is flagged as {} but is {}",
+                                   index + 1,
+                                   if last { "split" } else { "the last part" },
+                                   if last { "the last part" } else { "followed by more parts" }));
+            }
+            if header.is_none() {
+                header = Some(part_header.to_vec());
+            }
+        }
+        body.extend_from_slice(part_body);
+    }
+    let mut image = match header {
+        Some(mut header) => {
+            copier::set_part(&mut header, body.len(), false);
+            header
+        }
+        None => Vec::new(),
+    };
+    image.extend(body);
+    Ok(image)
+}
+
+// reads a rom, joining it with its sibling parts if it is a split dump
+pub fn load(path: &Path) -> Result<Vec<u8>, String> {
+    let mut parts = Vec::new();
+    for part in discover_parts(path) {
+        let mut data = Vec::new();
+        File::open(&part)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(|e| format!("{}: {}", part.display(), e))?;
+        parts.push(data);
+    }
+    if parts.len() == 1 {
+        return Ok(parts.pop().unwrap());
+    }
+    join_parts(&parts)
+}
+
+// splits an image into parts of part_size bytes, each with its own copier header
+pub fn split_image(image: &[u8], part_size: usize) -> Vec<Vec<u8>> {
+    let (header, body) = copier::split_copier_header(image);
+    let template = header
+        .map(|header| header.to_vec())
+        .unwrap_or_else(|| copier::build_header(&SnesUtils::from_bytes(body), CopierFormat::Swc, body.len()));
+    let chunks: Vec<&[u8]> = body.chunks(part_size).collect();
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut part = template.clone();
+            copier::set_part(&mut part, chunk.len(), index + 1 < chunks.len());
+            part.extend_from_slice(chunk);
+            part
+        })
+        .collect()
+}
@@ -0,0 +1,131 @@
+use snesutilities::{RomType, SnesUtils};
+
+// battery saves (.srm) are the cartridge's sram as is, 1 KiB << the header's sram size byte. carts with
+// a real time clock keep its state next to it: bsnes and snes9x write a separate .rtc, zsnes appends it
+// to the .srm. copiers that page the save in through 2 KiB windows store the two halves alternating in
+// 2 KiB chunks
+
+pub const SRTC_SIZE: usize = 20; // s-rtc (daikaijuu monogatari 2)
+pub const EPSON_RTC_SIZE: usize = 16; // rtc-4513 next to the spc7110 (far east of eden zero)
+const COPIER_CHUNK: usize = 0x800;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaveFormat {
+    Raw,
+    Copier,
+    Zsnes,
+}
+
+impl SaveFormat {
+    pub fn from_name(name: &str) -> Option<SaveFormat> {
+        match name {
+            "raw" => Some(SaveFormat::Raw),
+            "copier" => Some(SaveFormat::Copier),
+            "zsnes" => Some(SaveFormat::Zsnes),
+            _ => None,
+        }
+    }
+}
+
+pub fn sram_size(su: &SnesUtils) -> usize {
+    if su.sram_size == 0 || su.sram_size > 16 { 0 } else { 1024 << su.sram_size }
+}
+
+pub fn rtc_size(su: &SnesUtils) -> usize {
+    match su.rom_type {
+        RomType::SRTC => SRTC_SIZE,
+        RomType::SPC7110RTC => EPSON_RTC_SIZE,
+        _ => 0,
+    }
+}
+
+// a save fits when it is the declared size, or the declared size plus the clock for rtc carts
+pub fn validate(data: &[u8], su: &SnesUtils) -> Result<(), String> {
+    let size = sram_size(su);
+    if size == 0 {
+        return Err("the header declares no sram".to_string());
+    }
+    let rtc = rtc_size(su);
+    if data.len() == size || (rtc > 0 && data.len() == size + rtc) {
+        return Ok(());
+    }
+    Err(format!("save is {:#x} bytes, the header declares {:#x}{}", data.len(), size,
+                if rtc > 0 { format!(" (or {:#x} with the clock)", size + rtc) } else { String::new() }))
+}
+
+// cuts a save down or pads it with the fill byte (0x00 or 0xff, depending on what the emulator starts with)
+pub fn resize(data: &[u8], size: usize, fill: u8) -> Vec<u8> {
+    let mut resized = data[..data.len().min(size)].to_vec();
+    resized.resize(size, fill);
+    resized
+}
+
+fn check_halves(data: &[u8]) -> Result<(), String> {
+    if !data.len().is_multiple_of(2 * COPIER_CHUNK) {
+        return Err(format!("{:#x} bytes can't be split into 2 KiB chunks of two halves", data.len()));
+    }
+    Ok(())
+}
+
+pub fn interleave(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() <= COPIER_CHUNK {
+        return Ok(data.to_vec());
+    }
+    check_halves(data)?;
+    let (low, high) = data.split_at(data.len() / 2);
+    Ok(low.chunks(COPIER_CHUNK).zip(high.chunks(COPIER_CHUNK)).flat_map(|(low, high)| low.iter().chain(high)).cloned().collect())
+}
+
+pub fn deinterleave(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() <= COPIER_CHUNK {
+        return Ok(data.to_vec());
+    }
+    check_halves(data)?;
+    let mut plain = Vec::with_capacity(data.len());
+    plain.extend(data.chunks(COPIER_CHUNK).step_by(2).flatten());
+    plain.extend(data.chunks(COPIER_CHUNK).skip(1).step_by(2).flatten());
+    Ok(plain)
+}
+
+// sram followed by the clock the way zsnes saves rtc carts
+pub fn bundle(sram: &[u8], rtc: &[u8], su: &SnesUtils) -> Result<Vec<u8>, String> {
+    let size = rtc_size(su);
+    if size == 0 {
+        return Err("the cartridge has no real time clock".to_string());
+    }
+    if rtc.len() != size {
+        return Err(format!("rtc state is {} bytes, {:?} keeps {}", rtc.len(), su.rom_type, size));
+    }
+    let mut bundled = sram.to_vec();
+    bundled.extend(rtc);
+    Ok(bundled)
+}
+
+pub fn unbundle(data: &[u8], su: &SnesUtils) -> Result<(Vec<u8>, Option<Vec<u8>>), String> {
+    let size = sram_size(su);
+    let rtc = rtc_size(su);
+    if rtc > 0 && data.len() == size + rtc {
+        return Ok((data[..size].to_vec(), Some(data[size..].to_vec())));
+    }
+    Ok((data.to_vec(), None))
+}
+
+// the sram and the clock state (if the save had one) from a save in any of the layouts
+pub fn to_raw(data: &[u8], format: SaveFormat, su: &SnesUtils) -> Result<(Vec<u8>, Option<Vec<u8>>), String> {
+    match format {
+        SaveFormat::Raw => Ok((data.to_vec(), None)),
+        SaveFormat::Copier => Ok((deinterleave(data)?, None)),
+        SaveFormat::Zsnes => unbundle(data, su),
+    }
+}
+
+pub fn from_raw(sram: &[u8], rtc: Option<&[u8]>, format: SaveFormat, su: &SnesUtils) -> Result<Vec<u8>, String> {
+    match format {
+        SaveFormat::Raw => Ok(sram.to_vec()),
+        SaveFormat::Copier => interleave(sram),
+        SaveFormat::Zsnes => match rtc {
+            Some(rtc) => bundle(sram, rtc, su),
+            None => Ok(sram.to_vec()),
+        },
+    }
+}
@@ -0,0 +1,53 @@
+use bsx;
+
+// sufami turbo mini-carts have no snes header, the base cassette reads this one at the start of the rom:
+//   +00 "BANDAI SFC-ADX", +10 title, +20 entry point and nmi/irq/cop/brk/abort vectors (24 bit, banks
+//   20-3f where the base cassette maps slot a), +3c game id, +3f index in a series, +40 rom speed,
+//   +41 features, +42 rom size in 128 KiB units, +43 sram size in 2 KiB units
+// the base cassette starts with the same id, titled "SFC-ADX BACKUP", but has a normal header as well
+
+pub const ID: &[u8] = b"BANDAI SFC-ADX";
+const BASE_CASSETTE: &[u8] = b"SFC-ADX BACKUP";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SufamiTurboHeader {
+    pub title: String,
+    pub game_id: u32, // shared by the games that link with each other
+    pub series_index: u8,
+    pub fast_rom: bool,
+    pub features: u8,
+    pub rom_size: usize,
+    pub sram_size: usize,
+    pub entry: u32,
+    pub nmi: u32,
+    pub irq: u32,
+    pub cop: u32,
+    pub brk: u32,
+    pub abort: u32,
+}
+
+pub fn is_mini_cart(data: &[u8]) -> bool {
+    data.starts_with(ID) && data.len() >= 0x50 && &data[0x10..0x10 + BASE_CASSETTE.len()] != BASE_CASSETTE
+}
+
+pub fn read_header(data: &[u8]) -> Option<SufamiTurboHeader> {
+    if !is_mini_cart(data) {
+        return None;
+    }
+    let long = |at: usize| data[at] as u32 | (data[at + 1] as u32) << 8 | (data[at + 2] as u32) << 16;
+    Some(SufamiTurboHeader {
+        title: bsx::shift_jis(&data[0x10..0x1e]),
+        game_id: (data[0x3c] as u32) << 16 | (data[0x3d] as u32) << 8 | data[0x3e] as u32,
+        series_index: data[0x3f],
+        fast_rom: data[0x40] & 1 != 0,
+        features: data[0x41],
+        rom_size: data[0x42] as usize * 0x20000,
+        sram_size: data[0x43] as usize * 0x800,
+        entry: long(0x20),
+        nmi: long(0x24),
+        irq: long(0x28),
+        cop: long(0x2c),
+        brk: long(0x30),
+        abort: long(0x34),
+    })
+}
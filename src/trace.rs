@@ -0,0 +1,321 @@
+use crc32::crc32;
+use disasm::{self, Flags, Instruction, Mode};
+use mapper::Mapper;
+use snesutilities::Vectors;
+use std::collections::HashSet;
+
+// per byte flags, laid out like mesen's snes cdl files
+pub const CODE: u8 = 0x01;
+pub const DATA: u8 = 0x02;
+pub const JUMP_TARGET: u8 = 0x04;
+pub const SUB_ENTRY_POINT: u8 = 0x08;
+pub const INDEX_MODE_8: u8 = 0x10;
+pub const MEMORY_MODE_8: u8 = 0x20;
+
+// bsnes-plus usage flags
+const USAGE_READ: u8 = 0x80;
+const USAGE_EXEC: u8 = 0x20;
+const USAGE_OPCODE: u8 = 0x10;
+const USAGE_FLAG_M: u8 = 0x02;
+const USAGE_FLAG_X: u8 = 0x01;
+
+const MESEN_MAGIC: &[u8] = b"CDLv2";
+const JUMP_TABLE_LIMIT: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CdlFormat {
+    Mesen,     // "CDLv2", crc32 of the rom, then one byte per rom byte
+    BsnesPlus, // one usage byte per rom byte
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cdl {
+    pub flags: Vec<u8>,
+    pub opcodes: Vec<bool>, // first byte of an instruction, only bsnes-plus stores this
+}
+
+impl Cdl {
+    pub fn new(size: usize) -> Cdl {
+        Cdl {
+            flags: vec![0; size],
+            opcodes: vec![false; size],
+        }
+    }
+
+    pub fn code_bytes(&self) -> usize {
+        self.flags.iter().filter(|flags| *flags & CODE != 0).count()
+    }
+
+    pub fn data_bytes(&self) -> usize {
+        self.flags.iter().filter(|flags| *flags & DATA != 0).count()
+    }
+
+    pub fn merge(&mut self, other: &Cdl) {
+        for (flags, other) in self.flags.iter_mut().zip(&other.flags) {
+            *flags |= *other;
+        }
+        for (opcode, other) in self.opcodes.iter_mut().zip(&other.opcodes) {
+            *opcode |= *other;
+        }
+    }
+
+    pub fn export(&self, format: CdlFormat, rom: &[u8]) -> Vec<u8> {
+        match format {
+            CdlFormat::Mesen => {
+                let mut file = MESEN_MAGIC.to_vec();
+                let crc = crc32(rom);
+                file.extend_from_slice(&[crc as u8, (crc >> 8) as u8, (crc >> 16) as u8, (crc >> 24) as u8]);
+                file.extend_from_slice(&self.flags);
+                file
+            }
+            CdlFormat::BsnesPlus => self
+                .flags
+                .iter()
+                .zip(&self.opcodes)
+                .map(|(flags, opcode)| {
+                    let mut usage = 0;
+                    if flags & CODE != 0 {
+                        usage |= USAGE_EXEC;
+                        if flags & MEMORY_MODE_8 != 0 {
+                            usage |= USAGE_FLAG_M;
+                        }
+                        if flags & INDEX_MODE_8 != 0 {
+                            usage |= USAGE_FLAG_X;
+                        }
+                    }
+                    if *opcode {
+                        usage |= USAGE_OPCODE;
+                    }
+                    if flags & DATA != 0 {
+                        usage |= USAGE_READ;
+                    }
+                    usage
+                })
+                .collect(),
+        }
+    }
+
+    // the format is recognised by the mesen magic, everything else is taken as bsnes-plus usage data
+    pub fn import(file: &[u8], rom: &[u8]) -> Result<Cdl, String> {
+        if file.starts_with(MESEN_MAGIC) {
+            if file.len() < MESEN_MAGIC.len() + 4 {
+                return Err("cdl ends before the rom's CRC".to_string());
+            }
+            let flags = &file[MESEN_MAGIC.len() + 4..];
+            let crc = file[5] as u32 | (file[6] as u32) << 8 | (file[7] as u32) << 16 | (file[8] as u32) << 24;
+            if crc != crc32(rom) {
+                return Err(format!("cdl was made for a rom with CRC {:08X}", crc));
+            }
+            if flags.len() != rom.len() {
+                return Err(format!("cdl covers {:#x} bytes, the rom has {:#x}", flags.len(), rom.len()));
+            }
+            return Ok(Cdl {
+                flags: flags.to_vec(),
+                opcodes: vec![false; rom.len()],
+            });
+        }
+        if file.len() < rom.len() {
+            return Err(format!("cdl covers {:#x} bytes, the rom has {:#x}", file.len(), rom.len()));
+        }
+        let mut cdl = Cdl::new(rom.len());
+        for (offset, usage) in file[..rom.len()].iter().enumerate() {
+            let mut flags = 0;
+            if usage & (USAGE_EXEC | USAGE_OPCODE) != 0 {
+                flags |= CODE;
+                if usage & USAGE_FLAG_M != 0 {
+                    flags |= MEMORY_MODE_8;
+                }
+                if usage & USAGE_FLAG_X != 0 {
+                    flags |= INDEX_MODE_8;
+                }
+            }
+            if usage & USAGE_READ != 0 {
+                flags |= DATA;
+            }
+            cdl.flags[offset] = flags;
+            cdl.opcodes[offset] = usage & USAGE_OPCODE != 0;
+        }
+        Ok(cdl)
+    }
+}
+
+// the 65816 vectors as bank 0 addresses, for starting a trace
+pub fn vector_entries(vectors: &Vectors) -> Vec<u32> {
+    let mut entries: Vec<u32> = vectors
+        .all()
+        .iter()
+        .filter(|&&(_, address)| address >= 0x8000)
+        .map(|&(_, address)| address as u32)
+        .collect();
+    entries.sort();
+    entries.dedup();
+    entries
+}
+
+// follows every path from the entry points, marking instructions as code and resolvable tables as data
+pub fn trace(data: &[u8], mapper: &Mapper, entries: &[u32]) -> Cdl {
+    let mut cdl = Cdl::new(data.len());
+    let mut visited = HashSet::new();
+    let mut pending: Vec<(u32, Flags)> = entries.iter().map(|entry| (*entry, Flags::default())).collect();
+    for &(entry, _) in &pending {
+        if let Some(offset) = mapper.to_offset(entry) {
+            cdl.flags[offset] |= SUB_ENTRY_POINT;
+        }
+    }
+
+    while let Some((start, start_flags)) = pending.pop() {
+        let mut address = start;
+        let mut flags = start_flags;
+        while visited.insert((address, flags)) {
+            let instruction = match disasm::decode(data, mapper, address, flags) {
+                Some(instruction) => instruction,
+                None => break,
+            };
+            mark_code(&mut cdl, &instruction, flags);
+            mark_data(&mut cdl, data, mapper, &instruction, flags);
+            let next_flags = flags.after(&instruction);
+
+            if let Some(target) = instruction.target() {
+                if let Some(offset) = mapper.to_offset(target) {
+                    cdl.flags[offset] |= if is_call(&instruction) { SUB_ENTRY_POINT } else { JUMP_TARGET };
+                    pending.push((target, next_flags));
+                }
+            }
+            if instruction.mode == Mode::AbsoluteIndirectX {
+                for target in jump_table(&mut cdl, data, mapper, &instruction) {
+                    pending.push((target, next_flags));
+                }
+            }
+            if ends_path(&instruction) {
+                break;
+            }
+            address = instruction.next();
+            flags = next_flags;
+        }
+    }
+    cdl
+}
+
+fn is_call(instruction: &Instruction) -> bool {
+    matches!(instruction.mnemonic, "jsr" | "jsl")
+}
+
+fn ends_path(instruction: &Instruction) -> bool {
+    matches!(instruction.mnemonic, "rts" | "rtl" | "rti" | "stp" | "bra" | "brl" | "jmp" | "jml")
+}
+
+fn mark_code(cdl: &mut Cdl, instruction: &Instruction, flags: Flags) {
+    let mut mark = CODE;
+    if flags.m {
+        mark |= MEMORY_MODE_8;
+    }
+    if flags.x {
+        mark |= INDEX_MODE_8;
+    }
+    cdl.opcodes[instruction.offset] = true;
+    for offset in instruction.offset..instruction.offset + instruction.len() {
+        cdl.flags[offset] |= mark;
+    }
+}
+
+// long addressing names the bank, so those reads can be attributed to rom without knowing the data bank
+fn mark_data(cdl: &mut Cdl, data: &[u8], mapper: &Mapper, instruction: &Instruction, flags: Flags) {
+    if (instruction.mode != Mode::Long && instruction.mode != Mode::LongX) || is_call(instruction) || instruction.mnemonic == "jml" {
+        return;
+    }
+    let width = if flags.m { 1 } else { 2 };
+    for byte in 0..width {
+        if let Some(offset) = mapper.to_offset(instruction.operand + byte) {
+            if offset < data.len() {
+                cdl.flags[offset] |= DATA;
+            }
+        }
+    }
+}
+
+// jmp/jsr (table,x) with the table in the program bank: take words while they point at rom in the same bank
+fn jump_table(cdl: &mut Cdl, data: &[u8], mapper: &Mapper, instruction: &Instruction) -> Vec<u32> {
+    let bank = instruction.address & 0xff0000;
+    let mut targets = Vec::new();
+    for index in 0..JUMP_TABLE_LIMIT as u32 {
+        let entry = bank | ((instruction.operand + index * 2) & 0xffff);
+        let (low, high) = match (mapper.to_offset(entry), mapper.to_offset(bank | ((entry + 1) & 0xffff))) {
+            (Some(low), Some(high)) if low < data.len() && high < data.len() => (low, high),
+            _ => break,
+        };
+        // stop at code, the table usually sits right in front of a routine
+        if cdl.flags[low] & CODE != 0 || cdl.flags[high] & CODE != 0 {
+            break;
+        }
+        let target = bank | data[low] as u32 | (data[high] as u32) << 8;
+        let offset = match mapper.to_offset(target) {
+            Some(offset) => offset,
+            None => break,
+        };
+        cdl.flags[low] |= DATA;
+        cdl.flags[high] |= DATA;
+        cdl.flags[offset] |= JUMP_TARGET;
+        targets.push(target);
+    }
+    targets
+}
+
+// a store of a known value to an i/o register
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegisterWrite {
+    pub address: u32,  // of the store
+    pub register: u32, // bank 0 address stored to
+    pub value: u32,
+    pub wide: bool, // 16 bit, the high byte goes to register + 1
+}
+
+// walks the traced code in rom order, keeping the last immediate loaded into a, x and y, and reports
+// stores of them (and stz) that hit one of the registers
+pub fn register_writes(data: &[u8], mapper: &Mapper, cdl: &Cdl, registers: &[u32]) -> Vec<RegisterWrite> {
+    let mut writes = Vec::new();
+    let (mut a, mut x, mut y) = (None, None, None);
+    for offset in (0..data.len()).filter(|offset| cdl.opcodes[*offset]) {
+        let flags = Flags {
+            m: cdl.flags[offset] & MEMORY_MODE_8 != 0,
+            x: cdl.flags[offset] & INDEX_MODE_8 != 0,
+        };
+        let instruction = match mapper.to_address(offset).and_then(|address| disasm::decode(data, mapper, address, flags)) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        let (value, wide) = match instruction.opcode {
+            0xa9 => {
+                a = Some(instruction.operand);
+                continue;
+            }
+            0xa2 => {
+                x = Some(instruction.operand);
+                continue;
+            }
+            0xa0 => {
+                y = Some(instruction.operand);
+                continue;
+            }
+            0x8d | 0x8f => (a, !flags.m),
+            0x8e => (x, !flags.x),
+            0x8c => (y, !flags.x),
+            0x9c => (Some(0), !flags.m),
+            _ => continue,
+        };
+        // long stores only reach the registers through the system banks
+        if instruction.operand > 0xffff && (instruction.operand >> 16) & 0x40 != 0 {
+            continue;
+        }
+        let register = instruction.operand & 0xffff;
+        let hit = registers.contains(&register) || (wide && registers.contains(&(register + 1)));
+        if let (Some(value), true) = (value, hit) {
+            writes.push(RegisterWrite {
+                address: instruction.address,
+                register,
+                value,
+                wide,
+            });
+        }
+    }
+    writes
+}
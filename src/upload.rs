@@ -0,0 +1,96 @@
+// finds the block lists games send to the spc700 through the ipl handshake on $2140-$2143:
+// repeated [length lo, length hi, aram address lo, aram address hi, data...], ended by a zero length
+// block whose address is where the sound driver starts
+
+const MIN_UPLOAD_SIZE: usize = 0x100;
+const MAX_BLOCKS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub offset: usize, // file offset of the data
+    pub destination: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upload {
+    pub offset: usize,
+    pub blocks: Vec<Block>,
+    pub entry: u16,
+}
+
+impl Upload {
+    pub fn size(&self) -> usize {
+        self.blocks.iter().map(|block| block.data.len()).sum()
+    }
+
+    // the 64 KiB of apu ram as it looks after the upload
+    pub fn aram(&self) -> Vec<u8> {
+        let mut aram = vec![0u8; 0x10000];
+        for block in &self.blocks {
+            let start = block.destination as usize;
+            aram[start..start + block.data.len()].copy_from_slice(&block.data);
+        }
+        aram
+    }
+}
+
+pub fn parse_upload(data: &[u8], offset: usize) -> Option<Upload> {
+    // walk the headers first, copying the data only pays off once the list looks right
+    let mut ranges = Vec::new();
+    let mut position = offset;
+    let entry = loop {
+        let header = data.get(position..position + 4)?;
+        let length = header[0] as usize | (header[1] as usize) << 8;
+        let destination = header[2] as usize | (header[3] as usize) << 8;
+        position += 4;
+        if length == 0 {
+            break destination;
+        }
+        // the registers at $f0-$ff and the ipl rom area can't be sensible upload targets
+        let end = destination + length;
+        if ranges.len() == MAX_BLOCKS || end > 0xffc0 || (destination < 0x100 && end > 0xf0) || position + length > data.len() {
+            return None;
+        }
+        ranges.push((position, destination, length));
+        position += length;
+    };
+    let size: usize = ranges.iter().map(|&(_, _, length)| length).sum();
+    if ranges.is_empty() || size < MIN_UPLOAD_SIZE {
+        return None;
+    }
+    // blocks must not overlap and the entry point has to be uploaded code
+    let mut destinations: Vec<(usize, usize)> = ranges.iter().map(|&(_, destination, length)| (destination, destination + length)).collect();
+    destinations.sort();
+    if destinations.windows(2).any(|pair| pair[0].1 > pair[1].0) || !destinations.iter().any(|&(start, end)| entry >= start && entry < end) {
+        return None;
+    }
+    Some(Upload {
+        offset,
+        blocks: ranges
+            .iter()
+            .map(|&(position, destination, length)| Block {
+                offset: position,
+                destination: destination as u16,
+                data: data[position..position + length].to_vec(),
+            })
+            .collect(),
+        entry: entry as u16,
+    })
+}
+
+pub fn find_uploads(data: &[u8]) -> Vec<Upload> {
+    let mut uploads = Vec::new();
+    let mut offset = 0;
+    while offset + 4 < data.len() {
+        match parse_upload(data, offset) {
+            Some(upload) => {
+                let last = upload.blocks.last().unwrap();
+                offset = last.offset + last.data.len() + 4;
+                uploads.push(upload);
+            }
+            None => offset += 1,
+        }
+    }
+    uploads
+}
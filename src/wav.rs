@@ -0,0 +1,120 @@
+// 16-bit mono pcm wave files, loops go into a smpl chunk the way samplers and trackers read them
+
+pub const DSP_RATE: u32 = 32000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wave {
+    pub rate: u32,
+    pub pcm: Vec<i16>,
+    pub loop_start: Option<usize>, // in samples, the loop runs to the last sample
+}
+
+pub fn write_wav(wave: &Wave) -> Vec<u8> {
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&le16(1)); // pcm
+    fmt.extend_from_slice(&le16(1)); // mono
+    fmt.extend_from_slice(&le32(wave.rate));
+    fmt.extend_from_slice(&le32(wave.rate * 2));
+    fmt.extend_from_slice(&le16(2)); // block align
+    fmt.extend_from_slice(&le16(16));
+
+    let data: Vec<u8> = wave.pcm.iter().flat_map(|sample| le16(*sample as u16)).collect();
+
+    let mut body = b"WAVE".to_vec();
+    push_chunk(&mut body, b"fmt ", &fmt);
+    push_chunk(&mut body, b"data", &data);
+    if let (Some(start), false) = (wave.loop_start, wave.pcm.is_empty()) {
+        let mut smpl = Vec::new();
+        smpl.extend_from_slice(&le32(0)); // manufacturer
+        smpl.extend_from_slice(&le32(0)); // product
+        smpl.extend_from_slice(&le32(1_000_000_000 / wave.rate)); // sample period in ns
+        smpl.extend_from_slice(&le32(60)); // unity note, middle c
+        smpl.extend_from_slice(&le32(0)); // pitch fraction
+        smpl.extend_from_slice(&le32(0)); // smpte format
+        smpl.extend_from_slice(&le32(0)); // smpte offset
+        smpl.extend_from_slice(&le32(1)); // loops
+        smpl.extend_from_slice(&le32(0)); // sampler data
+        smpl.extend_from_slice(&le32(0)); // cue point id
+        smpl.extend_from_slice(&le32(0)); // forward loop
+        smpl.extend_from_slice(&le32(start as u32));
+        smpl.extend_from_slice(&le32(wave.pcm.len() as u32 - 1)); // inclusive end
+        smpl.extend_from_slice(&le32(0)); // fraction
+        smpl.extend_from_slice(&le32(0)); // play forever
+        push_chunk(&mut body, b"smpl", &smpl);
+    }
+
+    let mut file = b"RIFF".to_vec();
+    file.extend_from_slice(&le32(body.len() as u32));
+    file.extend(body);
+    file
+}
+
+// 8 and 16-bit pcm, channels are mixed down to mono
+pub fn read_wav(file: &[u8]) -> Result<Wave, String> {
+    if file.len() < 12 || &file[0..4] != b"RIFF" || &file[8..12] != b"WAVE" {
+        return Err("not a RIFF WAVE file".to_string());
+    }
+    let mut format = None;
+    let mut data = None;
+    let mut loop_start = None;
+    let mut position = 12;
+    while position + 8 <= file.len() {
+        let id = &file[position..position + 4];
+        let size = read_le32(&file[position + 4..]) as usize;
+        let chunk = file
+            .get(position + 8..position + 8 + size)
+            .ok_or(format!("{} chunk runs past the end of the file", String::from_utf8_lossy(id)))?;
+        match id {
+            b"fmt " if size >= 16 => format = Some((read_le16(chunk), read_le16(&chunk[2..]), read_le32(&chunk[4..]), read_le16(&chunk[14..]))),
+            b"data" => data = Some(chunk),
+            b"smpl" if size >= 60 && read_le32(&chunk[28..]) > 0 => loop_start = Some(read_le32(&chunk[44..]) as usize),
+            _ => {}
+        }
+        position += 8 + size + (size & 1); // chunks are padded to even sizes
+    }
+    let (tag, channels, rate, bits) = format.ok_or("missing fmt chunk")?;
+    let data = data.ok_or("missing data chunk")?;
+    if tag != 1 || channels == 0 || (bits != 8 && bits != 16) {
+        return Err(format!("unsupported wave format (tag {}, {} channels, {} bits)", tag, channels, bits));
+    }
+    let frame = channels as usize * bits as usize / 8;
+    let pcm = data
+        .chunks(frame)
+        .filter(|frame_bytes| frame_bytes.len() == frame)
+        .map(|frame_bytes| {
+            let sum: i32 = (0..channels as usize)
+                .map(|channel| match bits {
+                    8 => (frame_bytes[channel] as i32 - 0x80) << 8,
+                    _ => read_le16(&frame_bytes[channel * 2..]) as i16 as i32,
+                })
+                .sum();
+            (sum / channels as i32) as i16
+        })
+        .collect();
+    Ok(Wave { rate, pcm, loop_start })
+}
+
+fn push_chunk(body: &mut Vec<u8>, id: &[u8], chunk: &[u8]) {
+    body.extend_from_slice(id);
+    body.extend_from_slice(&le32(chunk.len() as u32));
+    body.extend_from_slice(chunk);
+    if chunk.len() & 1 != 0 {
+        body.push(0);
+    }
+}
+
+fn le16(value: u16) -> [u8; 2] {
+    [value as u8, (value >> 8) as u8]
+}
+
+fn le32(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+fn read_le16(bytes: &[u8]) -> u16 {
+    bytes[0] as u16 | (bytes[1] as u16) << 8
+}
+
+fn read_le32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}